@@ -0,0 +1,527 @@
+use anyhow::{anyhow, Result};
+use fixed::types::I80F48;
+use mango::state::{MangoAccount, MangoCache, MangoGroup, NodeBank, MAX_PAIRS, QUOTE_INDEX};
+use mango_blender::blender::state::{PoolStatus, Tranche};
+use mango_blender::helpers::get_mango_account_base_net;
+use mango_blender::helpers::quote::{
+    quote_health, quote_pool_value, AlignedData, PoolAccountsData, QuoteInput,
+};
+use mango_blender_client::state::{load_mango_account, load_nav_snapshot, load_pool};
+use mango_blender_client::{accounts, instructions, PoolKeys};
+use serde_json::{json, Value};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use solana_sdk::{system_program, sysvar};
+use spl_associated_token_account::{create_associated_token_account, get_associated_token_address};
+
+use crate::config::{ClusterConfig, GroupConfig, TokenConfig};
+use crate::{Command, Opts};
+
+struct Blender<'a> {
+    rpc: RpcClient,
+    payer: Keypair,
+    group: &'a GroupConfig,
+    keys: PoolKeys,
+}
+
+pub fn run(opts: &Opts) -> Result<Value> {
+    let config = ClusterConfig::load(&opts.config)?;
+    let group = config.group(&opts.cluster, opts.group.as_deref())?;
+    let payer = read_keypair_file(expand_home(&opts.keypair))
+        .map_err(|e| anyhow!("{}: {}", opts.keypair, e))?;
+    let admin = opts.admin.unwrap_or_else(|| payer.pubkey());
+    let keys = PoolKeys::new(
+        &opts.pool_name,
+        &admin,
+        &group.mango_program()?,
+        &group.group_key()?,
+    );
+    let blender = Blender {
+        rpc: RpcClient::new_with_commitment(
+            config.url(&opts.cluster)?.to_string(),
+            CommitmentConfig::confirmed(),
+        ),
+        payer,
+        group,
+        keys,
+    };
+
+    match &opts.command {
+        Command::CreatePool => blender.create_pool(),
+        Command::CreateSeniorTranche { rate_bps } => blender.create_senior_tranche(*rate_bps),
+        Command::Deposit { amount, senior } => {
+            let quote = group.quote_token()?;
+            blender.deposit(quote, quote.to_native(*amount), tranche(*senior))
+        }
+        Command::Withdraw { amount, senior } => {
+            let quote = group.quote_token()?;
+            blender.withdraw(quote, quote.to_native(*amount), tranche(*senior))
+        }
+        Command::ClaimDistribution => blender.claim_distribution(group.quote_token()?),
+        Command::ShowPool => blender.show_pool(),
+        Command::SetDelegate { delegate } => blender.set_delegate(delegate),
+        Command::RevokeDelegate => blender.set_delegate(&Pubkey::default()),
+        Command::Close { slippage_bps } => blender.close(*slippage_bps),
+    }
+}
+
+impl<'a> Blender<'a> {
+    fn create_pool(&self) -> Result<Value> {
+        let keys = &self.keys;
+        let create_pool = instructions::create_pool(
+            accounts::CreatePool {
+                pool: keys.pool,
+                admin: keys.admin,
+                mango_program: keys.mango_program,
+                mango_group: keys.mango_group,
+                mango_account: keys.mango_account,
+                pool_iou_mint: keys.iou_mint,
+                system_program: system_program::ID,
+                token_program: spl_token::ID,
+                rent: sysvar::rent::ID,
+            },
+            keys.pool_name.clone(),
+            keys.pool_bump,
+            keys.iou_mint_bump,
+        );
+        let create_nav_snapshot = instructions::create_nav_snapshot(
+            accounts::CreateNavSnapshot {
+                pool: keys.pool,
+                nav_snapshot: keys.nav_snapshot,
+                payer: self.payer.pubkey(),
+                system_program: system_program::ID,
+            },
+            keys.nav_snapshot_bump,
+        );
+        let create_nav_history = instructions::create_nav_history(accounts::CreateNavHistory {
+            pool: keys.pool,
+            nav_history: keys.nav_history,
+            payer: self.payer.pubkey(),
+            system_program: system_program::ID,
+        });
+        let create_price_feed = instructions::create_price_feed(
+            accounts::CreatePriceFeed {
+                pool: keys.pool,
+                admin: keys.admin,
+                price_feed: keys.price_feed,
+                system_program: system_program::ID,
+            },
+            keys.price_feed_bump,
+        );
+        let signature = self.send(&[
+            create_pool,
+            create_nav_snapshot,
+            create_nav_history,
+            create_price_feed,
+        ])?;
+        Ok(json!({
+            "signature": signature,
+            "pool": keys.pool.to_string(),
+            "iouMint": keys.iou_mint.to_string(),
+            "mangoAccount": keys.mango_account.to_string(),
+            "priceFeed": keys.price_feed.to_string(),
+        }))
+    }
+
+    fn create_senior_tranche(&self, rate_bps: u16) -> Result<Value> {
+        let keys = &self.keys;
+        let signature = self.send(&[instructions::create_senior_tranche(
+            accounts::CreateSeniorTranche {
+                pool: keys.pool,
+                admin: keys.admin,
+                pool_iou_mint: keys.iou_mint,
+                senior_iou_mint: keys.senior_iou_mint,
+                system_program: system_program::ID,
+                token_program: spl_token::ID,
+                rent: sysvar::rent::ID,
+            },
+            keys.senior_iou_mint_bump,
+            rate_bps,
+        )])?;
+        Ok(json!({
+            "signature": signature,
+            "seniorIouMint": keys.senior_iou_mint.to_string(),
+            "rateBps": rate_bps,
+        }))
+    }
+
+    fn deposit(&self, quote: &TokenConfig, quantity: u64, tranche: Tranche) -> Result<Value> {
+        let keys = &self.keys;
+        let owner = self.payer.pubkey();
+        let mut ixs = self.position_setup(tranche)?;
+        let mango_account = self.mango_account()?;
+        let mango_group = self.mango_group()?;
+        let root_bank = quote.root_bank()?;
+        let node_bank = quote.node_bank()?;
+
+        ixs.push(instructions::buy_into_pool(
+            accounts::BuyIntoPool {
+                mango_program: keys.mango_program,
+                pool: keys.pool,
+                mango_group: keys.mango_group,
+                mango_account: keys.mango_account,
+                depositor: owner,
+                mango_cache: mango_group.mango_cache,
+                root_bank,
+                node_bank,
+                vault: self.vault(&node_bank)?,
+                depositor_quote_token_account: get_associated_token_address(&owner, &quote.mint()?),
+                pool_iou_mint: keys.tranche_iou_mint(tranche),
+                depositor_iou_token_account: keys.tranche_iou_token_account(&owner, tranche),
+                depositor_position: keys.position(&owner),
+                nav_snapshot: keys.nav_snapshot,
+                price_feed: keys.price_feed,
+                instructions: sysvar::instructions::ID,
+                token_program: spl_token::ID,
+            },
+            &mango_account,
+            quantity,
+        ));
+        let signature = self.send(&ixs)?;
+        Ok(json!({
+            "signature": signature,
+            "quantity": quantity,
+        }))
+    }
+
+    fn withdraw(&self, quote: &TokenConfig, quantity: u64, tranche: Tranche) -> Result<Value> {
+        let keys = &self.keys;
+        let owner = self.payer.pubkey();
+        let mut ixs = self.position_setup(tranche)?;
+        let quote_mint = quote.mint()?;
+        let quote_token_account = get_associated_token_address(&owner, &quote_mint);
+        if !self.exists(&quote_token_account)? {
+            ixs.push(create_associated_token_account(&owner, &owner, &quote_mint));
+        }
+        let mango_account = self.mango_account()?;
+        let mango_group = self.mango_group()?;
+        let node_bank = quote.node_bank()?;
+
+        ixs.push(instructions::withdraw_from_pool(
+            accounts::WithdrawFromPool {
+                mango_program: keys.mango_program,
+                pool: keys.pool,
+                mango_group: keys.mango_group,
+                mango_group_signer: mango_group.signer_key,
+                mango_account: keys.mango_account,
+                withdrawer: owner,
+                mango_cache: mango_group.mango_cache,
+                root_bank: quote.root_bank()?,
+                node_bank,
+                vault: self.vault(&node_bank)?,
+                withdrawer_token_account: quote_token_account,
+                pool_iou_mint: keys.tranche_iou_mint(tranche),
+                withdrawer_iou_token_account: keys.tranche_iou_token_account(&owner, tranche),
+                withdrawer_position: keys.position(&owner),
+                nav_snapshot: keys.nav_snapshot,
+                price_feed: keys.price_feed,
+                token_program: spl_token::ID,
+            },
+            &mango_account,
+            quantity,
+        ));
+        let signature = self.send(&ixs)?;
+        Ok(json!({
+            "signature": signature,
+            "quantity": quantity,
+        }))
+    }
+
+    fn claim_distribution(&self, quote: &TokenConfig) -> Result<Value> {
+        let keys = &self.keys;
+        let owner = self.payer.pubkey();
+        let mut ixs = vec![];
+        let quote_mint = quote.mint()?;
+        let quote_token_account = get_associated_token_address(&owner, &quote_mint);
+        if !self.exists(&quote_token_account)? {
+            ixs.push(create_associated_token_account(&owner, &owner, &quote_mint));
+        }
+        ixs.push(instructions::claim_distribution(
+            accounts::ClaimDistribution {
+                pool: keys.pool,
+                holder: owner,
+                holder_position: keys.position(&owner),
+                pool_iou_mint: keys.iou_mint,
+                holder_iou_token_account: keys.iou_token_account(&owner),
+                distribution_vault: keys.distribution_vault,
+                holder_token_account: quote_token_account,
+                token_program: spl_token::ID,
+            },
+        ));
+        let signature = self.send(&ixs)?;
+        Ok(json!({
+            "signature": signature,
+        }))
+    }
+
+    fn show_pool(&self) -> Result<Value> {
+        let keys = &self.keys;
+        let pool = load_pool(&self.rpc.get_account_data(&keys.pool)?)?;
+        let iou_supply =
+            spl_token::state::Mint::unpack(&self.rpc.get_account_data(&keys.iou_mint)?)?.supply;
+        let nav_snapshot = load_nav_snapshot(&self.rpc.get_account_data(&keys.nav_snapshot)?)?;
+        let group_data = self.rpc.get_account_data(&keys.mango_group)?;
+        let mango_group_data = AlignedData::new(&group_data);
+        let mango_group: &MangoGroup = mango_group_data.load()?;
+        let account_data = self.rpc.get_account_data(&keys.mango_account)?;
+        let mango_account = load_mango_account(&account_data)?;
+        let cache_data = self.rpc.get_account_data(&mango_group.mango_cache)?;
+        let mango_cache_data = AlignedData::new(&cache_data);
+        let mango_cache: &MangoCache = mango_cache_data.load()?;
+
+        let open_orders_data = self.open_orders_data(&mango_account)?;
+        let mut open_orders: [Option<&[u8]>; MAX_PAIRS] = [None; MAX_PAIRS];
+        for (i, data) in open_orders_data.iter().enumerate() {
+            open_orders[i] = data.as_deref();
+        }
+        let now_ts = unix_now();
+        let input = QuoteInput {
+            pool: &pool,
+            tranche: Tranche::Junior,
+            iou_supply,
+            accounts: PoolAccountsData {
+                mango_group: &group_data,
+                mango_account: &account_data,
+                mango_cache: &cache_data,
+                open_orders,
+            },
+            nav_snapshot: None,
+            slot: self.rpc.get_slot()?,
+            unix_timestamp: now_ts,
+        };
+        let nav = quote_pool_value(&input)?;
+        let (init_health, maint_health) = quote_health(&input)?;
+        let (senior_value, junior_value) = pool.tranche_values(nav, now_ts);
+        let nav_per_iou = if iou_supply > 0 {
+            Some(junior_value / I80F48::from_num(iou_supply))
+        } else {
+            None
+        };
+        let senior_tranche = if pool.has_senior_tranche() {
+            let senior_supply =
+                spl_token::state::Mint::unpack(&self.rpc.get_account_data(&pool.senior_iou_mint)?)?
+                    .supply;
+            json!({
+                "iouMint": pool.senior_iou_mint.to_string(),
+                "iouSupply": senior_supply,
+                "rateBps": pool.senior_rate_bps,
+                "claim": pool.senior_claim_at(now_ts).to_string(),
+                "value": senior_value.to_string(),
+            })
+        } else {
+            Value::Null
+        };
+
+        let mut spot = vec![];
+        let mut perps = vec![];
+        for i in 0..mango_group.num_oracles {
+            let base_net =
+                get_mango_account_base_net(&mango_account, &mango_cache.root_bank_cache[i], i);
+            if base_net != 0 {
+                spot.push(json!({
+                    "tokenIndex": i,
+                    "mint": mango_group.tokens[i].mint.to_string(),
+                    "native": base_net.to_string(),
+                }));
+            }
+            let perp_account = &mango_account.perp_accounts[i];
+            if perp_account.base_position != 0 || perp_account.quote_position != 0 {
+                perps.push(json!({
+                    "marketIndex": i,
+                    "basePositionLots": perp_account.base_position,
+                    "quotePosition": perp_account.quote_position.to_string(),
+                    "bidsQuantity": perp_account.bids_quantity,
+                    "asksQuantity": perp_account.asks_quantity,
+                }));
+            }
+        }
+        let quote_net = get_mango_account_base_net(
+            &mango_account,
+            &mango_cache.root_bank_cache[QUOTE_INDEX],
+            QUOTE_INDEX,
+        );
+
+        Ok(json!({
+            "pool": keys.pool.to_string(),
+            "admin": pool.admin.to_string(),
+            "status": status_name(pool.status),
+            "mangoAccount": keys.mango_account.to_string(),
+            "delegate": mango_account.delegate.to_string(),
+            "iouMint": keys.iou_mint.to_string(),
+            "iouSupply": iou_supply,
+            "nav": nav.to_string(),
+            "navPerIou": nav_per_iou.map(|nav_per_iou| nav_per_iou.to_string()),
+            "seniorTranche": senior_tranche,
+            "distribution": {
+                "enabled": pool.distribution_mode,
+                "intervalSeconds": pool.distribution_interval_seconds,
+                "lastTimestamp": pool.last_distribution_ts,
+                "navPerIou": pool.distribution_nav_per_iou().to_string(),
+                "index": pool.distribution_index().to_string(),
+                "weight": pool.distribution_weight,
+            },
+            "navSnapshot": {
+                "nav": nav_snapshot.nav().to_string(),
+                "iouSupply": nav_snapshot.iou_supply,
+                "slot": nav_snapshot.slot,
+            },
+            "quote": quote_net.to_string(),
+            "spot": spot,
+            "perps": perps,
+            "health": {
+                "init": init_health.to_string(),
+                "maint": maint_health.to_string(),
+                "beingLiquidated": mango_account.being_liquidated,
+                "bankrupt": mango_account.is_bankrupt,
+            },
+        }))
+    }
+
+    fn set_delegate(&self, delegate: &Pubkey) -> Result<Value> {
+        let keys = &self.keys;
+        let signature = self.send(&[instructions::set_delegate(accounts::SetDelegate {
+            pool: keys.pool,
+            admin: keys.admin,
+            mango_program: keys.mango_program,
+            mango_group: keys.mango_group,
+            mango_account: keys.mango_account,
+            delegate: *delegate,
+        })])?;
+        Ok(json!({
+            "signature": signature,
+            "delegate": delegate.to_string(),
+        }))
+    }
+
+    fn close(&self, slippage_bps: u16) -> Result<Value> {
+        let keys = &self.keys;
+        let signature = self.send(&[instructions::start_wind_down(
+            accounts::StartWindDown {
+                pool: keys.pool,
+                admin: keys.admin,
+                mango_program: keys.mango_program,
+                mango_group: keys.mango_group,
+                mango_account: keys.mango_account,
+                system_program: system_program::ID,
+            },
+            slippage_bps,
+        )])?;
+        Ok(json!({
+            "signature": signature,
+            "status": status_name(PoolStatus::WindDown),
+        }))
+    }
+
+    /// Creates the payer's DepositorPosition and `tranche` IOU token account when they don't exist yet
+    fn position_setup(&self, tranche: Tranche) -> Result<Vec<Instruction>> {
+        let keys = &self.keys;
+        let owner = self.payer.pubkey();
+        let mut ixs = vec![];
+        let (position, position_bump) =
+            mango_blender_client::pda::depositor_position_address(&keys.pool, &owner);
+        if !self.exists(&position)? {
+            ixs.push(instructions::create_depositor_position(
+                accounts::CreateDepositorPosition {
+                    pool: keys.pool,
+                    depositor_position: position,
+                    depositor: owner,
+                    system_program: system_program::ID,
+                },
+                position_bump,
+            ));
+        }
+        if !self.exists(&keys.tranche_iou_token_account(&owner, tranche))? {
+            ixs.push(create_associated_token_account(
+                &owner,
+                &owner,
+                &keys.tranche_iou_mint(tranche),
+            ));
+        }
+        Ok(ixs)
+    }
+
+    fn mango_account(&self) -> Result<MangoAccount> {
+        Ok(load_mango_account(
+            &self.rpc.get_account_data(&self.keys.mango_account)?,
+        )?)
+    }
+
+    fn mango_group(&self) -> Result<MangoGroup> {
+        let data = self.rpc.get_account_data(&self.keys.mango_group)?;
+        Ok(*AlignedData::new(&data).load::<MangoGroup>()?)
+    }
+
+    fn vault(&self, node_bank: &Pubkey) -> Result<Pubkey> {
+        let data = self.rpc.get_account_data(node_bank)?;
+        Ok(AlignedData::new(&data).load::<NodeBank>()?.vault)
+    }
+
+    fn open_orders_data(&self, mango_account: &MangoAccount) -> Result<Vec<Option<Vec<u8>>>> {
+        mango_account
+            .spot_open_orders
+            .iter()
+            .enumerate()
+            .map(|(i, key)| {
+                if mango_account.in_margin_basket[i] && *key != Pubkey::default() {
+                    Ok(Some(self.rpc.get_account_data(key)?))
+                } else {
+                    Ok(None)
+                }
+            })
+            .collect()
+    }
+
+    fn exists(&self, key: &Pubkey) -> Result<bool> {
+        Ok(self
+            .rpc
+            .get_account_with_commitment(key, self.rpc.commitment())?
+            .value
+            .is_some())
+    }
+
+    fn send(&self, ixs: &[Instruction]) -> Result<String> {
+        let blockhash = self.rpc.get_latest_blockhash()?;
+        let tx = Transaction::new_signed_with_payer(
+            ixs,
+            Some(&self.payer.pubkey()),
+            &[&self.payer],
+            blockhash,
+        );
+        Ok(self.rpc.send_and_confirm_transaction(&tx)?.to_string())
+    }
+}
+
+fn tranche(senior: bool) -> Tranche {
+    if senior {
+        Tranche::Senior
+    } else {
+        Tranche::Junior
+    }
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+fn status_name(status: PoolStatus) -> &'static str {
+    match status {
+        PoolStatus::Active => "active",
+        PoolStatus::WithdrawOnly => "withdrawOnly",
+        PoolStatus::WindDown => "windDown",
+    }
+}
+
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home, rest),
+        _ => path.to_string(),
+    }
+}
@@ -0,0 +1,106 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Cluster config in the mango-client format, e.g. tests/fixtures/mangoConfig.json
+#[derive(Deserialize)]
+pub struct ClusterConfig {
+    pub cluster_urls: HashMap<String, String>,
+    pub groups: Vec<GroupConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupConfig {
+    pub cluster: String,
+    pub name: String,
+    pub public_key: String,
+    pub quote_symbol: String,
+    pub mango_program_id: String,
+    pub tokens: Vec<TokenConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenConfig {
+    pub symbol: String,
+    pub mint_key: String,
+    pub decimals: u8,
+    pub root_key: String,
+    pub node_keys: Vec<String>,
+}
+
+impl ClusterConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn url(&self, cluster: &str) -> Result<&str> {
+        self.cluster_urls
+            .get(cluster)
+            .map(|url| url.as_str())
+            .ok_or_else(|| anyhow!("no url for cluster {}", cluster))
+    }
+
+    /// The group called `name`, or the cluster's first group
+    pub fn group(&self, cluster: &str, name: Option<&str>) -> Result<&GroupConfig> {
+        self.groups
+            .iter()
+            .filter(|group| group.cluster == cluster)
+            .find(|group| name.map_or(true, |name| group.name == name))
+            .ok_or_else(|| anyhow!("no group {} on cluster {}", name.unwrap_or(""), cluster))
+    }
+}
+
+impl GroupConfig {
+    pub fn group_key(&self) -> Result<Pubkey> {
+        parse_pubkey(&self.public_key)
+    }
+
+    pub fn mango_program(&self) -> Result<Pubkey> {
+        parse_pubkey(&self.mango_program_id)
+    }
+
+    pub fn token(&self, symbol: &str) -> Result<&TokenConfig> {
+        self.tokens
+            .iter()
+            .find(|token| token.symbol == symbol)
+            .ok_or_else(|| anyhow!("no token {} in group {}", symbol, self.name))
+    }
+
+    pub fn quote_token(&self) -> Result<&TokenConfig> {
+        self.token(&self.quote_symbol)
+    }
+}
+
+impl TokenConfig {
+    pub fn mint(&self) -> Result<Pubkey> {
+        parse_pubkey(&self.mint_key)
+    }
+
+    pub fn root_bank(&self) -> Result<Pubkey> {
+        parse_pubkey(&self.root_key)
+    }
+
+    pub fn node_bank(&self) -> Result<Pubkey> {
+        let node_key = self
+            .node_keys
+            .first()
+            .ok_or_else(|| anyhow!("no node bank for {}", self.symbol))?;
+        parse_pubkey(node_key)
+    }
+
+    /// Converts a UI amount to native units
+    pub fn to_native(&self, amount: f64) -> u64 {
+        (amount * 10f64.powi(self.decimals as i32)).round() as u64
+    }
+}
+
+pub fn parse_pubkey(key: &str) -> Result<Pubkey> {
+    Pubkey::from_str(key).map_err(|_| anyhow!("invalid pubkey {}", key))
+}
@@ -0,0 +1,74 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use solana_sdk::pubkey::Pubkey;
+use std::path::PathBuf;
+
+mod commands;
+mod config;
+
+/// Manage mango-blender pools from the command line, output is JSON
+#[derive(Parser)]
+#[clap(name = "blender-cli", version)]
+pub struct Opts {
+    /// Cluster config in the mango-client format
+    #[clap(long, default_value = "tests/fixtures/mangoConfig.json")]
+    pub config: PathBuf,
+    #[clap(long, default_value = "localnet")]
+    pub cluster: String,
+    /// Mango group name, defaults to the cluster's first group
+    #[clap(long)]
+    pub group: Option<String>,
+    /// Keypair that signs and pays for transactions
+    #[clap(long, default_value = "~/.config/solana/id.json")]
+    pub keypair: String,
+    #[clap(long)]
+    pub pool_name: String,
+    /// Pool admin, defaults to the keypair
+    #[clap(long)]
+    pub admin: Option<Pubkey>,
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Creates the pool with its MangoAccount, NAV snapshot, NAV history and price account
+    CreatePool,
+    /// Splits the (still empty) pool into a senior and a junior tranche, seniors accrue `rate_bps` a year
+    CreateSeniorTranche { rate_bps: u16 },
+    /// Buys into the pool with QUOTE, amount in UI units
+    Deposit {
+        amount: f64,
+        /// Buys senior IOUs instead of junior ones
+        #[clap(long)]
+        senior: bool,
+    },
+    /// Withdraws QUOTE from the pool, amount in UI units
+    Withdraw {
+        amount: f64,
+        /// Burns senior IOUs instead of junior ones
+        #[clap(long)]
+        senior: bool,
+    },
+    /// Claims the payer's share of the pool's distributions, in QUOTE
+    ClaimDistribution,
+    /// Prints NAV, IOU supply, NAV per IOU, tranches, positions and health
+    ShowPool,
+    /// Hands trading control of the pool's MangoAccount to `delegate`
+    SetDelegate { delegate: Pubkey },
+    /// Revokes the delegate, the pool stops trading but deposits and withdrawals continue
+    #[clap(alias = "pause")]
+    RevokeDelegate,
+    /// Starts winding the pool down for good
+    Close {
+        #[clap(long, default_value = "100")]
+        slippage_bps: u16,
+    },
+}
+
+fn main() -> Result<()> {
+    let opts = Opts::parse();
+    let output = commands::run(&opts)?;
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
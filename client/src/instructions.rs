@@ -0,0 +1,533 @@
+//! Typed builders for every mango-blender instruction
+//!
+//! Accounts are the program's generated `accounts` structs, `PoolKeys` has the derived addresses.
+//! Instructions that value the pool (or trade through it) take the pool's MangoAccount and append its
+//! open orders accounts as remaining accounts.
+
+use anchor_lang::prelude::{AccountMeta, Pubkey};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{InstructionData, ToAccountMetas};
+use mango::state::MangoAccount;
+use mango_blender::accounts;
+use mango_blender::blender::instructions::{OrderSide, ProxyOrderType};
+use mango_blender::blender::state::RiskLimits;
+use mango_blender::instruction as ix;
+
+use crate::state::open_orders_metas;
+
+fn build(
+    accounts: impl ToAccountMetas,
+    data: impl InstructionData,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    let mut account_metas = accounts.to_account_metas(None);
+    account_metas.extend(remaining_accounts);
+    Instruction {
+        program_id: mango_blender::ID,
+        accounts: account_metas,
+        data: data.data(),
+    }
+}
+
+/// Marks `signer` as signing `instruction`, for accounts the program checks itself rather than through Anchor
+/// (the withdrawer signs withdrawals, but not a keeper paying out a queued one)
+fn signed_by(mut instruction: Instruction, signer: &Pubkey) -> Instruction {
+    for meta in instruction.accounts.iter_mut() {
+        if meta.pubkey == *signer {
+            meta.is_signer = true;
+        }
+    }
+    instruction
+}
+
+pub fn create_pool(
+    accounts: accounts::CreatePool,
+    pool_name: String,
+    pool_bump: u8,
+    iou_mint_bump: u8,
+) -> Instruction {
+    build(
+        accounts,
+        ix::CreatePool {
+            pool_name,
+            pool_bump,
+            iou_mint_bump,
+        },
+        vec![],
+    )
+}
+
+pub fn create_depositor_position(
+    accounts: accounts::CreateDepositorPosition,
+    bump: u8,
+) -> Instruction {
+    build(accounts, ix::CreateDepositorPosition { bump }, vec![])
+}
+
+pub fn buy_into_pool(
+    accounts: accounts::BuyIntoPool,
+    mango_account: &MangoAccount,
+    quantity: u64,
+) -> Instruction {
+    build(
+        accounts,
+        ix::BuyIntoPool { quantity },
+        open_orders_metas(mango_account),
+    )
+}
+
+pub fn withdraw_from_pool(
+    accounts: accounts::WithdrawFromPool,
+    mango_account: &MangoAccount,
+    quantity: u64,
+) -> Instruction {
+    let withdrawer = accounts.withdrawer;
+    let instruction = build(
+        accounts,
+        ix::WithdrawFromPool { quantity },
+        open_orders_metas(mango_account),
+    );
+    signed_by(instruction, &withdrawer)
+}
+
+/// Keeper crank, pays out the withdrawer's request queued in the previous withdrawal window
+pub fn process_queued_withdrawal(
+    accounts: accounts::WithdrawFromPool,
+    mango_account: &MangoAccount,
+) -> Instruction {
+    build(
+        accounts,
+        ix::ProcessQueuedWithdrawal,
+        open_orders_metas(mango_account),
+    )
+}
+
+pub fn withdraw_with_unwind(
+    accounts: accounts::WithdrawWithUnwind,
+    mango_account: &MangoAccount,
+    quantity: u64,
+    max_slippage_bps: u16,
+) -> Instruction {
+    let withdrawer = accounts.withdraw.withdrawer;
+    let instruction = build(
+        accounts,
+        ix::WithdrawWithUnwind {
+            quantity,
+            max_slippage_bps,
+        },
+        open_orders_metas(mango_account),
+    );
+    signed_by(instruction, &withdrawer)
+}
+
+pub fn set_deposit_lock_slots(
+    accounts: accounts::UpdatePool,
+    deposit_lock_slots: u64,
+) -> Instruction {
+    build(
+        accounts,
+        ix::SetDepositLockSlots { deposit_lock_slots },
+        vec![],
+    )
+}
+
+pub fn set_withdrawal_limit(
+    accounts: accounts::UpdatePool,
+    withdrawal_limit_bps: u16,
+) -> Instruction {
+    build(
+        accounts,
+        ix::SetWithdrawalLimit {
+            withdrawal_limit_bps,
+        },
+        vec![],
+    )
+}
+
+pub fn set_queue_unhealthy_withdrawals(
+    accounts: accounts::UpdatePool,
+    queue_unhealthy_withdrawals: bool,
+) -> Instruction {
+    build(
+        accounts,
+        ix::SetQueueUnhealthyWithdrawals {
+            queue_unhealthy_withdrawals,
+        },
+        vec![],
+    )
+}
+
+pub fn view_withdrawal_capacity(accounts: accounts::ViewPool) -> Instruction {
+    build(accounts, ix::ViewWithdrawalCapacity, vec![])
+}
+
+pub fn set_withdraw_health_floor(
+    accounts: accounts::UpdatePool,
+    withdraw_health_floor_bps: u16,
+) -> Instruction {
+    build(
+        accounts,
+        ix::SetWithdrawHealthFloor {
+            withdraw_health_floor_bps,
+        },
+        vec![],
+    )
+}
+
+pub fn set_max_withdraw_leverage(
+    accounts: accounts::UpdatePool,
+    max_withdraw_leverage_bps: u32,
+) -> Instruction {
+    build(
+        accounts,
+        ix::SetMaxWithdrawLeverage {
+            max_withdraw_leverage_bps,
+        },
+        vec![],
+    )
+}
+
+pub fn set_risk_limits(accounts: accounts::UpdatePool, risk_limits: RiskLimits) -> Instruction {
+    build(accounts, ix::SetRiskLimits { risk_limits }, vec![])
+}
+
+pub fn set_delegate(accounts: accounts::SetDelegate) -> Instruction {
+    build(accounts, ix::SetDelegate, vec![])
+}
+
+pub fn enable_proxy_only(accounts: accounts::SetDelegate) -> Instruction {
+    build(accounts, ix::EnableProxyOnly, vec![])
+}
+
+pub fn proxy_place_perp_order(
+    accounts: accounts::ProxyPlacePerpOrder,
+    mango_account: &MangoAccount,
+    side: OrderSide,
+    price: i64,
+    quantity: i64,
+    client_order_id: u64,
+    order_type: ProxyOrderType,
+    reduce_only: bool,
+) -> Instruction {
+    build(
+        accounts,
+        ix::ProxyPlacePerpOrder {
+            side,
+            price,
+            quantity,
+            client_order_id,
+            order_type,
+            reduce_only,
+        },
+        open_orders_metas(mango_account),
+    )
+}
+
+pub fn proxy_cancel_perp_order(
+    accounts: accounts::ProxyCancelPerpOrder,
+    order_id: i128,
+    invalid_id_ok: bool,
+) -> Instruction {
+    build(
+        accounts,
+        ix::ProxyCancelPerpOrder {
+            order_id,
+            invalid_id_ok,
+        },
+        vec![],
+    )
+}
+
+pub fn proxy_place_spot_order(
+    accounts: accounts::ProxyPlaceSpotOrder,
+    mango_account: &MangoAccount,
+    side: OrderSide,
+    limit_price: u64,
+    max_coin_qty: u64,
+    max_native_pc_qty_including_fees: u64,
+    client_order_id: u64,
+    order_type: ProxyOrderType,
+) -> Instruction {
+    build(
+        accounts,
+        ix::ProxyPlaceSpotOrder {
+            side,
+            limit_price,
+            max_coin_qty,
+            max_native_pc_qty_including_fees,
+            client_order_id,
+            order_type,
+        },
+        open_orders_metas(mango_account),
+    )
+}
+
+pub fn proxy_cancel_spot_order(
+    accounts: accounts::ProxyCancelSpotOrder,
+    side: OrderSide,
+    order_id: u128,
+) -> Instruction {
+    build(
+        accounts,
+        ix::ProxyCancelSpotOrder { side, order_id },
+        vec![],
+    )
+}
+
+pub fn set_market_allowlist(
+    accounts: accounts::UpdatePool,
+    spot_markets: u16,
+    perp_markets: u16,
+) -> Instruction {
+    build(
+        accounts,
+        ix::SetMarketAllowlist {
+            spot_markets,
+            perp_markets,
+        },
+        vec![],
+    )
+}
+
+pub fn set_allowlist_timelock(
+    accounts: accounts::UpdatePool,
+    timelock_seconds: i64,
+) -> Instruction {
+    build(
+        accounts,
+        ix::SetAllowlistTimelock { timelock_seconds },
+        vec![],
+    )
+}
+
+pub fn apply_market_allowlist(accounts: accounts::ApplyMarketAllowlist) -> Instruction {
+    build(accounts, ix::ApplyMarketAllowlist, vec![])
+}
+
+pub fn apply_risk_limits(accounts: accounts::ApplyRiskLimits) -> Instruction {
+    build(accounts, ix::ApplyRiskLimits, vec![])
+}
+
+pub fn view_market_allowlist(accounts: accounts::ViewPool) -> Instruction {
+    build(accounts, ix::ViewMarketAllowlist, vec![])
+}
+
+pub fn set_max_drawdown(accounts: accounts::UpdatePool, max_drawdown_bps: u16) -> Instruction {
+    build(accounts, ix::SetMaxDrawdown { max_drawdown_bps }, vec![])
+}
+
+pub fn apply_max_drawdown(accounts: accounts::ApplyMaxDrawdown) -> Instruction {
+    build(accounts, ix::ApplyMaxDrawdown, vec![])
+}
+
+pub fn trip_circuit_breaker(
+    accounts: accounts::TripCircuitBreaker,
+    mango_account: &MangoAccount,
+) -> Instruction {
+    build(
+        accounts,
+        ix::TripCircuitBreaker,
+        open_orders_metas(mango_account),
+    )
+}
+
+pub fn start_wind_down(
+    accounts: accounts::StartWindDown,
+    wind_down_slippage_bps: u16,
+) -> Instruction {
+    build(
+        accounts,
+        ix::StartWindDown {
+            wind_down_slippage_bps,
+        },
+        vec![],
+    )
+}
+
+pub fn unwind_perp(
+    accounts: accounts::UnwindPerp,
+    mango_account: &MangoAccount,
+    limit: u8,
+) -> Instruction {
+    build(
+        accounts,
+        ix::UnwindPerp { limit },
+        open_orders_metas(mango_account),
+    )
+}
+
+pub fn cancel_all_orders(accounts: accounts::CancelAllOrders, limit: u8) -> Instruction {
+    build(accounts, ix::CancelAllOrders { limit }, vec![])
+}
+
+pub fn unwind_spot(accounts: accounts::UnwindSpot, mango_account: &MangoAccount) -> Instruction {
+    build(accounts, ix::UnwindSpot, open_orders_metas(mango_account))
+}
+
+pub fn redeem(
+    accounts: accounts::WithdrawFromPool,
+    mango_account: &MangoAccount,
+    iou_amount: u64,
+) -> Instruction {
+    let withdrawer = accounts.withdrawer;
+    let instruction = build(
+        accounts,
+        ix::Redeem { iou_amount },
+        open_orders_metas(mango_account),
+    );
+    signed_by(instruction, &withdrawer)
+}
+
+pub fn settle_pnl(accounts: accounts::SettlePnl, market_index: u8) -> Instruction {
+    build(accounts, ix::SettlePnl { market_index }, vec![])
+}
+
+pub fn settle_funds(accounts: accounts::SettleFunds) -> Instruction {
+    build(accounts, ix::SettleFunds, vec![])
+}
+
+pub fn settle_fees(accounts: accounts::SettleFees) -> Instruction {
+    build(accounts, ix::SettleFees, vec![])
+}
+
+/// `perp_markets` are (perp market, MNGO perp vault) pairs, every market the MangoAccount has MNGO accrued on
+pub fn redeem_mngo(
+    accounts: accounts::RedeemMngo,
+    perp_markets: &[(Pubkey, Pubkey)],
+) -> Instruction {
+    let remaining_accounts = perp_markets
+        .iter()
+        .flat_map(|(perp_market, mngo_perp_vault)| {
+            vec![
+                AccountMeta::new_readonly(*perp_market, false),
+                AccountMeta::new(*mngo_perp_vault, false),
+            ]
+        })
+        .collect();
+    build(accounts, ix::RedeemMngo, remaining_accounts)
+}
+
+pub fn set_accrued_mngo_valuation(
+    accounts: accounts::UpdatePool,
+    value_accrued_mngo: bool,
+    mngo_token_index: u8,
+) -> Instruction {
+    build(
+        accounts,
+        ix::SetAccruedMngoValuation {
+            value_accrued_mngo,
+            mngo_token_index,
+        },
+        vec![],
+    )
+}
+
+pub fn create_nav_snapshot(accounts: accounts::CreateNavSnapshot, bump: u8) -> Instruction {
+    build(accounts, ix::CreateNavSnapshot { bump }, vec![])
+}
+
+pub fn refresh_nav(accounts: accounts::RefreshNav, mango_account: &MangoAccount) -> Instruction {
+    build(accounts, ix::RefreshNav, open_orders_metas(mango_account))
+}
+
+pub fn set_nav_max_age_slots(
+    accounts: accounts::UpdatePool,
+    nav_max_age_slots: u64,
+) -> Instruction {
+    build(
+        accounts,
+        ix::SetNavMaxAgeSlots { nav_max_age_slots },
+        vec![],
+    )
+}
+
+pub fn create_nav_history(accounts: accounts::CreateNavHistory) -> Instruction {
+    build(accounts, ix::CreateNavHistory, vec![])
+}
+
+pub fn set_nav_history_interval(
+    accounts: accounts::UpdatePool,
+    nav_history_interval_seconds: i64,
+) -> Instruction {
+    build(
+        accounts,
+        ix::SetNavHistoryInterval {
+            nav_history_interval_seconds,
+        },
+        vec![],
+    )
+}
+
+pub fn set_price_confidence(
+    accounts: accounts::UpdatePool,
+    price_confidence_bps: u16,
+) -> Instruction {
+    build(
+        accounts,
+        ix::SetPriceConfidence {
+            price_confidence_bps,
+        },
+        vec![],
+    )
+}
+
+pub fn create_price_feed(accounts: accounts::CreatePriceFeed, bump: u8) -> Instruction {
+    build(accounts, ix::CreatePriceFeed { bump }, vec![])
+}
+
+pub fn create_senior_tranche(
+    accounts: accounts::CreateSeniorTranche,
+    senior_iou_mint_bump: u8,
+    senior_rate_bps: u16,
+) -> Instruction {
+    build(
+        accounts,
+        ix::CreateSeniorTranche {
+            senior_iou_mint_bump,
+            senior_rate_bps,
+        },
+        vec![],
+    )
+}
+
+pub fn set_senior_rate(accounts: accounts::UpdatePool, senior_rate_bps: u16) -> Instruction {
+    build(accounts, ix::SetSeniorRate { senior_rate_bps }, vec![])
+}
+
+pub fn set_distribution_mode(
+    accounts: accounts::UpdatePool,
+    distribution_mode: bool,
+    distribution_interval_seconds: i64,
+) -> Instruction {
+    build(
+        accounts,
+        ix::SetDistributionMode {
+            distribution_mode,
+            distribution_interval_seconds,
+        },
+        vec![],
+    )
+}
+
+pub fn create_distribution_vault(
+    accounts: accounts::CreateDistributionVault,
+    bump: u8,
+) -> Instruction {
+    build(accounts, ix::CreateDistributionVault { bump }, vec![])
+}
+
+pub fn distribute(accounts: accounts::Distribute, mango_account: &MangoAccount) -> Instruction {
+    build(accounts, ix::Distribute, open_orders_metas(mango_account))
+}
+
+pub fn claim_distribution(accounts: accounts::ClaimDistribution) -> Instruction {
+    build(accounts, ix::ClaimDistribution, vec![])
+}
+
+pub fn thaw_ious(accounts: accounts::ThawIous) -> Instruction {
+    build(accounts, ix::ThawIous, vec![])
+}
+
+pub fn transfer_ious(accounts: accounts::TransferIous, amount: u64) -> Instruction {
+    build(accounts, ix::TransferIous { amount }, vec![])
+}
@@ -0,0 +1,10 @@
+//! Off-chain helpers for the mango-blender program: PDA derivation, instruction builders and account deserializers
+
+pub mod instructions;
+pub mod pda;
+pub mod state;
+
+pub use mango_blender::accounts;
+pub use mango_blender::helpers::quote;
+pub use mango_blender::ID;
+pub use pda::PoolKeys;
@@ -0,0 +1,158 @@
+use anchor_lang::prelude::Pubkey;
+use mango_blender::blender::state::Tranche;
+
+/// Mango account number the pool's MangoAccount is created with in `create_pool`
+pub const POOL_MANGO_ACCOUNT_NUM: u64 = 1;
+
+/// `[pool_name, admin]`
+pub fn pool_address(pool_name: &str, admin: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[pool_name.as_ref(), admin.as_ref()], &mango_blender::ID)
+}
+
+/// `[pool_name, admin, "iou"]`
+pub fn iou_mint_address(pool_name: &str, admin: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[pool_name.as_ref(), admin.as_ref(), b"iou"],
+        &mango_blender::ID,
+    )
+}
+
+/// `[pool_name, admin, "senior"]`
+pub fn senior_iou_mint_address(pool_name: &str, admin: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[pool_name.as_ref(), admin.as_ref(), b"senior"],
+        &mango_blender::ID,
+    )
+}
+
+/// The MangoAccount owned by the pool, derived by the mango program from `[mango_group, pool, account_num]`
+pub fn pool_mango_account_address(
+    mango_program: &Pubkey,
+    mango_group: &Pubkey,
+    pool: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            mango_group.as_ref(),
+            pool.as_ref(),
+            &POOL_MANGO_ACCOUNT_NUM.to_le_bytes(),
+        ],
+        mango_program,
+    )
+}
+
+/// `[pool, depositor, "position"]`
+pub fn depositor_position_address(pool: &Pubkey, depositor: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[pool.as_ref(), depositor.as_ref(), b"position"],
+        &mango_blender::ID,
+    )
+}
+
+/// `[pool, "nav"]`
+pub fn nav_snapshot_address(pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[pool.as_ref(), b"nav"], &mango_blender::ID)
+}
+
+/// `[pool, "nav_history"]`
+pub fn nav_history_address(pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[pool.as_ref(), b"nav_history"], &mango_blender::ID)
+}
+
+/// `[pool, "price"]`
+pub fn price_feed_address(pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[pool.as_ref(), b"price"], &mango_blender::ID)
+}
+
+/// `[pool, "distribution"]`
+pub fn distribution_vault_address(pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[pool.as_ref(), b"distribution"], &mango_blender::ID)
+}
+
+/// Every address a pool's instructions derive from its name and admin
+#[derive(Clone, Debug)]
+pub struct PoolKeys {
+    pub pool_name: String,
+    pub admin: Pubkey,
+    pub pool: Pubkey,
+    pub pool_bump: u8,
+    pub iou_mint: Pubkey,
+    pub iou_mint_bump: u8,
+    /// Only initialized once the pool has a senior tranche
+    pub senior_iou_mint: Pubkey,
+    pub senior_iou_mint_bump: u8,
+    pub mango_program: Pubkey,
+    pub mango_group: Pubkey,
+    pub mango_account: Pubkey,
+    pub nav_snapshot: Pubkey,
+    pub nav_snapshot_bump: u8,
+    pub nav_history: Pubkey,
+    pub price_feed: Pubkey,
+    pub price_feed_bump: u8,
+    pub distribution_vault: Pubkey,
+    pub distribution_vault_bump: u8,
+}
+
+impl PoolKeys {
+    pub fn new(
+        pool_name: &str,
+        admin: &Pubkey,
+        mango_program: &Pubkey,
+        mango_group: &Pubkey,
+    ) -> Self {
+        let (pool, pool_bump) = pool_address(pool_name, admin);
+        let (iou_mint, iou_mint_bump) = iou_mint_address(pool_name, admin);
+        let (senior_iou_mint, senior_iou_mint_bump) = senior_iou_mint_address(pool_name, admin);
+        let (mango_account, _) = pool_mango_account_address(mango_program, mango_group, &pool);
+        let (nav_snapshot, nav_snapshot_bump) = nav_snapshot_address(&pool);
+        let (nav_history, _) = nav_history_address(&pool);
+        let (price_feed, price_feed_bump) = price_feed_address(&pool);
+        let (distribution_vault, distribution_vault_bump) = distribution_vault_address(&pool);
+        PoolKeys {
+            pool_name: pool_name.to_string(),
+            admin: *admin,
+            pool,
+            pool_bump,
+            iou_mint,
+            iou_mint_bump,
+            senior_iou_mint,
+            senior_iou_mint_bump,
+            mango_program: *mango_program,
+            mango_group: *mango_group,
+            mango_account,
+            nav_snapshot,
+            nav_snapshot_bump,
+            nav_history,
+            price_feed,
+            price_feed_bump,
+            distribution_vault,
+            distribution_vault_bump,
+        }
+    }
+
+    /// The holder's DepositorPosition
+    pub fn position(&self, owner: &Pubkey) -> Pubkey {
+        depositor_position_address(&self.pool, owner).0
+    }
+
+    /// The holder's IOU associated token account
+    pub fn iou_token_account(&self, owner: &Pubkey) -> Pubkey {
+        spl_associated_token_account::get_associated_token_address(owner, &self.iou_mint)
+    }
+
+    /// IOU mint of `tranche`, the junior tranche's is the pool's IOU mint
+    pub fn tranche_iou_mint(&self, tranche: Tranche) -> Pubkey {
+        match tranche {
+            Tranche::Senior => self.senior_iou_mint,
+            Tranche::Junior => self.iou_mint,
+        }
+    }
+
+    /// The holder's associated token account for `tranche`'s IOUs
+    pub fn tranche_iou_token_account(&self, owner: &Pubkey, tranche: Tranche) -> Pubkey {
+        spl_associated_token_account::get_associated_token_address(
+            owner,
+            &self.tranche_iou_mint(tranche),
+        )
+    }
+}
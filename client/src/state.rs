@@ -0,0 +1,41 @@
+use anchor_lang::prelude::{AccountMeta, Pubkey};
+use anchor_lang::AccountDeserialize;
+use mango::state::{MangoAccount, MAX_PAIRS};
+use mango_blender::blender::state::{DepositorPosition, NavSnapshot, Pool};
+use mango_blender::helpers::quote::AlignedData;
+
+pub use anchor_lang::solana_program::program_error::ProgramError;
+
+/// Deserializes a Pool account, checking its discriminator
+pub fn load_pool(data: &[u8]) -> Result<Pool, ProgramError> {
+    Pool::try_deserialize(&mut &data[..])
+}
+
+/// Deserializes a DepositorPosition account, checking its discriminator
+pub fn load_depositor_position(data: &[u8]) -> Result<DepositorPosition, ProgramError> {
+    DepositorPosition::try_deserialize(&mut &data[..])
+}
+
+/// Deserializes a NavSnapshot account, checking its discriminator
+pub fn load_nav_snapshot(data: &[u8]) -> Result<NavSnapshot, ProgramError> {
+    NavSnapshot::try_deserialize(&mut &data[..])
+}
+
+/// Reads a MangoAccount from raw account data
+pub fn load_mango_account(data: &[u8]) -> Result<MangoAccount, ProgramError> {
+    Ok(*AlignedData::new(data).load::<MangoAccount>()?)
+}
+
+/// The MAX_PAIRS open orders accounts the pool's instructions expect as remaining accounts,
+/// in market order (unused markets are the default pubkey)
+pub fn open_orders_keys(mango_account: &MangoAccount) -> [Pubkey; MAX_PAIRS] {
+    mango_account.spot_open_orders
+}
+
+/// `open_orders_keys` as read-only account metas
+pub fn open_orders_metas(mango_account: &MangoAccount) -> Vec<AccountMeta> {
+    open_orders_keys(mango_account)
+        .iter()
+        .map(|key| AccountMeta::new_readonly(*key, false))
+        .collect()
+}
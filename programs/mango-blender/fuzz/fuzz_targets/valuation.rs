@@ -0,0 +1,287 @@
+//! Fuzzes pool valuation over synthetic Mango accounts
+//!
+//! Builds a MangoAccount, MangoCache, MangoGroup and serum OpenOrders accounts from the fuzz input
+//! and checks `get_spot_val_in_quote` and `calculate_pool_value` against an exact rational reference.
+//! Inputs stay inside the range real accounts live in (see `ValuationInput`), overflow outside of it is expected.
+//!
+//! Run with `cargo fuzz run valuation` from programs/mango-blender
+
+#![no_main]
+
+use std::mem::size_of;
+
+use anchor_lang::prelude::{AccountInfo, Pubkey};
+use arbitrary::Arbitrary;
+use bytemuck::{bytes_of, Zeroable};
+use fixed::types::I80F48;
+use libfuzzer_sys::fuzz_target;
+use mango::state::{
+    MangoAccount, MangoCache, MangoGroup, UserActiveAssets, MAX_PAIRS, QUOTE_INDEX,
+};
+use mango_blender::helpers::{
+    calculate_pool_value, get_mango_account_base_net, get_spot_val_in_quote,
+};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, Zero};
+use serum_dex::state::{AccountFlag, OpenOrders};
+
+/// Valuation may differ from the exact value by this much (native QUOTE): every fixed point
+/// multiplication and division truncates below 2^-48, scaled by prices of at most 2^21
+const TOLERANCE_BITS: u32 = 16;
+
+#[derive(Arbitrary, Debug)]
+struct TokenInput {
+    /// Deposit (or borrow) in native units / index, up to 2^46 with 16 fractional bits
+    amount: u64,
+    borrow: bool,
+    /// Bank indexes between 1 and 17
+    deposit_index: u64,
+    borrow_index: u64,
+}
+
+#[derive(Arbitrary, Debug)]
+struct OpenOrdersInput {
+    /// Native amounts, up to 2^46 each
+    coin_free: u64,
+    coin_locked: u64,
+    pc_free: u64,
+    pc_locked: u64,
+    referrer_rebates: u32,
+}
+
+#[derive(Arbitrary, Debug)]
+struct PerpInput {
+    base_lots: i32,
+    /// Base lot size of 2^(exponent % 17)
+    base_lot_exponent: u8,
+    /// Native QUOTE, up to ±2^60
+    quote_position: i64,
+    /// Funding accrued since the position was last settled, up to ±2^19 per lot
+    unsettled_long_funding: i64,
+    unsettled_short_funding: i64,
+    mngo_accrued: u32,
+}
+
+#[derive(Arbitrary, Debug)]
+struct MarketInput {
+    token: TokenInput,
+    /// Oracle price (native QUOTE per native base) between 2^-20 and 2^21
+    price_mantissa: u16,
+    price_exponent: u8,
+    in_margin_basket: bool,
+    open_orders: Option<OpenOrdersInput>,
+    perp: Option<PerpInput>,
+}
+
+/// At most MAX_PAIRS markets, the bounds keep a full account's value under 2^77 (I80F48 holds 2^79)
+#[derive(Arbitrary, Debug)]
+struct ValuationInput {
+    quote: TokenInput,
+    markets: Vec<MarketInput>,
+    mngo_index: Option<u8>,
+}
+
+fn bounded(raw: u64, bits: u32) -> u64 {
+    raw & ((1 << bits) - 1)
+}
+
+fn amount(raw: u64) -> I80F48 {
+    I80F48::from_bits((bounded(raw, 62) as i128) << 32)
+}
+
+fn index(raw: u64) -> I80F48 {
+    I80F48::from_bits((1 << 48) + bounded(raw, 52) as i128)
+}
+
+fn price(mantissa: u16, exponent: u8) -> I80F48 {
+    let shift = 12 + (exponent % 41) as u32;
+    I80F48::from_bits(((1 << 16) | mantissa as i128) << shift)
+}
+
+fn funding(raw: i64) -> I80F48 {
+    I80F48::from_bits((raw as i128) << 4)
+}
+
+fn exact(value: I80F48) -> BigRational {
+    BigRational::new(BigInt::from(value.to_bits()), BigInt::one() << 48)
+}
+
+fn exact_int(value: impl Into<BigInt>) -> BigRational {
+    BigRational::from_integer(value.into())
+}
+
+/// Serum account layout: "serum" + OpenOrders + "padding"
+fn open_orders_data(input: &OpenOrdersInput) -> Vec<u8> {
+    let mut open_orders = OpenOrders::zeroed();
+    open_orders.account_flags = (AccountFlag::Initialized | AccountFlag::OpenOrders).bits();
+    open_orders.native_coin_free = bounded(input.coin_free, 46);
+    open_orders.native_coin_total = bounded(input.coin_free, 46) + bounded(input.coin_locked, 46);
+    open_orders.native_pc_free = bounded(input.pc_free, 46);
+    open_orders.native_pc_total = bounded(input.pc_free, 46) + bounded(input.pc_locked, 46);
+    open_orders.referrer_rebates_accrued = input.referrer_rebates as u64;
+
+    let mut data = Vec::with_capacity(size_of::<OpenOrders>() + 12);
+    data.extend_from_slice(b"serum");
+    data.extend_from_slice(bytes_of(&open_orders));
+    data.extend_from_slice(b"padding");
+    data
+}
+
+/// Exact value of a token balance, deposits or borrows times the bank index
+fn reference_base_net(input: &TokenInput) -> BigRational {
+    if input.borrow {
+        -(exact(amount(input.amount)) * exact(index(input.borrow_index)))
+    } else {
+        exact(amount(input.amount)) * exact(index(input.deposit_index))
+    }
+}
+
+/// Exact value of a spot market: balance and open orders (free, locked and rebates) at the oracle price
+/// Either worst case scenario of the on chain valuation sums to this, they only split it differently between base and quote
+fn reference_spot_value(market: &MarketInput) -> BigRational {
+    let price = exact(price(market.price_mantissa, market.price_exponent));
+    let mut base = reference_base_net(&market.token);
+    let mut quote = BigRational::zero();
+    if let (true, Some(open_orders)) = (market.in_margin_basket, &market.open_orders) {
+        base +=
+            exact_int(bounded(open_orders.coin_free, 46) + bounded(open_orders.coin_locked, 46));
+        quote += exact_int(bounded(open_orders.pc_free, 46) + bounded(open_orders.pc_locked, 46));
+        quote += exact_int(open_orders.referrer_rebates);
+    }
+    base * price + quote
+}
+
+/// Exact value of a perp position (no open perp orders): base at the oracle price plus quote less unsettled funding
+fn reference_perp_value(perp: &PerpInput, price: &BigRational) -> BigRational {
+    let base_lots = exact_int(perp.base_lots);
+    let base_lot_size = exact_int(1i64 << (perp.base_lot_exponent % 17));
+    let mut quote = exact(I80F48::from_num(perp.quote_position >> 3));
+    if perp.base_lots > 0 {
+        quote -= exact(funding(perp.unsettled_long_funding)) * &base_lots;
+    } else if perp.base_lots < 0 {
+        quote -= exact(funding(perp.unsettled_short_funding)) * &base_lots;
+    }
+    base_lots * base_lot_size * price + quote
+}
+
+fn assert_close(what: &str, value: I80F48, reference: &BigRational) {
+    // fixed point is never NaN or infinite, overflow wraps instead, which the comparison catches
+    let error = (exact(value) - reference).abs();
+    let tolerance = BigRational::new(BigInt::one(), BigInt::one() << TOLERANCE_BITS);
+    assert!(
+        error <= tolerance,
+        "{}: got {}, expected {} (error {})",
+        what,
+        value,
+        reference,
+        error
+    );
+}
+
+fuzz_target!(|input: ValuationInput| {
+    let markets: Vec<&MarketInput> = input.markets.iter().take(MAX_PAIRS).collect();
+
+    let mut mango_account = MangoAccount::zeroed();
+    let mut mango_cache = MangoCache::zeroed();
+    let mut mango_group = MangoGroup::zeroed();
+    mango_group.num_oracles = markets.len();
+
+    let tokens = markets
+        .iter()
+        .map(|market| &market.token)
+        .enumerate()
+        .chain(std::iter::once((QUOTE_INDEX, &input.quote)));
+    for (i, token) in tokens {
+        if token.borrow {
+            mango_account.borrows[i] = amount(token.amount);
+        } else {
+            mango_account.deposits[i] = amount(token.amount);
+        }
+        mango_cache.root_bank_cache[i].deposit_index = index(token.deposit_index);
+        mango_cache.root_bank_cache[i].borrow_index = index(token.borrow_index);
+    }
+
+    for (i, market) in markets.iter().enumerate() {
+        mango_cache.price_cache[i].price = price(market.price_mantissa, market.price_exponent);
+        mango_account.in_margin_basket[i] = market.in_margin_basket;
+        mango_group.spot_markets[i].spot_market = Pubkey::new_unique();
+
+        if let Some(perp) = &market.perp {
+            mango_group.perp_markets[i].perp_market = Pubkey::new_unique();
+            mango_group.perp_markets[i].base_lot_size = 1 << (perp.base_lot_exponent % 17);
+            mango_group.perp_markets[i].quote_lot_size = 1;
+            mango_cache.perp_market_cache[i].long_funding = funding(perp.unsettled_long_funding);
+            mango_cache.perp_market_cache[i].short_funding = funding(perp.unsettled_short_funding);
+            let perp_account = &mut mango_account.perp_accounts[i];
+            perp_account.base_position = perp.base_lots as i64;
+            perp_account.quote_position = I80F48::from_num(perp.quote_position >> 3);
+            perp_account.mngo_accrued = perp.mngo_accrued as u64;
+        }
+    }
+    let active_assets = UserActiveAssets::new(&mango_group, &mango_account, vec![]);
+    let mngo_index = input
+        .mngo_index
+        .map(|index| index as usize % markets.len().max(1));
+
+    // open orders accounts, the MangoAccount may hold some for markets outside its margin basket
+    let keys: Vec<Pubkey> = (0..MAX_PAIRS).map(|_| Pubkey::new_unique()).collect();
+    let owner = Pubkey::new_unique();
+    let mut lamports = vec![0u64; MAX_PAIRS];
+    let mut data: Vec<Option<Vec<u8>>> = (0..MAX_PAIRS)
+        .map(|i| {
+            markets
+                .get(i)
+                .and_then(|market| market.open_orders.as_ref())
+                .map(open_orders_data)
+        })
+        .collect();
+    let open_orders_ais: Vec<Option<AccountInfo>> = keys
+        .iter()
+        .zip(lamports.iter_mut())
+        .zip(data.iter_mut())
+        .map(|((key, lamports), data)| {
+            data.as_mut()
+                .map(|data| AccountInfo::new(key, false, false, lamports, data, &owner, false, 0))
+        })
+        .collect();
+
+    let mut reference = reference_base_net(&input.quote);
+    for (i, market) in markets.iter().enumerate() {
+        if active_assets.spot[i] {
+            let base_net =
+                get_mango_account_base_net(&mango_account, &mango_cache.root_bank_cache[i], i);
+            let spot_value = get_spot_val_in_quote(
+                base_net,
+                mango_cache.price_cache[i].price,
+                open_orders_ais[i].as_ref(),
+                market.in_margin_basket,
+            )
+            .unwrap();
+            let spot_reference = reference_spot_value(market);
+            assert_close(&format!("spot market {}", i), spot_value, &spot_reference);
+            reference += spot_reference;
+        }
+        if let (true, Some(perp)) = (active_assets.perps[i], &market.perp) {
+            reference += reference_perp_value(perp, &exact(mango_cache.price_cache[i].price));
+        }
+    }
+    if let Some(mngo_index) = mngo_index {
+        let mngo_accrued: u64 = markets
+            .iter()
+            .filter_map(|market| market.perp.as_ref())
+            .map(|perp| perp.mngo_accrued as u64)
+            .sum();
+        reference += exact_int(mngo_accrued) * exact(mango_cache.get_price(mngo_index));
+    }
+
+    let pool_value = calculate_pool_value(
+        &mango_account,
+        &mango_cache,
+        &mango_group,
+        open_orders_ais.iter().map(Option::as_ref).collect(),
+        &active_assets,
+        mngo_index,
+    );
+    assert_close("pool value", pool_value, &reference);
+});
@@ -0,0 +1,81 @@
+use anchor_lang::prelude::*;
+
+#[error]
+pub enum BlenderError {
+    #[msg("Withdrawals are locked until enough slots have passed since the last deposit")]
+    WithdrawLocked,
+    #[msg("A Mango cache or price update precedes this instruction in the transaction")]
+    CacheUpdatedInTransaction,
+    #[msg("Pool setting is out of range")]
+    InvalidPoolSetting,
+    #[msg("Pool MangoAccount is being liquidated, bankrupt or below maintenance health")]
+    MangoAccountUnhealthy,
+    #[msg("Withdrawal would leave the pool below its init health floor")]
+    HealthFloorBreached,
+    #[msg("Pool MangoAccount would be more leveraged than the pool allows")]
+    LeverageTooHigh,
+    #[msg("Slippage must be below 100%")]
+    InvalidSlippage,
+    #[msg("Market is not part of the Mango group")]
+    UnknownMarket,
+    #[msg("Open orders account does not belong to the pool's MangoAccount")]
+    InvalidOpenOrders,
+    #[msg("Withdrawal does not fit in the current withdrawal window")]
+    WithdrawalLimitExceeded,
+    #[msg("Pool is proxy only, the MangoAccount can't be delegated")]
+    ProxyOnly,
+    #[msg("Order price and size must be positive")]
+    InvalidOrder,
+    #[msg("Market is not allowed by the pool's risk limits")]
+    MarketNotAllowed,
+    #[msg("Order notional is above the pool's limit")]
+    OrderTooLarge,
+    #[msg("Position notional would be above the pool's limit for this market")]
+    PositionTooLarge,
+    #[msg("No allowlist change is pending or its timelock has not passed")]
+    AllowlistTimelocked,
+    #[msg("Pool is not active, only withdrawals are allowed")]
+    PoolNotActive,
+    #[msg("Pool has no IOUs outstanding")]
+    PoolEmpty,
+    #[msg("Pool is not winding down")]
+    NotWindingDown,
+    #[msg("Pool MangoAccount still has open positions or orders")]
+    PoolNotFlat,
+    #[msg("Price feed account has not been created")]
+    InvalidPriceFeed,
+    #[msg("Pool has IOUs outstanding")]
+    PoolNotEmpty,
+    #[msg("IOU mint is not one of the pool's tranches")]
+    InvalidTrancheMint,
+    #[msg("Tranche has IOUs outstanding but nothing left to price them at")]
+    TrancheWipedOut,
+    #[msg("Pool is not in distribution mode")]
+    DistributionModeOff,
+    #[msg("Distribution interval has not passed yet")]
+    DistributionTooEarly,
+    #[msg("Nothing to claim")]
+    NothingToClaim,
+    #[msg("DEX program is not the one the Mango group trades spot on")]
+    InvalidDexProgram,
+    #[msg("A perp market with MNGO accrued was not passed")]
+    MngoMarketMissing,
+    #[msg("Pool is winding down, IOUs can only be redeemed")]
+    WindingDown,
+    #[msg("Math error")]
+    MathError,
+    #[msg("Senior tranche is worth less than its claim")]
+    SeniorTrancheImpaired,
+    #[msg("Withdrawer must sign")]
+    WithdrawerNotSigner,
+    #[msg("No withdrawal queued for this position")]
+    NothingQueued,
+    #[msg("Queued withdrawal has no IOUs approved for the pool to burn")]
+    QueueNotApproved,
+    #[msg("No risk limit change is pending or its timelock has not passed")]
+    RiskLimitsTimelocked,
+    #[msg("The pool only signs trades while no delegate can trade its MangoAccount directly")]
+    DelegateSet,
+    #[msg("No drawdown limit change is pending or its timelock has not passed")]
+    MaxDrawdownTimelocked,
+}
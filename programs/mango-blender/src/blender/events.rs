@@ -0,0 +1,130 @@
+use anchor_lang::prelude::*;
+
+use crate::blender::state::{PoolStatus, RiskLimits};
+
+// QUOTE amounts and NAVs are in native QUOTE units
+
+#[event]
+pub struct PoolCreated {
+    pub pool: Pubkey,
+    pub pool_name: String,
+    pub admin: Pubkey,
+    pub iou_mint: Pubkey,
+    pub mango_account: Pubkey,
+}
+
+/// `nav_before` is the value of the tranche `iou_mint` belongs to (the whole pool without a senior tranche)
+#[event]
+pub struct Deposit {
+    pub pool: Pubkey,
+    pub depositor: Pubkey,
+    pub quantity: u64,
+    pub deposit_value: u64,
+    pub ious_minted: u64,
+    pub nav_before: u64,
+    pub iou_supply_before: u64,
+    pub iou_mint: Pubkey,
+}
+
+/// `nav` is the value of the tranche `iou_mint` belongs to (the whole pool without a senior tranche)
+#[event]
+pub struct Withdraw {
+    pub pool: Pubkey,
+    pub withdrawer: Pubkey,
+    pub quantity: u64,
+    pub ious_burned: u64,
+    pub nav: u64,
+    pub iou_supply_before: u64,
+    pub iou_mint: Pubkey,
+}
+
+/// QUOTE left over a withdrawal window, `window_id` is the window it was queued in
+/// (paid out by process_queued_withdrawal in the next one)
+#[event]
+pub struct WithdrawalQueued {
+    pub pool: Pubkey,
+    pub withdrawer: Pubkey,
+    pub quantity: u64,
+    pub window_id: u64,
+}
+
+/// NAV growth paid out of the pool into its distribution vault, `nav_before` is the junior tranche's value
+/// and `distribution_index` the QUOTE paid per junior IOU to date (I80F48 bits)
+#[event]
+pub struct Distribution {
+    pub pool: Pubkey,
+    pub quantity: u64,
+    pub nav_before: u64,
+    pub iou_supply: u64,
+    pub distribution_index: i128,
+}
+
+#[event]
+pub struct DistributionClaimed {
+    pub pool: Pubkey,
+    pub holder: Pubkey,
+    pub quantity: u64,
+}
+
+/// Fees paid to `recipient` in IOUs, for fee-charging instructions (none charge a fee yet)
+#[event]
+pub struct FeeAccrued {
+    pub pool: Pubkey,
+    pub recipient: Pubkey,
+    pub ious_minted: u64,
+    pub nav: u64,
+}
+
+/// The pool address is derived from its admin, so the admin is only set on creation (`old_admin` is the default pubkey)
+#[event]
+pub struct AdminChanged {
+    pub pool: Pubkey,
+    pub old_admin: Pubkey,
+    pub new_admin: Pubkey,
+}
+
+/// `delegate` is the default pubkey when delegation is revoked
+#[event]
+pub struct DelegateChanged {
+    pub pool: Pubkey,
+    pub delegate: Pubkey,
+}
+
+#[event]
+pub struct StatusChanged {
+    pub pool: Pubkey,
+    pub old_status: PoolStatus,
+    pub new_status: PoolStatus,
+}
+
+/// `setting` is the `Pool` field that changed (e.g. "withdrawal_limit_bps"), values as integers (bools as 0/1)
+/// A timelocked change is emitted once when it is queued, with the time it can be applied as `effective_ts`,
+/// and again with `effective_ts` 0 when it is applied
+#[event]
+pub struct PoolSettingsChanged {
+    pub pool: Pubkey,
+    pub setting: String,
+    pub old_value: u64,
+    pub new_value: u64,
+    pub effective_ts: i64,
+}
+
+/// Timelocked like `PoolSettingsChanged`
+#[event]
+pub struct RiskLimitsChanged {
+    pub pool: Pubkey,
+    pub old_limits: RiskLimits,
+    pub new_limits: RiskLimits,
+    pub effective_ts: i64,
+}
+
+/// Market bitmaps (bit i = Mango market index i), timelocked like `PoolSettingsChanged`
+#[event]
+pub struct MarketAllowlistChanged {
+    pub pool: Pubkey,
+    pub old_spot_markets: u16,
+    pub old_perp_markets: u16,
+    pub new_spot_markets: u16,
+    pub new_perp_markets: u16,
+    pub effective_ts: i64,
+}
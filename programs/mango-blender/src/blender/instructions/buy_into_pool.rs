@@ -5,26 +5,34 @@ use mango::declare_check_assert_macros;
 use mango::error::{check_assert, MangoErrorCode, SourceFileId};
 use mango::instruction as MangoInstructions;
 use mango::state::{
-    AssetType, MangoAccount, MangoCache, MangoGroup, UserActiveAssets, QUOTE_INDEX,
+    AssetType, MangoAccount, MangoCache, MangoGroup, UserActiveAssets, QUOTE_INDEX, ZERO_I80F48,
 };
 use solana_program::program::invoke_signed_unchecked;
+use solana_program::sysvar::instructions as sysvar_instructions;
 use std::convert::TryFrom;
 
-use crate::blender::state::Pool;
+use crate::blender::errors::BlenderError;
+use crate::blender::events::Deposit;
+use crate::blender::state::{DepositorPosition, NavSnapshot, Pool, PoolStatus, Tranche};
 use crate::helpers::*;
 
 declare_check_assert_macros!(SourceFileId::Processor);
 
 #[derive(Accounts)]
 pub struct BuyIntoPool<'info> {
-    ///CHECK: checked in mango program
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
     pub mango_program: UncheckedAccount<'info>,
-    #[account(mut, seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()], bump)]
+    #[account(
+        mut,
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump,
+        has_one = mango_account)]
     pub pool: Account<'info, Pool>, // Validation??
     ///CHECK: checked in mango program
     pub mango_group: UncheckedAccount<'info>,
     #[account(mut)]
-    ///CHECK: checked in mango program
+    ///CHECK: checked against the pool
     pub mango_account: UncheckedAccount<'info>,
     #[account(signer)]
     pub depositor: AccountInfo<'info>,
@@ -40,10 +48,10 @@ pub struct BuyIntoPool<'info> {
     pub vault: UncheckedAccount<'info>,
     #[account(mut, constraint = depositor_quote_token_account.owner == depositor.key())]
     pub depositor_quote_token_account: Account<'info, TokenAccount>,
+    // the IOU mint of the tranche being bought into
     #[account(
         mut,
-        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref(), b"iou"],
-        bump = pool.iou_mint_bump,
+        constraint = pool.tranche_of(&pool_iou_mint.key()).is_some() @ BlenderError::InvalidTrancheMint,
     )]
     pub pool_iou_mint: Account<'info, Mint>,
 
@@ -52,11 +60,39 @@ pub struct BuyIntoPool<'info> {
         associated_token::mint = pool_iou_mint
     )]
     pub depositor_iou_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [pool.key().as_ref(), depositor.key.as_ref(), b"position"],
+        bump = depositor_position.bump,
+        has_one = pool,
+        constraint = depositor_position.owner == depositor.key()
+    )]
+    pub depositor_position: Box<Account<'info, DepositorPosition>>,
+    #[account(
+        seeds = [pool.key().as_ref(), b"nav"],
+        bump = nav_snapshot.bump,
+        has_one = pool)]
+    pub nav_snapshot: Box<Account<'info, NavSnapshot>>,
+    #[account(mut, seeds = [pool.key().as_ref(), b"price"], bump = pool.price_feed_bump)]
+    ///CHECK: pool's price account, layout checked when written
+    pub price_feed: UncheckedAccount<'info>,
+    #[account(address = sysvar_instructions::ID)]
+    ///CHECK: address is checked, read via instruction introspection
+    pub instructions: UncheckedAccount<'info>,
     pub token_program: Program<'info, Token>,
 }
 
 /// A user "buys a percentage" of the mango pool by depositing quote token into the mango pool
+/// In a pool with a senior tranche the IOU mint passed picks the tranche, IOUs are priced off that tranche's value
 pub fn handler(ctx: Context<BuyIntoPool>, quantity: u64) -> ProgramResult {
+    require!(
+        ctx.accounts.pool.status == PoolStatus::Active,
+        BlenderError::PoolNotActive
+    );
+
+    // refuse to price a deposit off a cache that was refreshed earlier in this transaction
+    check_no_prior_cache_update(&ctx.accounts.instructions.to_account_info())?;
+
     // load mango account, group, cache
     let mango_account_ai = ctx.accounts.mango_account.to_account_info();
     let mango_group_ai = ctx.accounts.mango_group.to_account_info();
@@ -79,7 +115,7 @@ pub fn handler(ctx: Context<BuyIntoPool>, quantity: u64) -> ProgramResult {
     let active_assets = UserActiveAssets::new(
         &mango_group,
         &mango_account,
-        vec![(AssetType::Token, QUOTE_INDEX)],
+        pool_valuation_assets(&ctx.accounts.pool, vec![(AssetType::Token, QUOTE_INDEX)]),
     );
     let clock = Clock::get()?;
     let now_ts = clock.unix_timestamp as u64;
@@ -95,21 +131,93 @@ pub fn handler(ctx: Context<BuyIntoPool>, quantity: u64) -> ProgramResult {
     let open_orders_ais =
         mango_account.checked_unpack_open_orders(&mango_group, &ctx.remaining_accounts)?;
 
-    // get values and mint amount
-    let outstanding_iou_tokens = I80F48::from_num(ctx.accounts.pool_iou_mint.supply);
-    let deposit_value_quote = I80F48::from_num(quantity);
-    let pool_value_quote = calculate_pool_value(
+    // no deposits while the pooled account is in (or close to) liquidation
+    check_mango_account_healthy(
         &mango_account,
         &mango_cache,
         &mango_group,
-        open_orders_ais,
+        &open_orders_ais,
         &active_assets,
+    )?;
+
+    // get values and mint amount
+    let tranche = ctx
+        .accounts
+        .pool
+        .tranche_of(&ctx.accounts.pool_iou_mint.key())
+        .unwrap();
+    let outstanding_iou_tokens = I80F48::from_num(ctx.accounts.pool_iou_mint.supply);
+    let deposit_value_quote = I80F48::from_num(quantity);
+    // a recent enough snapshot saves valuing the MangoAccount again
+    let cached_pool_value = ctx.accounts.nav_snapshot.cached_pool_value(
+        &ctx.accounts.pool,
+        ctx.accounts.pool_iou_mint.supply,
+        clock.slot,
     );
+    let pool_value_quote = match cached_pool_value {
+        Some(pool_value_quote) => pool_value_quote,
+        None => calculate_pool_value(
+            &mango_account,
+            &mango_cache,
+            &mango_group,
+            open_orders_ais,
+            &active_assets,
+            ctx.accounts.pool.mngo_index(),
+        ),
+    };
+    ctx.accounts.pool.accrue_senior_claim(clock.unix_timestamp);
+    let mut tranche_value_quote =
+        ctx.accounts
+            .pool
+            .tranche_value(tranche, pool_value_quote, clock.unix_timestamp);
+    require!(
+        outstanding_iou_tokens == 0 || tranche_value_quote > 0,
+        BlenderError::TrancheWipedOut
+    );
+    // senior IOUs bought below their claim would be owed more than was deposited, at the junior holders' expense
+    require!(
+        tranche == Tranche::Junior
+            || tranche_value_quote >= I80F48::from_bits(ctx.accounts.pool.senior_claim),
+        BlenderError::SeniorTrancheImpaired
+    );
+    if tranche == Tranche::Junior && outstanding_iou_tokens == 0 {
+        ctx.accounts.pool.sweep_junior_residual(tranche_value_quote);
+        tranche_value_quote = ZERO_I80F48;
+    }
+    // keep the circuit breaker's high-water mark and the price account (both per junior IOU) up to date
+    if tranche == Tranche::Junior && outstanding_iou_tokens > 0 {
+        let nav_per_iou = tranche_value_quote / outstanding_iou_tokens;
+        ctx.accounts.pool.record_nav_per_iou(nav_per_iou);
+        publish_nav_price(
+            &ctx.accounts.price_feed,
+            &ctx.accounts.pool,
+            nav_per_iou,
+            mango_group.tokens[QUOTE_INDEX].decimals,
+        )?;
+    }
     let mint_amount = calculate_iou_mint_amount(
         deposit_value_quote,
-        pool_value_quote,
+        tranche_value_quote,
         outstanding_iou_tokens,
     );
+    if tranche == Tranche::Senior {
+        ctx.accounts.pool.add_senior_claim(
+            deposit_value_quote,
+            mint_amount,
+            ctx.accounts.pool_iou_mint.supply,
+        );
+    } else {
+        // new IOUs only share in distributions made from here on
+        let accounts = &mut *ctx.accounts;
+        let iou_balance = accounts.depositor_iou_token_account.amount;
+        let position = &mut accounts.depositor_position;
+        position.settle_distribution(&mut accounts.pool, iou_balance)?;
+        let distribution_balance = position
+            .distribution_balance
+            .checked_add(mint_amount)
+            .ok_or(BlenderError::MathError)?;
+        position.record_distribution_balance(&mut accounts.pool, distribution_balance)?;
+    }
 
     // prepare iou mint
     let seeds = &[
@@ -119,6 +227,14 @@ pub fn handler(ctx: Context<BuyIntoPool>, quantity: u64) -> ProgramResult {
     ];
     let cpi_seed = &[&seeds[..]];
 
+    // the IOU account is still frozen if an earlier deposit's lock hasn't passed
+    thaw_iou_account(
+        &ctx.accounts.pool,
+        &ctx.accounts.pool_iou_mint.to_account_info(),
+        &ctx.accounts.depositor_iou_token_account,
+        &ctx.accounts.token_program.to_account_info(),
+    )?;
+
     let mint_accounts = MintTo {
         to: ctx.accounts.depositor_iou_token_account.to_account_info(),
         mint: ctx.accounts.pool_iou_mint.to_account_info(),
@@ -129,6 +245,31 @@ pub fn handler(ctx: Context<BuyIntoPool>, quantity: u64) -> ProgramResult {
 
     token::mint_to(iou_mint_ctx, mint_amount)?;
 
+    // the lock is on the IOUs, they can't be moved until thaw_ious (or a withdrawal) after the lock
+    freeze_iou_account(
+        &ctx.accounts.pool,
+        &ctx.accounts.pool_iou_mint.to_account_info(),
+        &ctx.accounts.depositor_iou_token_account,
+        &ctx.accounts.token_program.to_account_info(),
+    )?;
+
+    emit!(Deposit {
+        pool: ctx.accounts.pool.key(),
+        depositor: ctx.accounts.depositor.key(),
+        quantity,
+        deposit_value: quote_to_native(deposit_value_quote),
+        ious_minted: mint_amount,
+        nav_before: quote_to_native(tranche_value_quote),
+        iou_supply_before: ctx.accounts.pool_iou_mint.supply,
+        iou_mint: ctx.accounts.pool_iou_mint.key(),
+    });
+
+    // record deposit slot, withdrawals are locked for a number of slots after
+    let deposit_lock_slots = ctx.accounts.pool.deposit_lock_slots;
+    ctx.accounts
+        .depositor_position
+        .record_deposit(clock.slot, deposit_lock_slots);
+
     // handle deposit
     let deposit_instruction = MangoInstructions::deposit(
         ctx.accounts.mango_program.key,
@@ -168,33 +309,3 @@ pub fn handler(ctx: Context<BuyIntoPool>, quantity: u64) -> ProgramResult {
 
     Ok(())
 }
-
-/// Calculate how many iou tokens should be issued for a deposit
-/// We want to ensure that a depositor always purchases a proportion of the pool that is determined by the pool value at time of deposit
-/// e.g. If the pool is worth $90 and I deposit $10, I should own 10% of all minted iou tokens
-///
-/// To achieve this: (deposit value / old pool + deposit value) = (new iou tokens / old + new iou tokens)
-///
-/// Implying: new iou tokens = (deposit *  old iou tokens) / (old pool value)
-fn calculate_iou_mint_amount(
-    deposit_value_quote: I80F48,
-    pool_value_quote: I80F48,
-    outstanding_iou_tokens: I80F48,
-) -> u64 {
-    if outstanding_iou_tokens == 0 {
-        let mint_amount: u64 = deposit_value_quote
-            .checked_floor()
-            .unwrap()
-            .checked_to_num()
-            .unwrap();
-        mint_amount
-    } else {
-        let mint_amount: u64 = ((deposit_value_quote * outstanding_iou_tokens)
-            / (pool_value_quote))
-            .checked_floor()
-            .unwrap()
-            .checked_to_num()
-            .unwrap();
-        mint_amount
-    }
-}
@@ -0,0 +1,130 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+use fixed::types::I80F48;
+use mango::state::{MangoAccount, MangoCache, MangoGroup, UserActiveAssets};
+
+use crate::blender::errors::BlenderError;
+use crate::blender::events::StatusChanged;
+use crate::blender::state::{Pool, PoolStatus, Tranche};
+use crate::helpers::*;
+
+#[derive(Accounts)]
+pub struct TripCircuitBreaker<'info> {
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
+    pub mango_program: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = mango_account)]
+    pub pool: Account<'info, Pool>,
+    ///CHECK: checked in mango program
+    pub mango_group: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked against the pool, loaded with mango's owner check
+    pub mango_account: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_cache: UncheckedAccount<'info>,
+    #[account(
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref(), b"iou"],
+        bump = pool.iou_mint_bump,
+    )]
+    pub pool_iou_mint: Account<'info, Mint>,
+    // the default pubkey, revokes the delegate
+    pub system_program: Program<'info, System>,
+}
+
+/// Permissionless, anyone can trip the breaker once NAV per IOU is `max_drawdown_bps` below its high-water mark
+/// Revokes the delegate and leaves the pool withdraw only, for good
+/// If the drawdown isn't there this only moves the high-water mark up
+/// With a senior tranche the breaker watches the junior (first loss) IOU
+pub fn handler<'a, 'b, 'c, 'info>(
+    ctx: Context<'a, 'b, 'c, 'info, TripCircuitBreaker<'info>>,
+) -> ProgramResult {
+    require!(
+        ctx.accounts.pool.status == PoolStatus::Active,
+        BlenderError::PoolNotActive
+    );
+    require!(
+        ctx.accounts.pool.max_drawdown_bps > 0,
+        BlenderError::InvalidPoolSetting
+    );
+
+    let nav_per_iou = get_nav_per_iou(&ctx)?;
+    let pool = &mut ctx.accounts.pool;
+    if !pool.is_drawdown_breached(nav_per_iou) {
+        pool.record_nav_per_iou(nav_per_iou);
+        msg!(
+            "drawdown not reached, nav per iou: {}, high-water mark: {}",
+            nav_per_iou,
+            pool.nav_per_iou_high_water_mark()
+        );
+        return Ok(());
+    }
+
+    msg!(
+        "circuit breaker tripped, nav per iou: {}, high-water mark: {}",
+        nav_per_iou,
+        pool.nav_per_iou_high_water_mark()
+    );
+    set_mango_delegate(
+        &ctx.accounts.pool,
+        &ctx.accounts.mango_program,
+        &ctx.accounts.mango_group,
+        &ctx.accounts.mango_account,
+        &ctx.accounts.system_program,
+    )?;
+    ctx.accounts.pool.status = PoolStatus::WithdrawOnly;
+    emit!(StatusChanged {
+        pool: ctx.accounts.pool.key(),
+        old_status: PoolStatus::Active,
+        new_status: PoolStatus::WithdrawOnly,
+    });
+    Ok(())
+}
+
+#[inline(never)]
+fn get_nav_per_iou<'a, 'b, 'c, 'info>(
+    ctx: &Context<'a, 'b, 'c, 'info, TripCircuitBreaker<'info>>,
+) -> Result<I80F48, ProgramError> {
+    let accounts = &ctx.accounts;
+    let mango_account = MangoAccount::load_checked(
+        &accounts.mango_account,
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+    )?;
+    let mango_group = MangoGroup::load_checked(&accounts.mango_group, accounts.mango_program.key)?;
+    let mango_cache = MangoCache::load_checked(
+        &accounts.mango_cache,
+        accounts.mango_program.key,
+        &mango_group,
+    )?;
+
+    let active_assets = UserActiveAssets::new(
+        &mango_group,
+        &mango_account,
+        pool_valuation_assets(&accounts.pool, vec![]),
+    );
+    let clock = Clock::get()?;
+    mango_cache.check_valid(&mango_group, &active_assets, clock.unix_timestamp as u64)?;
+
+    let open_orders_ais =
+        mango_account.checked_unpack_open_orders(&mango_group, &ctx.remaining_accounts)?;
+    let pool_value_quote = calculate_pool_value(
+        &mango_account,
+        &mango_cache,
+        &mango_group,
+        open_orders_ais,
+        &active_assets,
+        accounts.pool.mngo_index(),
+    );
+
+    let junior_value_quote =
+        accounts
+            .pool
+            .tranche_value(Tranche::Junior, pool_value_quote, clock.unix_timestamp);
+    let outstanding_iou_tokens = I80F48::from_num(accounts.pool_iou_mint.supply);
+    require!(outstanding_iou_tokens > 0, BlenderError::PoolEmpty);
+    Ok(junior_value_quote / outstanding_iou_tokens)
+}
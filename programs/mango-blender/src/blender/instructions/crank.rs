@@ -0,0 +1,386 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+use mango::instruction as MangoInstructions;
+use mango::state::{MangoAccount, MangoGroup};
+use solana_program::program::{invoke_signed_unchecked, invoke_unchecked};
+
+use crate::blender::errors::BlenderError;
+use crate::blender::state::Pool;
+use crate::helpers::*;
+
+#[derive(Accounts)]
+pub struct SettlePnl<'info> {
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
+    pub mango_program: UncheckedAccount<'info>,
+    #[account(
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = mango_account)]
+    pub pool: Box<Account<'info, Pool>>,
+    ///CHECK: checked in mango program
+    pub mango_group: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked against the pool
+    pub mango_account: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program, any account with opposite unsettled pnl
+    pub counterparty_mango_account: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_cache: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub root_bank: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub node_bank: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SettleFunds<'info> {
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
+    pub mango_program: UncheckedAccount<'info>,
+    #[account(
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = mango_account)]
+    pub pool: Box<Account<'info, Pool>>,
+    ///CHECK: checked in mango program
+    pub mango_group: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_cache: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked against the pool
+    pub mango_account: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_group_signer: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub dex_program: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub spot_market: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub spot_open_orders: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub dex_base: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub dex_quote: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub base_root_bank: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub base_node_bank: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub quote_root_bank: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub quote_node_bank: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub base_vault: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub quote_vault: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub dex_signer: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SettleFees<'info> {
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
+    pub mango_program: UncheckedAccount<'info>,
+    #[account(
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = mango_account)]
+    pub pool: Box<Account<'info, Pool>>,
+    ///CHECK: checked in mango program
+    pub mango_group: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_cache: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub perp_market: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked against the pool
+    pub mango_account: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub root_bank: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub node_bank: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub bank_vault: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub fees_vault: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_group_signer: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Remaining accounts: a (perp market, MNGO perp vault) pair for every perp market with MNGO accrued
+#[derive(Accounts)]
+pub struct RedeemMngo<'info> {
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
+    pub mango_program: UncheckedAccount<'info>,
+    #[account(
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = mango_account)]
+    pub pool: Box<Account<'info, Pool>>,
+    ///CHECK: checked in mango program
+    pub mango_group: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_cache: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked against the pool
+    pub mango_account: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mngo_root_bank: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub mngo_node_bank: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub mngo_bank_vault: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_group_signer: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Permissionless crank, settles the pool's pnl on a perp market against a counterparty with opposite pnl
+/// Mango doesn't need the owner to sign, neither does the pool
+pub fn settle_pnl(ctx: Context<SettlePnl>, market_index: u8) -> ProgramResult {
+    let accounts = &ctx.accounts;
+
+    let settle_instruction = MangoInstructions::settle_pnl(
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+        accounts.mango_account.key,
+        accounts.counterparty_mango_account.key,
+        accounts.mango_cache.key,
+        accounts.root_bank.key,
+        accounts.node_bank.key,
+        market_index as usize,
+    )
+    .unwrap();
+
+    invoke_unchecked(
+        &settle_instruction,
+        &[
+            accounts.mango_program.to_account_info(),
+            accounts.mango_group.to_account_info(),
+            accounts.mango_account.to_account_info(),
+            accounts.counterparty_mango_account.to_account_info(),
+            accounts.mango_cache.to_account_info(),
+            accounts.root_bank.to_account_info(),
+            accounts.node_bank.to_account_info(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Permissionless crank, moves the pool's free Serum balances on a spot market back into its MangoAccount
+/// Mango needs the owner to sign here, so the DEX program passed along is checked against the group's
+pub fn settle_funds(ctx: Context<SettleFunds>) -> ProgramResult {
+    let accounts = &ctx.accounts;
+    {
+        let mango_group =
+            MangoGroup::load_checked(&accounts.mango_group, accounts.mango_program.key)?;
+        check_dex_program(&mango_group, &accounts.dex_program)?;
+    }
+    let seeds = &[
+        &accounts.pool.pool_name.as_ref(),
+        accounts.pool.admin.as_ref(),
+        &[accounts.pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    let settle_instruction = MangoInstructions::settle_funds(
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+        accounts.mango_cache.key,
+        accounts.pool.to_account_info().key,
+        accounts.mango_account.key,
+        accounts.dex_program.key,
+        accounts.spot_market.key,
+        accounts.spot_open_orders.key,
+        accounts.mango_group_signer.key,
+        accounts.dex_base.key,
+        accounts.dex_quote.key,
+        accounts.base_root_bank.key,
+        accounts.base_node_bank.key,
+        accounts.quote_root_bank.key,
+        accounts.quote_node_bank.key,
+        accounts.base_vault.key,
+        accounts.quote_vault.key,
+        accounts.dex_signer.key,
+    )
+    .unwrap();
+
+    invoke_signed_unchecked(
+        &settle_instruction,
+        &[
+            accounts.mango_program.to_account_info(),
+            accounts.mango_group.to_account_info(),
+            accounts.mango_cache.to_account_info(),
+            accounts.pool.to_account_info(),
+            accounts.mango_account.to_account_info(),
+            accounts.dex_program.to_account_info(),
+            accounts.spot_market.to_account_info(),
+            accounts.spot_open_orders.to_account_info(),
+            accounts.mango_group_signer.to_account_info(),
+            accounts.dex_base.to_account_info(),
+            accounts.dex_quote.to_account_info(),
+            accounts.base_root_bank.to_account_info(),
+            accounts.base_node_bank.to_account_info(),
+            accounts.quote_root_bank.to_account_info(),
+            accounts.quote_node_bank.to_account_info(),
+            accounts.base_vault.to_account_info(),
+            accounts.quote_vault.to_account_info(),
+            accounts.dex_signer.to_account_info(),
+            accounts.token_program.to_account_info(),
+        ],
+        cpi_seed,
+    )?;
+    Ok(())
+}
+
+/// Permissionless crank, pays the pool's positive pnl on a perp market out of the market's accrued fees
+/// Mango doesn't need the owner to sign, neither does the pool
+pub fn settle_fees(ctx: Context<SettleFees>) -> ProgramResult {
+    let accounts = &ctx.accounts;
+
+    let settle_instruction = MangoInstructions::settle_fees(
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+        accounts.mango_cache.key,
+        accounts.perp_market.key,
+        accounts.mango_account.key,
+        accounts.root_bank.key,
+        accounts.node_bank.key,
+        accounts.bank_vault.key,
+        accounts.fees_vault.key,
+        accounts.mango_group_signer.key,
+    )
+    .unwrap();
+
+    invoke_unchecked(
+        &settle_instruction,
+        &[
+            accounts.mango_program.to_account_info(),
+            accounts.mango_group.to_account_info(),
+            accounts.mango_cache.to_account_info(),
+            accounts.perp_market.to_account_info(),
+            accounts.mango_account.to_account_info(),
+            accounts.root_bank.to_account_info(),
+            accounts.node_bank.to_account_info(),
+            accounts.bank_vault.to_account_info(),
+            accounts.fees_vault.to_account_info(),
+            accounts.mango_group_signer.to_account_info(),
+            accounts.token_program.to_account_info(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Permissionless crank, redeems the MNGO rewards the pool has accrued on every perp market into its MangoAccount
+/// Fails if a perp market with MNGO accrued is missing from the remaining accounts
+pub fn redeem_mngo<'a, 'b, 'c, 'info>(
+    ctx: Context<'a, 'b, 'c, 'info, RedeemMngo<'info>>,
+) -> ProgramResult {
+    let accounts = &ctx.accounts;
+    let markets = pending_mngo_markets(&ctx)?;
+    if markets.is_empty() {
+        msg!("no mngo accrued");
+        return Ok(());
+    }
+
+    let seeds = &[
+        &accounts.pool.pool_name.as_ref(),
+        accounts.pool.admin.as_ref(),
+        &[accounts.pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    for (perp_market, mngo_perp_vault) in markets {
+        let redeem_instruction = MangoInstructions::redeem_mngo(
+            accounts.mango_program.key,
+            accounts.mango_group.key,
+            accounts.mango_cache.key,
+            accounts.mango_account.key,
+            accounts.pool.to_account_info().key,
+            perp_market.key,
+            mngo_perp_vault.key,
+            accounts.mngo_root_bank.key,
+            accounts.mngo_node_bank.key,
+            accounts.mngo_bank_vault.key,
+            accounts.mango_group_signer.key,
+        )
+        .unwrap();
+
+        invoke_signed_unchecked(
+            &redeem_instruction,
+            &[
+                accounts.mango_program.to_account_info(),
+                accounts.mango_group.to_account_info(),
+                accounts.mango_cache.to_account_info(),
+                accounts.mango_account.to_account_info(),
+                accounts.pool.to_account_info(),
+                perp_market.clone(),
+                mngo_perp_vault.clone(),
+                accounts.mngo_root_bank.to_account_info(),
+                accounts.mngo_node_bank.to_account_info(),
+                accounts.mngo_bank_vault.to_account_info(),
+                accounts.mango_group_signer.to_account_info(),
+                accounts.token_program.to_account_info(),
+            ],
+            cpi_seed,
+        )?;
+    }
+    Ok(())
+}
+
+/// The (perp market, MNGO perp vault) pairs passed that have MNGO accrued, checking none is left out
+#[inline(never)]
+fn pending_mngo_markets<'a, 'b, 'c, 'info>(
+    ctx: &Context<'a, 'b, 'c, 'info, RedeemMngo<'info>>,
+) -> Result<Vec<(&'c AccountInfo<'info>, &'c AccountInfo<'info>)>, ProgramError> {
+    let accounts = &ctx.accounts;
+    let mango_account = MangoAccount::load_checked(
+        &accounts.mango_account,
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+    )?;
+    let mango_group = MangoGroup::load_checked(&accounts.mango_group, accounts.mango_program.key)?;
+
+    let mut unpassed: Vec<bool> = mango_account
+        .perp_accounts
+        .iter()
+        .map(|perp_account| perp_account.mngo_accrued > 0)
+        .collect();
+    let mut markets = vec![];
+    for pair in ctx.remaining_accounts.chunks(2) {
+        require!(pair.len() == 2, BlenderError::UnknownMarket);
+        let market_index = mango_group
+            .find_perp_market_index(pair[0].key)
+            .ok_or(BlenderError::UnknownMarket)?;
+        if unpassed[market_index] {
+            unpassed[market_index] = false;
+            markets.push((&pair[0], &pair[1]));
+        }
+    }
+    require!(
+        !unpassed.iter().any(|&pending| pending),
+        BlenderError::MngoMarketMissing
+    );
+    Ok(markets)
+}
@@ -0,0 +1,33 @@
+use anchor_lang::prelude::*;
+
+use crate::blender::state::{DepositorPosition, Pool, DEPOSITOR_POSITION_SIZE};
+
+#[derive(Accounts)]
+#[instruction(bump: u8)]
+pub struct CreateDepositorPosition<'info> {
+    #[account(seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()], bump = pool.pool_bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        seeds = [pool.key().as_ref(), depositor.key.as_ref(), b"position"],
+        bump = bump,
+        payer = depositor,
+        space = DEPOSITOR_POSITION_SIZE)]
+    pub depositor_position: Account<'info, DepositorPosition>,
+    #[account(mut, signer)]
+    pub depositor: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// A user needs a position account before they can buy into or withdraw from the pool
+pub fn handler(ctx: Context<CreateDepositorPosition>, bump: u8) -> ProgramResult {
+    let position = &mut ctx.accounts.depositor_position;
+    position.pool = ctx.accounts.pool.key();
+    position.owner = *ctx.accounts.depositor.key;
+    position.bump = bump;
+    position.last_deposit_slot = 0;
+    position.deposit_unlock_slot = 0;
+    // only distributions made from here on are owed
+    position.distribution_index = ctx.accounts.pool.distribution_index;
+    Ok(())
+}
@@ -3,7 +3,8 @@ use anchor_spl::token::{Mint, Token};
 use mango::instruction as MangoInstructions;
 use solana_program::program::invoke_signed;
 
-use crate::blender::state::Pool;
+use crate::blender::events::{AdminChanged, DelegateChanged, PoolCreated};
+use crate::blender::state::{Pool, POOL_SIZE};
 
 #[derive(Accounts)]
 #[instruction(pool_name: String, bump: u8, iou_mint_bump: u8)] // FUTURE: should be able to set a withdraw fee (in bps probably)
@@ -13,12 +14,12 @@ pub struct CreatePool<'info> {
         seeds = [pool_name.as_ref(), admin.key.as_ref()], 
         bump, 
         payer = admin, 
-        space = 8 + 32 + 32 + 32 + 1)]
-    // ??? + admin Pkey + iou pkey + string + bump
+        space = POOL_SIZE)]
     pub pool: Account<'info, Pool>,
     #[account(signer)]
     pub admin: AccountInfo<'info>,
-    ///CHECK: checked in mango program
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
     pub mango_program: UncheckedAccount<'info>,
     #[account(mut)]
     ///CHECK: checked in mango program
@@ -53,6 +54,7 @@ pub fn handler(
     ctx.accounts.pool.pool_bump = pool_bump;
     ctx.accounts.pool.iou_mint_bump = iou_mint_bump;
     ctx.accounts.pool.iou_mint = ctx.accounts.pool_iou_mint.key();
+    ctx.accounts.pool.mango_account = ctx.accounts.mango_account.key();
 
     //cpi to create mango account
     let create_instruction = MangoInstructions::create_mango_account(
@@ -107,6 +109,22 @@ pub fn handler(
         ],
         cpi_seed,
     )?;
+    emit!(AdminChanged {
+        pool: ctx.accounts.pool.key(),
+        old_admin: Pubkey::default(),
+        new_admin: ctx.accounts.pool.admin,
+    });
+    emit!(DelegateChanged {
+        pool: ctx.accounts.pool.key(),
+        delegate: *ctx.accounts.admin.key,
+    });
 
+    emit!(PoolCreated {
+        pool: ctx.accounts.pool.key(),
+        pool_name: ctx.accounts.pool.pool_name.clone(),
+        admin: ctx.accounts.pool.admin,
+        iou_mint: ctx.accounts.pool.iou_mint,
+        mango_account: ctx.accounts.pool.mango_account,
+    });
     Ok(())
 }
@@ -0,0 +1,378 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use fixed::types::I80F48;
+use mango::declare_check_assert_macros;
+use mango::error::{check_assert, MangoErrorCode, SourceFileId};
+use mango::instruction as MangoInstructions;
+use mango::state::{
+    AssetType, MangoAccount, MangoCache, MangoGroup, UserActiveAssets, MAX_PAIRS, QUOTE_INDEX,
+};
+use solana_program::program::invoke_signed_unchecked;
+
+use crate::blender::errors::BlenderError;
+use crate::blender::events::{Distribution, DistributionClaimed};
+use crate::blender::state::{DepositorPosition, NavSnapshot, Pool, PoolStatus, Tranche};
+use crate::helpers::*;
+
+declare_check_assert_macros!(SourceFileId::Processor);
+
+#[derive(Accounts)]
+#[instruction(bump: u8)]
+pub struct CreateDistributionVault<'info> {
+    #[account(
+        mut,
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = admin,
+        has_one = mango_account)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, signer)]
+    pub admin: AccountInfo<'info>,
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
+    pub mango_program: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_group: UncheckedAccount<'info>,
+    ///CHECK: checked against the pool, loaded with mango's owner check
+    pub mango_account: UncheckedAccount<'info>,
+    pub quote_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        token::mint = quote_mint,
+        token::authority = pool,
+        seeds = [pool.key().as_ref(), b"distribution"],
+        bump = bump,
+        payer = admin
+    )]
+    pub distribution_vault: Account<'info, TokenAccount>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct Distribute<'info> {
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
+    pub mango_program: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = mango_account)]
+    pub pool: Box<Account<'info, Pool>>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub mango_group: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_group_signer: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked against the pool, loaded with mango's owner check
+    pub mango_account: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_cache: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub root_bank: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub node_bank: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub vault: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [pool.key().as_ref(), b"distribution"],
+        bump = pool.distribution_vault_bump)]
+    pub distribution_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref(), b"iou"],
+        bump = pool.iou_mint_bump,
+    )]
+    pub pool_iou_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [pool.key().as_ref(), b"nav"],
+        bump = nav_snapshot.bump,
+        has_one = pool)]
+    pub nav_snapshot: Box<Account<'info, NavSnapshot>>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimDistribution<'info> {
+    #[account(mut, seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()], bump = pool.pool_bump)]
+    pub pool: Box<Account<'info, Pool>>,
+    #[account(signer)]
+    pub holder: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [pool.key().as_ref(), holder.key.as_ref(), b"position"],
+        bump = holder_position.bump,
+        has_one = pool,
+        constraint = holder_position.owner == holder.key()
+    )]
+    pub holder_position: Box<Account<'info, DepositorPosition>>,
+    #[account(
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref(), b"iou"],
+        bump = pool.iou_mint_bump,
+    )]
+    pub pool_iou_mint: Box<Account<'info, Mint>>,
+    #[account(
+        associated_token::authority = holder,
+        associated_token::mint = pool_iou_mint
+    )]
+    pub holder_iou_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [pool.key().as_ref(), b"distribution"],
+        bump = pool.distribution_vault_bump)]
+    pub distribution_vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut, constraint = holder_token_account.mint == distribution_vault.mint)]
+    pub holder_token_account: Box<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Creates the pool's QUOTE distribution vault, it has to exist before the pool can distribute
+pub fn create_distribution_vault(ctx: Context<CreateDistributionVault>, bump: u8) -> ProgramResult {
+    let accounts = &ctx.accounts;
+    MangoAccount::load_checked(
+        &accounts.mango_account,
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+    )?;
+    let mango_group = MangoGroup::load_checked(&accounts.mango_group, accounts.mango_program.key)?;
+    check!(
+        mango_group.tokens[QUOTE_INDEX].mint == accounts.quote_mint.key(),
+        MangoErrorCode::InvalidToken
+    )?;
+
+    ctx.accounts.pool.distribution_vault_bump = bump;
+    Ok(())
+}
+
+/// Permissionless, pays NAV per junior IOU above what was left after the last distribution out to
+/// junior IOU holders: the QUOTE is withdrawn from the MangoAccount into the distribution vault and
+/// added to the pool's distribution index, holders claim it with claim_distribution
+/// Only junior IOUs recorded on positions (`distribution_weight`) are paid
+/// Capped by the pool's QUOTE deposit, whatever doesn't fit is left for the next distribution
+/// The first distribution after distribution mode is turned on only records the starting NAV per IOU
+pub fn distribute<'a, 'b, 'c, 'info>(
+    ctx: Context<'a, 'b, 'c, 'info, Distribute<'info>>,
+) -> ProgramResult {
+    require!(
+        ctx.accounts.pool.distribution_mode,
+        BlenderError::DistributionModeOff
+    );
+    require!(
+        ctx.accounts.pool.status == PoolStatus::Active,
+        BlenderError::PoolNotActive
+    );
+    let clock = Clock::get()?;
+    require!(
+        clock.unix_timestamp
+            >= ctx.accounts.pool.last_distribution_ts
+                + ctx.accounts.pool.distribution_interval_seconds,
+        BlenderError::DistributionTooEarly
+    );
+    let iou_supply = ctx.accounts.pool_iou_mint.supply;
+    require!(iou_supply > 0, BlenderError::PoolEmpty);
+
+    let (pool_value_quote, quote_available, open_orders_keys) = value_pool(&ctx)?;
+    let pool = &mut ctx.accounts.pool;
+    pool.accrue_senior_claim(clock.unix_timestamp);
+    let junior_value_quote =
+        pool.tranche_value(Tranche::Junior, pool_value_quote, clock.unix_timestamp);
+    let outstanding_iou_tokens = I80F48::from_num(iou_supply);
+    let nav_per_iou = junior_value_quote / outstanding_iou_tokens;
+
+    if pool.distribution_nav_per_iou == 0 {
+        pool.distribution_nav_per_iou = nav_per_iou.to_bits();
+        pool.last_distribution_ts = clock.unix_timestamp;
+        return Ok(());
+    }
+    let distribution_weight = I80F48::from_num(pool.distribution_weight);
+    let growth_quote = (nav_per_iou - pool.distribution_nav_per_iou()) * distribution_weight;
+    let quantity = quote_to_native(growth_quote.min(quote_available));
+    if quantity == 0 {
+        msg!("no NAV growth to distribute");
+        return Ok(());
+    }
+
+    // paid per recorded IOU, but the payout comes out of every IOU's NAV
+    let paid_per_iou = I80F48::from_num(quantity) / distribution_weight;
+    let distributed_per_iou = I80F48::from_num(quantity) / outstanding_iou_tokens;
+    pool.distribution_index = (pool.distribution_index() + paid_per_iou).to_bits();
+    pool.distribution_nav_per_iou = (nav_per_iou - distributed_per_iou).to_bits();
+    pool.last_distribution_ts = clock.unix_timestamp;
+    pool.lower_high_water_mark(distributed_per_iou);
+
+    // the snapshot would price deposits and withdrawals off the value before the payout
+    let snapshot = &mut ctx.accounts.nav_snapshot;
+    snapshot.nav = (pool_value_quote - I80F48::from_num(quantity)).to_bits();
+    snapshot.iou_supply = iou_supply;
+    snapshot.slot = clock.slot;
+    snapshot.timestamp = clock.unix_timestamp;
+
+    emit!(Distribution {
+        pool: ctx.accounts.pool.key(),
+        quantity,
+        nav_before: quote_to_native(junior_value_quote),
+        iou_supply,
+        distribution_index: ctx.accounts.pool.distribution_index,
+    });
+    withdraw_to_vault(&ctx, &open_orders_keys, quantity)
+}
+
+/// Pays the holder what distributions owe their junior IOUs
+pub fn claim_distribution(ctx: Context<ClaimDistribution>) -> ProgramResult {
+    let accounts = &mut *ctx.accounts;
+    let iou_balance = accounts.holder_iou_token_account.amount;
+    let position = &mut accounts.holder_position;
+    position.settle_distribution(&mut accounts.pool, iou_balance)?;
+    let quantity = position.unclaimed_distribution;
+    require!(quantity > 0, BlenderError::NothingToClaim);
+    position.unclaimed_distribution = 0;
+
+    let seeds = &[
+        &ctx.accounts.pool.pool_name.as_ref(),
+        ctx.accounts.pool.admin.as_ref(),
+        &[ctx.accounts.pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+    let transfer_accounts = Transfer {
+        from: ctx.accounts.distribution_vault.to_account_info(),
+        to: ctx.accounts.holder_token_account.to_account_info(),
+        authority: ctx.accounts.pool.to_account_info(),
+    };
+    let token_program_ai = ctx.accounts.token_program.to_account_info();
+    token::transfer(
+        CpiContext::new_with_signer(token_program_ai, transfer_accounts, cpi_seed),
+        quantity,
+    )?;
+
+    emit!(DistributionClaimed {
+        pool: ctx.accounts.pool.key(),
+        holder: ctx.accounts.holder.key(),
+        quantity,
+    });
+    Ok(())
+}
+
+/// Pool value and the QUOTE deposit available to pay out, both in native QUOTE
+#[inline(never)]
+fn value_pool<'a, 'b, 'c, 'info>(
+    ctx: &Context<'a, 'b, 'c, 'info, Distribute<'info>>,
+) -> Result<(I80F48, I80F48, [Pubkey; MAX_PAIRS]), ProgramError> {
+    let accounts = &ctx.accounts;
+    let mango_account = MangoAccount::load_checked(
+        &accounts.mango_account,
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+    )?;
+    let mango_group = MangoGroup::load_checked(&accounts.mango_group, accounts.mango_program.key)?;
+    let mango_cache = MangoCache::load_checked(
+        &accounts.mango_cache,
+        accounts.mango_program.key,
+        &mango_group,
+    )?;
+
+    let active_assets = UserActiveAssets::new(
+        &mango_group,
+        &mango_account,
+        pool_valuation_assets(&accounts.pool, vec![(AssetType::Token, QUOTE_INDEX)]),
+    );
+    let clock = Clock::get()?;
+    mango_cache.check_valid(&mango_group, &active_assets, clock.unix_timestamp as u64)?;
+
+    let open_orders_ais =
+        mango_account.checked_unpack_open_orders(&mango_group, &ctx.remaining_accounts)?;
+    check_mango_account_healthy(
+        &mango_account,
+        &mango_cache,
+        &mango_group,
+        &open_orders_ais,
+        &active_assets,
+    )?;
+    let pool_value_quote = calculate_pool_value(
+        &mango_account,
+        &mango_cache,
+        &mango_group,
+        open_orders_ais,
+        &active_assets,
+        accounts.pool.mngo_index(),
+    );
+    let quote_available = get_mango_account_base_net(
+        &mango_account,
+        &mango_cache.root_bank_cache[QUOTE_INDEX],
+        QUOTE_INDEX,
+    );
+    Ok((
+        pool_value_quote,
+        quote_available,
+        mango_account.spot_open_orders,
+    ))
+}
+
+#[inline(never)]
+fn withdraw_to_vault<'a, 'b, 'c, 'info>(
+    ctx: &Context<'a, 'b, 'c, 'info, Distribute<'info>>,
+    open_orders_keys: &[Pubkey; MAX_PAIRS],
+    quantity: u64,
+) -> ProgramResult {
+    let seeds = &[
+        &ctx.accounts.pool.pool_name.as_ref(),
+        ctx.accounts.pool.admin.as_ref(),
+        &[ctx.accounts.pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    // never borrows, only QUOTE the pool holds is paid out
+    let withdraw_instruction = MangoInstructions::withdraw(
+        ctx.accounts.mango_program.key,
+        ctx.accounts.mango_group.key,
+        ctx.accounts.mango_account.key,
+        ctx.accounts.pool.to_account_info().key,
+        ctx.accounts.mango_cache.key,
+        ctx.accounts.root_bank.key,
+        ctx.accounts.node_bank.key,
+        ctx.accounts.vault.key,
+        ctx.accounts.distribution_vault.to_account_info().key,
+        ctx.accounts.mango_group_signer.key,
+        open_orders_keys,
+        quantity,
+        false,
+    )
+    .unwrap();
+
+    let mut account_infos = vec![
+        ctx.accounts.mango_program.to_account_info(),
+        ctx.accounts.mango_group.to_account_info(),
+        ctx.accounts.mango_account.to_account_info(),
+        ctx.accounts.pool.to_account_info(),
+        ctx.accounts.mango_cache.to_account_info(),
+        ctx.accounts.root_bank.to_account_info(),
+        ctx.accounts.node_bank.to_account_info(),
+        ctx.accounts.vault.to_account_info(),
+        ctx.accounts.distribution_vault.to_account_info(),
+        ctx.accounts.mango_group_signer.to_account_info(),
+        ctx.accounts.token_program.to_account_info(),
+    ];
+    account_infos.extend(ctx.remaining_accounts[..MAX_PAIRS].iter().cloned());
+    invoke_signed_unchecked(&withdraw_instruction, &account_infos, cpi_seed)?;
+
+    // the payout can't take the pool below its withdrawal health floor
+    let health_floor_bps = ctx.accounts.pool.withdraw_health_floor_bps;
+    if health_floor_bps > 0 {
+        check_post_withdraw_health(
+            ctx.accounts.mango_program.key,
+            &ctx.accounts.mango_account,
+            &ctx.accounts.mango_group.to_account_info(),
+            &ctx.accounts.mango_cache.to_account_info(),
+            ctx.remaining_accounts,
+            health_floor_bps,
+            0,
+            ctx.accounts.pool.mngo_index(),
+        )?;
+    }
+    Ok(())
+}
@@ -1,7 +1,37 @@
 pub use buy_into_pool::*;
+pub use circuit_breaker::*;
+pub use crank::*;
+pub use create_depositor_position::*;
 pub use create_pool::*;
+pub use distribution::*;
+pub use price_feed::*;
+pub use proxy_trade::*;
+pub use refresh_nav::*;
+pub use set_delegate::*;
+pub use thaw_ious::*;
+pub use tranche::*;
+pub use transfer_ious::*;
+pub use update_pool::*;
+pub use view_pool::*;
+pub use wind_down::*;
 pub use withdraw_from_pool::*;
+pub use withdraw_with_unwind::*;
 
 pub mod buy_into_pool;
+pub mod circuit_breaker;
+pub mod crank;
+pub mod create_depositor_position;
 pub mod create_pool;
+pub mod distribution;
+pub mod price_feed;
+pub mod proxy_trade;
+pub mod refresh_nav;
+pub mod set_delegate;
+pub mod thaw_ious;
+pub mod tranche;
+pub mod transfer_ious;
+pub mod update_pool;
+pub mod view_pool;
+pub mod wind_down;
 pub mod withdraw_from_pool;
+pub mod withdraw_with_unwind;
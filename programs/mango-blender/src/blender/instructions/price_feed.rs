@@ -0,0 +1,50 @@
+use anchor_lang::prelude::*;
+use solana_program::program::invoke_signed;
+use solana_program::system_instruction;
+
+use crate::blender::state::{init_price_feed, Pool, PRICE_FEED_SIZE};
+
+#[derive(Accounts)]
+#[instruction(bump: u8)]
+pub struct CreatePriceFeed<'info> {
+    #[account(
+        mut,
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = admin)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, signer)]
+    pub admin: AccountInfo<'info>,
+    #[account(mut, seeds = [pool.key().as_ref(), b"price"], bump = bump)]
+    ///CHECK: created here with the Pyth price account layout
+    pub price_feed: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Creates the pool's NAV per IOU price account, it has to exist before deposits and withdrawals
+/// The account uses the Pyth v2 price layout so it can be read by anything that reads Pyth prices
+pub fn create_price_feed(ctx: Context<CreatePriceFeed>, bump: u8) -> ProgramResult {
+    let pool_key = ctx.accounts.pool.key();
+    let price_feed_seeds: &[&[u8]] = &[pool_key.as_ref(), b"price", &[bump]];
+    let lamports = Rent::get()?.minimum_balance(PRICE_FEED_SIZE);
+
+    invoke_signed(
+        &system_instruction::create_account(
+            ctx.accounts.admin.key,
+            ctx.accounts.price_feed.key,
+            lamports,
+            PRICE_FEED_SIZE as u64,
+            ctx.program_id,
+        ),
+        &[
+            ctx.accounts.admin.to_account_info(),
+            ctx.accounts.price_feed.to_account_info(),
+            ctx.accounts.system_program.to_account_info(),
+        ],
+        &[price_feed_seeds],
+    )?;
+
+    init_price_feed(&mut ctx.accounts.price_feed.try_borrow_mut_data()?)?;
+    ctx.accounts.pool.price_feed_bump = bump;
+    Ok(())
+}
@@ -0,0 +1,650 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+use fixed::types::I80F48;
+use mango::instruction as MangoInstructions;
+use mango::matching::{OrderType as PerpOrderType, Side as PerpSide};
+use mango::state::{MangoAccount, MangoCache, MangoGroup, MAX_PAIRS};
+use serum_dex::instruction::{CancelOrderInstructionV2, NewOrderInstructionV3, SelfTradeBehavior};
+use serum_dex::matching::{OrderType as SpotOrderType, Side as SpotSide};
+use serum_dex::state::MarketState;
+use solana_program::program::invoke_signed_unchecked;
+use std::num::NonZeroU64;
+
+use crate::blender::errors::BlenderError;
+use crate::blender::instructions::SpotMarketAccounts;
+use crate::blender::state::{Pool, PoolStatus};
+use crate::helpers::*;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum OrderSide {
+    Bid,
+    Ask,
+}
+
+impl OrderSide {
+    fn perp(self) -> PerpSide {
+        match self {
+            OrderSide::Bid => PerpSide::Bid,
+            OrderSide::Ask => PerpSide::Ask,
+        }
+    }
+
+    fn spot(self) -> SpotSide {
+        match self {
+            OrderSide::Bid => SpotSide::Bid,
+            OrderSide::Ask => SpotSide::Ask,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq)]
+pub enum ProxyOrderType {
+    Limit,
+    ImmediateOrCancel,
+    PostOnly,
+}
+
+impl ProxyOrderType {
+    fn perp(self) -> PerpOrderType {
+        match self {
+            ProxyOrderType::Limit => PerpOrderType::Limit,
+            ProxyOrderType::ImmediateOrCancel => PerpOrderType::ImmediateOrCancel,
+            ProxyOrderType::PostOnly => PerpOrderType::PostOnly,
+        }
+    }
+
+    fn spot(self) -> SpotOrderType {
+        match self {
+            ProxyOrderType::Limit => SpotOrderType::Limit,
+            ProxyOrderType::ImmediateOrCancel => SpotOrderType::ImmediateOrCancel,
+            ProxyOrderType::PostOnly => SpotOrderType::PostOnly,
+        }
+    }
+}
+
+#[derive(Accounts)]
+pub struct ProxyPlacePerpOrder<'info> {
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
+    pub mango_program: UncheckedAccount<'info>,
+    #[account(
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = admin)]
+    pub pool: Box<Account<'info, Pool>>,
+    #[account(signer)]
+    pub admin: AccountInfo<'info>,
+    ///CHECK: checked in mango program
+    pub mango_group: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub mango_account: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_cache: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub perp_market: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub bids: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub asks: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub event_queue: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ProxyCancelPerpOrder<'info> {
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
+    pub mango_program: UncheckedAccount<'info>,
+    #[account(
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = admin)]
+    pub pool: Box<Account<'info, Pool>>,
+    #[account(signer)]
+    pub admin: AccountInfo<'info>,
+    ///CHECK: checked in mango program
+    pub mango_group: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub mango_account: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub perp_market: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub bids: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub asks: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ProxyPlaceSpotOrder<'info> {
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
+    pub mango_program: UncheckedAccount<'info>,
+    #[account(
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = admin)]
+    pub pool: Box<Account<'info, Pool>>,
+    #[account(signer)]
+    pub admin: AccountInfo<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub mango_group: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_group_signer: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub mango_account: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_cache: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub quote_root_bank: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub quote_node_bank: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub quote_vault: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+    pub spot: SpotMarketAccounts<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ProxyCancelSpotOrder<'info> {
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
+    pub mango_program: UncheckedAccount<'info>,
+    #[account(
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = admin)]
+    pub pool: Box<Account<'info, Pool>>,
+    #[account(signer)]
+    pub admin: AccountInfo<'info>,
+    ///CHECK: checked in mango program
+    pub mango_group: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_group_signer: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_account: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub dex_program: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub spot_market: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub bids: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub asks: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub spot_open_orders: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub dex_event_queue: UncheckedAccount<'info>,
+}
+
+/// The admin trades the pooled MangoAccount through the pool, which signs the Mango CPI only if the order
+/// respects the pool's risk limits (allowed market, order size, position size and, after the fill, account leverage)
+pub fn place_perp_order<'a, 'b, 'c, 'info>(
+    ctx: Context<'a, 'b, 'c, 'info, ProxyPlacePerpOrder<'info>>,
+    side: OrderSide,
+    price: i64,
+    quantity: i64,
+    client_order_id: u64,
+    order_type: ProxyOrderType,
+    reduce_only: bool,
+) -> ProgramResult {
+    let open_orders_keys = check_perp_order(&ctx, side, price, quantity, reduce_only)?;
+
+    let accounts = &ctx.accounts;
+    let seeds = &[
+        &accounts.pool.pool_name.as_ref(),
+        accounts.pool.admin.as_ref(),
+        &[accounts.pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    let place_instruction = MangoInstructions::place_perp_order(
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+        accounts.mango_account.key,
+        accounts.pool.to_account_info().key,
+        accounts.mango_cache.key,
+        accounts.perp_market.key,
+        accounts.bids.key,
+        accounts.asks.key,
+        accounts.event_queue.key,
+        None,
+        &open_orders_keys,
+        side.perp(),
+        price,
+        quantity,
+        client_order_id,
+        order_type.perp(),
+        reduce_only,
+    )
+    .unwrap();
+
+    let mut account_infos = vec![
+        accounts.mango_program.to_account_info(),
+        accounts.mango_group.to_account_info(),
+        accounts.mango_account.to_account_info(),
+        accounts.pool.to_account_info(),
+        accounts.mango_cache.to_account_info(),
+        accounts.perp_market.to_account_info(),
+        accounts.bids.to_account_info(),
+        accounts.asks.to_account_info(),
+        accounts.event_queue.to_account_info(),
+    ];
+    account_infos.extend(ctx.remaining_accounts.iter().cloned());
+    invoke_signed_unchecked(&place_instruction, &account_infos, cpi_seed)?;
+
+    let max_leverage_bps = accounts.pool.risk_limits.max_leverage_bps;
+    if max_leverage_bps > 0 && !reduce_only {
+        check_post_trade_leverage(
+            accounts.mango_program.key,
+            &accounts.mango_account,
+            &accounts.mango_group,
+            &accounts.mango_cache,
+            ctx.remaining_accounts,
+            max_leverage_bps,
+            accounts.pool.mngo_index(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Checks a perp order against the pool's risk limits, returns the open orders keys for the CPI
+#[inline(never)]
+fn check_perp_order<'a, 'b, 'c, 'info>(
+    ctx: &Context<'a, 'b, 'c, 'info, ProxyPlacePerpOrder<'info>>,
+    side: OrderSide,
+    price: i64,
+    quantity: i64,
+    reduce_only: bool,
+) -> Result<[Pubkey; MAX_PAIRS], ProgramError> {
+    require!(price > 0 && quantity > 0, BlenderError::InvalidOrder);
+
+    let accounts = &ctx.accounts;
+    require!(
+        accounts.pool.status == PoolStatus::Active,
+        BlenderError::PoolNotActive
+    );
+    let mango_account = MangoAccount::load_checked(
+        &accounts.mango_account,
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+    )?;
+    // the limits mean nothing while a delegate can trade around them
+    require!(
+        mango_account.delegate == Pubkey::default(),
+        BlenderError::DelegateSet
+    );
+    let mango_group = MangoGroup::load_checked(&accounts.mango_group, accounts.mango_program.key)?;
+    let mango_cache = MangoCache::load_checked(
+        &accounts.mango_cache,
+        accounts.mango_program.key,
+        &mango_group,
+    )?;
+
+    let market_index = mango_group
+        .find_perp_market_index(accounts.perp_market.key)
+        .ok_or(BlenderError::UnknownMarket)?;
+    require!(
+        accounts.pool.market_allowlist.is_perp_allowed(market_index),
+        BlenderError::MarketNotAllowed
+    );
+    let limits = &accounts.pool.risk_limits;
+
+    // price is in quote lots per base lot, quantity in base lots
+    let perp_market_info = &mango_group.perp_markets[market_index];
+    let order_notional = I80F48::from_num(price)
+        * I80F48::from_num(quantity)
+        * I80F48::from_num(perp_market_info.quote_lot_size);
+    require!(
+        limits.max_order_notional == 0
+            || order_notional <= I80F48::from_num(limits.max_order_notional),
+        BlenderError::OrderTooLarge
+    );
+
+    // position if this and every resting order on the same side were filled
+    let max_position_notional = limits.max_position_notional[market_index];
+    if !reduce_only && max_position_notional > 0 {
+        let perp_account = &mango_account.perp_accounts[market_index];
+        let worst_position = match side {
+            OrderSide::Bid => perp_account.base_position + perp_account.bids_quantity + quantity,
+            OrderSide::Ask => perp_account.base_position - perp_account.asks_quantity - quantity,
+        };
+        let position_notional = get_perp_position_notional(
+            worst_position,
+            perp_market_info.base_lot_size,
+            mango_cache.get_price(market_index),
+        );
+        require!(
+            position_notional <= I80F48::from_num(max_position_notional),
+            BlenderError::PositionTooLarge
+        );
+    }
+
+    Ok(mango_account.spot_open_orders)
+}
+
+/// Cancels a perp order of the pooled MangoAccount, signed by the pool
+pub fn cancel_perp_order(
+    ctx: Context<ProxyCancelPerpOrder>,
+    order_id: i128,
+    invalid_id_ok: bool,
+) -> ProgramResult {
+    let accounts = &ctx.accounts;
+    let seeds = &[
+        &accounts.pool.pool_name.as_ref(),
+        accounts.pool.admin.as_ref(),
+        &[accounts.pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    let cancel_instruction = MangoInstructions::cancel_perp_order(
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+        accounts.mango_account.key,
+        accounts.pool.to_account_info().key,
+        accounts.perp_market.key,
+        accounts.bids.key,
+        accounts.asks.key,
+        order_id,
+        invalid_id_ok,
+    )
+    .unwrap();
+
+    invoke_signed_unchecked(
+        &cancel_instruction,
+        &[
+            accounts.mango_program.to_account_info(),
+            accounts.mango_group.to_account_info(),
+            accounts.mango_account.to_account_info(),
+            accounts.pool.to_account_info(),
+            accounts.perp_market.to_account_info(),
+            accounts.bids.to_account_info(),
+            accounts.asks.to_account_info(),
+        ],
+        cpi_seed,
+    )?;
+    Ok(())
+}
+
+/// Spot version of place_perp_order, placed on Serum through Mango's place_spot_order2
+pub fn place_spot_order<'a, 'b, 'c, 'info>(
+    ctx: Context<'a, 'b, 'c, 'info, ProxyPlaceSpotOrder<'info>>,
+    side: OrderSide,
+    limit_price: u64,
+    max_coin_qty: u64,
+    max_native_pc_qty_including_fees: u64,
+    client_order_id: u64,
+    order_type: ProxyOrderType,
+) -> ProgramResult {
+    let (market_index, open_orders_keys) = check_spot_order(
+        &ctx,
+        side,
+        limit_price,
+        max_coin_qty,
+        max_native_pc_qty_including_fees,
+    )?;
+
+    let accounts = &ctx.accounts;
+    let spot = &accounts.spot;
+    let seeds = &[
+        &accounts.pool.pool_name.as_ref(),
+        accounts.pool.admin.as_ref(),
+        &[accounts.pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    let order = NewOrderInstructionV3 {
+        side: side.spot(),
+        limit_price: NonZeroU64::new(limit_price).unwrap(),
+        max_coin_qty: NonZeroU64::new(max_coin_qty).unwrap(),
+        max_native_pc_qty_including_fees: NonZeroU64::new(max_native_pc_qty_including_fees)
+            .unwrap(),
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        order_type: order_type.spot(),
+        client_order_id,
+        limit: 32,
+    };
+
+    let place_instruction = MangoInstructions::place_spot_order2(
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+        accounts.mango_account.key,
+        accounts.pool.to_account_info().key,
+        accounts.mango_cache.key,
+        spot.dex_program.key,
+        spot.spot_market.key,
+        spot.bids.key,
+        spot.asks.key,
+        spot.dex_request_queue.key,
+        spot.dex_event_queue.key,
+        spot.dex_base.key,
+        spot.dex_quote.key,
+        spot.base_root_bank.key,
+        spot.base_node_bank.key,
+        spot.base_vault.key,
+        accounts.quote_root_bank.key,
+        accounts.quote_node_bank.key,
+        accounts.quote_vault.key,
+        accounts.mango_group_signer.key,
+        spot.dex_signer.key,
+        spot.msrm_or_srm_vault.key,
+        &open_orders_keys,
+        market_index,
+        order,
+    )
+    .unwrap();
+
+    let mut account_infos = vec![
+        accounts.mango_program.to_account_info(),
+        accounts.mango_group.to_account_info(),
+        accounts.mango_account.to_account_info(),
+        accounts.pool.to_account_info(),
+        accounts.mango_cache.to_account_info(),
+        spot.dex_program.to_account_info(),
+        spot.spot_market.to_account_info(),
+        spot.bids.to_account_info(),
+        spot.asks.to_account_info(),
+        spot.dex_request_queue.to_account_info(),
+        spot.dex_event_queue.to_account_info(),
+        spot.dex_base.to_account_info(),
+        spot.dex_quote.to_account_info(),
+        spot.base_root_bank.to_account_info(),
+        spot.base_node_bank.to_account_info(),
+        spot.base_vault.to_account_info(),
+        accounts.quote_root_bank.to_account_info(),
+        accounts.quote_node_bank.to_account_info(),
+        accounts.quote_vault.to_account_info(),
+        accounts.token_program.to_account_info(),
+        accounts.mango_group_signer.to_account_info(),
+        spot.dex_signer.to_account_info(),
+        spot.msrm_or_srm_vault.to_account_info(),
+        spot.spot_open_orders.to_account_info(),
+    ];
+    account_infos.extend(ctx.remaining_accounts.iter().cloned());
+    invoke_signed_unchecked(&place_instruction, &account_infos, cpi_seed)?;
+
+    let max_leverage_bps = accounts.pool.risk_limits.max_leverage_bps;
+    if max_leverage_bps > 0 {
+        check_post_trade_leverage(
+            accounts.mango_program.key,
+            &accounts.mango_account,
+            &accounts.mango_group,
+            &accounts.mango_cache,
+            ctx.remaining_accounts,
+            max_leverage_bps,
+            accounts.pool.mngo_index(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Checks a spot order against the pool's risk limits, returns the market index and open orders keys for the CPI
+#[inline(never)]
+fn check_spot_order<'a, 'b, 'c, 'info>(
+    ctx: &Context<'a, 'b, 'c, 'info, ProxyPlaceSpotOrder<'info>>,
+    side: OrderSide,
+    limit_price: u64,
+    max_coin_qty: u64,
+    max_native_pc_qty_including_fees: u64,
+) -> Result<(usize, [Pubkey; MAX_PAIRS]), ProgramError> {
+    require!(
+        limit_price > 0 && max_coin_qty > 0 && max_native_pc_qty_including_fees > 0,
+        BlenderError::InvalidOrder
+    );
+
+    let accounts = &ctx.accounts;
+    require!(
+        accounts.pool.status == PoolStatus::Active,
+        BlenderError::PoolNotActive
+    );
+    let mango_account = MangoAccount::load_checked(
+        &accounts.mango_account,
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+    )?;
+    // the limits mean nothing while a delegate can trade around them
+    require!(
+        mango_account.delegate == Pubkey::default(),
+        BlenderError::DelegateSet
+    );
+    let mango_group = MangoGroup::load_checked(&accounts.mango_group, accounts.mango_program.key)?;
+    let mango_cache = MangoCache::load_checked(
+        &accounts.mango_cache,
+        accounts.mango_program.key,
+        &mango_group,
+    )?;
+
+    check_dex_program(&mango_group, &accounts.spot.dex_program)?;
+    let market_index = mango_group
+        .find_spot_market_index(accounts.spot.spot_market.key)
+        .ok_or(BlenderError::UnknownMarket)?;
+    require!(
+        mango_account.spot_open_orders[market_index] == accounts.spot.spot_open_orders.key(),
+        BlenderError::InvalidOpenOrders
+    );
+    require!(
+        accounts.pool.market_allowlist.is_spot_allowed(market_index),
+        BlenderError::MarketNotAllowed
+    );
+    let limits = &accounts.pool.risk_limits;
+
+    let (coin_lot_size, pc_lot_size) = {
+        let market = MarketState::load(
+            &accounts.spot.spot_market,
+            accounts.spot.dex_program.key,
+            false,
+        )?;
+        (
+            I80F48::from_num(market.coin_lot_size),
+            I80F48::from_num(market.pc_lot_size),
+        )
+    };
+
+    // limit price is in pc lots per coin lot, quantity in coin lots
+    let mut order_notional =
+        I80F48::from_num(limit_price) * I80F48::from_num(max_coin_qty) * pc_lot_size;
+    if side == OrderSide::Bid {
+        order_notional = order_notional.min(I80F48::from_num(max_native_pc_qty_including_fees));
+    }
+    require!(
+        limits.max_order_notional == 0
+            || order_notional <= I80F48::from_num(limits.max_order_notional),
+        BlenderError::OrderTooLarge
+    );
+
+    let max_position_notional = limits.max_position_notional[market_index];
+    if max_position_notional > 0 {
+        let base_net = get_mango_account_base_net(
+            &mango_account,
+            &mango_cache.root_bank_cache[market_index],
+            market_index,
+        );
+        let order_base = I80F48::from_num(max_coin_qty) * coin_lot_size;
+        let worst_position = match side {
+            OrderSide::Bid => base_net + order_base,
+            OrderSide::Ask => base_net - order_base,
+        };
+        let position_notional = worst_position.abs() * mango_cache.get_price(market_index);
+        require!(
+            position_notional <= I80F48::from_num(max_position_notional),
+            BlenderError::PositionTooLarge
+        );
+    }
+
+    Ok((market_index, mango_account.spot_open_orders))
+}
+
+/// Cancels a Serum order of the pooled MangoAccount, signed by the pool
+pub fn cancel_spot_order(
+    ctx: Context<ProxyCancelSpotOrder>,
+    side: OrderSide,
+    order_id: u128,
+) -> ProgramResult {
+    let accounts = &ctx.accounts;
+    {
+        let mango_group =
+            MangoGroup::load_checked(&accounts.mango_group, accounts.mango_program.key)?;
+        check_dex_program(&mango_group, &accounts.dex_program)?;
+    }
+    let seeds = &[
+        &accounts.pool.pool_name.as_ref(),
+        accounts.pool.admin.as_ref(),
+        &[accounts.pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    let cancel_instruction = MangoInstructions::cancel_spot_order(
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+        accounts.pool.to_account_info().key,
+        accounts.mango_account.key,
+        accounts.dex_program.key,
+        accounts.spot_market.key,
+        accounts.bids.key,
+        accounts.asks.key,
+        accounts.spot_open_orders.key,
+        accounts.mango_group_signer.key,
+        accounts.dex_event_queue.key,
+        CancelOrderInstructionV2 {
+            side: side.spot(),
+            order_id,
+        },
+    )
+    .unwrap();
+
+    invoke_signed_unchecked(
+        &cancel_instruction,
+        &[
+            accounts.mango_program.to_account_info(),
+            accounts.mango_group.to_account_info(),
+            accounts.pool.to_account_info(),
+            accounts.mango_account.to_account_info(),
+            accounts.dex_program.to_account_info(),
+            accounts.spot_market.to_account_info(),
+            accounts.bids.to_account_info(),
+            accounts.asks.to_account_info(),
+            accounts.spot_open_orders.to_account_info(),
+            accounts.mango_group_signer.to_account_info(),
+            accounts.dex_event_queue.to_account_info(),
+        ],
+        cpi_seed,
+    )?;
+    Ok(())
+}
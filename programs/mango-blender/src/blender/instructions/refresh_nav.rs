@@ -0,0 +1,176 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+use fixed::types::I80F48;
+use mango::state::{MangoAccount, MangoCache, MangoGroup, UserActiveAssets, QUOTE_INDEX};
+
+use crate::blender::state::{
+    NavHistory, NavHistoryEntry, NavSnapshot, Pool, Tranche, NAV_HISTORY_SIZE, NAV_SNAPSHOT_SIZE,
+};
+use crate::helpers::*;
+
+#[derive(Accounts)]
+#[instruction(bump: u8)]
+pub struct CreateNavSnapshot<'info> {
+    #[account(seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()], bump = pool.pool_bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        seeds = [pool.key().as_ref(), b"nav"],
+        bump = bump,
+        payer = payer,
+        space = NAV_SNAPSHOT_SIZE)]
+    pub nav_snapshot: Account<'info, NavSnapshot>,
+    #[account(mut, signer)]
+    pub payer: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateNavHistory<'info> {
+    #[account(seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()], bump = pool.pool_bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        seeds = [pool.key().as_ref(), b"nav_history"],
+        bump,
+        payer = payer,
+        space = NAV_HISTORY_SIZE)]
+    pub nav_history: AccountLoader<'info, NavHistory>,
+    #[account(mut, signer)]
+    pub payer: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RefreshNav<'info> {
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
+    pub mango_program: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = mango_account)]
+    pub pool: Box<Account<'info, Pool>>,
+    ///CHECK: checked in mango program
+    pub mango_group: UncheckedAccount<'info>,
+    ///CHECK: checked against the pool, loaded with mango's owner check
+    pub mango_account: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_cache: UncheckedAccount<'info>,
+    #[account(
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref(), b"iou"],
+        bump = pool.iou_mint_bump,
+    )]
+    pub pool_iou_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [pool.key().as_ref(), b"nav"],
+        bump = nav_snapshot.bump,
+        has_one = pool)]
+    pub nav_snapshot: Box<Account<'info, NavSnapshot>>,
+    #[account(mut, seeds = [pool.key().as_ref(), b"nav_history"], bump)]
+    pub nav_history: AccountLoader<'info, NavHistory>,
+    #[account(mut, seeds = [pool.key().as_ref(), b"price"], bump = pool.price_feed_bump)]
+    ///CHECK: pool's price account, layout checked when written
+    pub price_feed: UncheckedAccount<'info>,
+}
+
+/// Anyone can create a pool's NAV snapshot, it has to exist before deposits and withdrawals
+pub fn create_nav_snapshot(ctx: Context<CreateNavSnapshot>, bump: u8) -> ProgramResult {
+    let snapshot = &mut ctx.accounts.nav_snapshot;
+    snapshot.pool = ctx.accounts.pool.key();
+    snapshot.bump = bump;
+    Ok(())
+}
+
+/// Anyone can create a pool's NAV history, it has to exist before refresh_nav
+pub fn create_nav_history(ctx: Context<CreateNavHistory>) -> ProgramResult {
+    let mut history = ctx.accounts.nav_history.load_init()?;
+    history.pool = ctx.accounts.pool.key();
+    Ok(())
+}
+
+/// Permissionless, values the pooled MangoAccount and writes the result to the pool's NAV snapshot
+/// and price account (and to its NAV history, if the last entry is old enough)
+/// NAV per IOU is per junior IOU, the senior tranche's share of the pool comes off first
+pub fn refresh_nav<'a, 'b, 'c, 'info>(
+    ctx: Context<'a, 'b, 'c, 'info, RefreshNav<'info>>,
+) -> ProgramResult {
+    let (nav, quote_decimals) = value_pool(&ctx)?;
+    let iou_supply = ctx.accounts.pool_iou_mint.supply;
+    let clock = Clock::get()?;
+
+    let snapshot = &mut ctx.accounts.nav_snapshot;
+    snapshot.nav = nav.to_bits();
+    snapshot.iou_supply = iou_supply;
+    snapshot.slot = clock.slot;
+    snapshot.timestamp = clock.unix_timestamp;
+
+    if iou_supply == 0 {
+        return Ok(());
+    }
+    let junior_nav = ctx
+        .accounts
+        .pool
+        .tranche_value(Tranche::Junior, nav, clock.unix_timestamp);
+    let nav_per_iou = junior_nav / I80F48::from_num(iou_supply);
+
+    // keep the circuit breaker's high-water mark up to date
+    ctx.accounts.pool.record_nav_per_iou(nav_per_iou);
+    publish_nav_price(
+        &ctx.accounts.price_feed,
+        &ctx.accounts.pool,
+        nav_per_iou,
+        quote_decimals,
+    )?;
+
+    let mut history = ctx.accounts.nav_history.load_mut()?;
+    history.append(
+        NavHistoryEntry {
+            timestamp: clock.unix_timestamp,
+            nav_per_iou: nav_per_iou.to_bits(),
+            nav: nav.to_bits(),
+        },
+        ctx.accounts.pool.nav_history_interval_seconds,
+    );
+    Ok(())
+}
+
+#[inline(never)]
+fn value_pool<'a, 'b, 'c, 'info>(
+    ctx: &Context<'a, 'b, 'c, 'info, RefreshNav<'info>>,
+) -> Result<(I80F48, u8), ProgramError> {
+    let accounts = &ctx.accounts;
+    let mango_account = MangoAccount::load_checked(
+        &accounts.mango_account,
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+    )?;
+    let mango_group = MangoGroup::load_checked(&accounts.mango_group, accounts.mango_program.key)?;
+    let mango_cache = MangoCache::load_checked(
+        &accounts.mango_cache,
+        accounts.mango_program.key,
+        &mango_group,
+    )?;
+
+    let active_assets = UserActiveAssets::new(
+        &mango_group,
+        &mango_account,
+        pool_valuation_assets(&accounts.pool, vec![]),
+    );
+    let clock = Clock::get()?;
+    mango_cache.check_valid(&mango_group, &active_assets, clock.unix_timestamp as u64)?;
+
+    let open_orders_ais =
+        mango_account.checked_unpack_open_orders(&mango_group, &ctx.remaining_accounts)?;
+    let nav = calculate_pool_value(
+        &mango_account,
+        &mango_cache,
+        &mango_group,
+        open_orders_ais,
+        &active_assets,
+        accounts.pool.mngo_index(),
+    );
+    Ok((nav, mango_group.tokens[QUOTE_INDEX].decimals))
+}
@@ -0,0 +1,69 @@
+use anchor_lang::prelude::*;
+
+use crate::blender::errors::BlenderError;
+use crate::blender::instructions::update_pool::emit_settings_changed;
+use crate::blender::state::{Pool, PoolStatus};
+use crate::helpers::*;
+
+#[derive(Accounts)]
+pub struct SetDelegate<'info> {
+    #[account(
+        mut,
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = admin)]
+    pub pool: Account<'info, Pool>,
+    #[account(signer)]
+    pub admin: AccountInfo<'info>,
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
+    pub mango_program: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_group: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub mango_account: UncheckedAccount<'info>,
+    ///CHECK: any account can be the delegate, the system program revokes delegation
+    pub delegate: UncheckedAccount<'info>,
+}
+
+/// Hands raw trading control of the pooled MangoAccount to `delegate`
+/// Not allowed once the pool is proxy only, other than revoking
+pub fn set_delegate(ctx: Context<SetDelegate>) -> ProgramResult {
+    let revoking = ctx.accounts.delegate.key() == Pubkey::default();
+    require!(
+        ctx.accounts.pool.status == PoolStatus::Active || revoking,
+        BlenderError::PoolNotActive
+    );
+    require!(
+        !ctx.accounts.pool.proxy_only || revoking,
+        BlenderError::ProxyOnly
+    );
+    set_mango_delegate(
+        &ctx.accounts.pool,
+        &ctx.accounts.mango_program,
+        &ctx.accounts.mango_group,
+        &ctx.accounts.mango_account,
+        &ctx.accounts.delegate,
+    )
+}
+
+/// Revokes the delegate for good, after this the admin can only trade through the pool's proxy instructions
+/// (which are bound by the pool's risk limits)
+pub fn enable_proxy_only(ctx: Context<SetDelegate>) -> ProgramResult {
+    require!(
+        ctx.accounts.delegate.key() == Pubkey::default(),
+        BlenderError::ProxyOnly
+    );
+    set_mango_delegate(
+        &ctx.accounts.pool,
+        &ctx.accounts.mango_program,
+        &ctx.accounts.mango_group,
+        &ctx.accounts.mango_account,
+        &ctx.accounts.delegate,
+    )?;
+    let old_value = ctx.accounts.pool.proxy_only;
+    ctx.accounts.pool.proxy_only = true;
+    emit_settings_changed(&ctx.accounts.pool, "proxy_only", old_value as u64, 1);
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+use crate::blender::errors::BlenderError;
+use crate::blender::state::{DepositorPosition, Pool};
+use crate::helpers::*;
+
+#[derive(Accounts)]
+pub struct ThawIous<'info> {
+    #[account(seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()], bump = pool.pool_bump)]
+    pub pool: Box<Account<'info, Pool>>,
+    ///CHECK: owner of the position and the IOU account, doesn't have to sign
+    pub owner: UncheckedAccount<'info>,
+    #[account(
+        seeds = [pool.key().as_ref(), owner.key.as_ref(), b"position"],
+        bump = position.bump,
+        has_one = pool,
+        has_one = owner)]
+    pub position: Box<Account<'info, DepositorPosition>>,
+    #[account(
+        constraint = pool.tranche_of(&pool_iou_mint.key()).is_some() @ BlenderError::InvalidTrancheMint,
+    )]
+    pub pool_iou_mint: Box<Account<'info, Mint>>,
+    #[account(mut,
+        associated_token::authority = owner,
+        associated_token::mint = pool_iou_mint
+    )]
+    pub iou_token_account: Box<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Permissionless, thaws the IOUs frozen by a deposit once its lock has passed so they can be transferred again
+pub fn handler(ctx: Context<ThawIous>) -> ProgramResult {
+    let clock = Clock::get()?;
+    require!(
+        !ctx.accounts.position.is_deposit_locked(clock.slot),
+        BlenderError::WithdrawLocked
+    );
+    thaw_iou_account(
+        &ctx.accounts.pool,
+        &ctx.accounts.pool_iou_mint.to_account_info(),
+        &ctx.accounts.iou_token_account,
+        &ctx.accounts.token_program.to_account_info(),
+    )
+}
@@ -0,0 +1,68 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token};
+
+use crate::blender::errors::BlenderError;
+use crate::blender::state::{Pool, PoolStatus};
+
+#[derive(Accounts)]
+#[instruction(senior_iou_mint_bump: u8)]
+pub struct CreateSeniorTranche<'info> {
+    #[account(
+        mut,
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = admin)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, signer)]
+    pub admin: AccountInfo<'info>,
+    #[account(
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref(), b"iou"],
+        bump = pool.iou_mint_bump,
+    )]
+    pub pool_iou_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        mint::decimals = 6,
+        mint::authority = pool,
+        mint::freeze_authority = pool,
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref(), b"senior"],
+        bump = senior_iou_mint_bump,
+        payer = admin
+    )]
+    pub senior_iou_mint: Account<'info, Mint>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+/// Splits an empty pool into a senior tranche (the new mint) and a junior tranche (the pool's IOU mint)
+/// Senior IOUs are owed their deposits plus `senior_rate_bps` a year and are paid before junior IOUs
+/// Only possible before anyone holds IOUs, existing holders would be subordinated without a say
+pub fn create_senior_tranche(
+    ctx: Context<CreateSeniorTranche>,
+    senior_iou_mint_bump: u8,
+    senior_rate_bps: u16,
+) -> ProgramResult {
+    require!(
+        ctx.accounts.pool.status == PoolStatus::Active,
+        BlenderError::PoolNotActive
+    );
+    require!(
+        !ctx.accounts.pool.has_senior_tranche(),
+        BlenderError::InvalidPoolSetting
+    );
+    require!(
+        ctx.accounts.pool_iou_mint.supply == 0,
+        BlenderError::PoolNotEmpty
+    );
+    require!(senior_rate_bps <= 10_000, BlenderError::InvalidPoolSetting);
+
+    let pool = &mut ctx.accounts.pool;
+    pool.senior_iou_mint = ctx.accounts.senior_iou_mint.key();
+    pool.senior_iou_mint_bump = senior_iou_mint_bump;
+    pool.senior_rate_bps = senior_rate_bps;
+    pool.senior_claim = 0;
+    pool.senior_accrual_ts = Clock::get()?.unix_timestamp;
+    Ok(())
+}
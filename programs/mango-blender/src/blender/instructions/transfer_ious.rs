@@ -0,0 +1,115 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+
+use crate::blender::errors::BlenderError;
+use crate::blender::state::{DepositorPosition, Pool, Tranche};
+use crate::helpers::*;
+
+#[derive(Accounts)]
+pub struct TransferIous<'info> {
+    #[account(mut, seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()], bump = pool.pool_bump)]
+    pub pool: Box<Account<'info, Pool>>,
+    #[account(signer)]
+    pub owner: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [pool.key().as_ref(), owner.key.as_ref(), b"position"],
+        bump = owner_position.bump,
+        has_one = pool,
+        has_one = owner)]
+    pub owner_position: Box<Account<'info, DepositorPosition>>,
+    #[account(constraint = recipient.key() != owner.key())]
+    ///CHECK: receives the IOUs, needs a position in the pool
+    pub recipient: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [pool.key().as_ref(), recipient.key.as_ref(), b"position"],
+        bump = recipient_position.bump,
+        has_one = pool,
+        constraint = recipient_position.owner == recipient.key())]
+    pub recipient_position: Box<Account<'info, DepositorPosition>>,
+    #[account(
+        constraint = pool.tranche_of(&pool_iou_mint.key()).is_some() @ BlenderError::InvalidTrancheMint,
+    )]
+    pub pool_iou_mint: Box<Account<'info, Mint>>,
+    #[account(mut,
+        associated_token::authority = owner,
+        associated_token::mint = pool_iou_mint
+    )]
+    pub owner_iou_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(mut,
+        associated_token::authority = recipient,
+        associated_token::mint = pool_iou_mint
+    )]
+    pub recipient_iou_token_account: Box<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Moves IOUs to another holder through the pool, junior IOUs moved this way keep earning distributions
+/// Distributions owed to both sides are settled first, the sender has to be past their deposit lock
+pub fn handler(ctx: Context<TransferIous>, amount: u64) -> ProgramResult {
+    let clock = Clock::get()?;
+    require!(
+        !ctx.accounts.owner_position.is_deposit_locked(clock.slot),
+        BlenderError::WithdrawLocked
+    );
+
+    let accounts = &mut *ctx.accounts;
+    if accounts.pool.tranche_of(&accounts.pool_iou_mint.key()) == Some(Tranche::Junior) {
+        // the recorded IOUs go first, the recipient only picks up the ones distributions are paid on
+        let owner_balance = accounts.owner_iou_token_account.amount;
+        let owner_position = &mut accounts.owner_position;
+        owner_position.settle_distribution(&mut accounts.pool, owner_balance)?;
+        let owner_recorded = owner_position.distribution_balance;
+        let owner_distribution_balance = owner_recorded.min(owner_balance.saturating_sub(amount));
+        owner_position
+            .record_distribution_balance(&mut accounts.pool, owner_distribution_balance)?;
+
+        let recipient_balance = accounts.recipient_iou_token_account.amount;
+        let recipient_position = &mut accounts.recipient_position;
+        recipient_position.settle_distribution(&mut accounts.pool, recipient_balance)?;
+        let recipient_distribution_balance = recipient_position
+            .distribution_balance
+            .checked_add(owner_recorded - owner_distribution_balance)
+            .ok_or(BlenderError::MathError)?;
+        recipient_position
+            .record_distribution_balance(&mut accounts.pool, recipient_distribution_balance)?;
+    }
+
+    // a recipient still inside their own deposit lock stays frozen
+    let recipient_frozen = ctx.accounts.recipient_iou_token_account.is_frozen();
+    let pool_iou_mint_ai = ctx.accounts.pool_iou_mint.to_account_info();
+    let token_program_ai = ctx.accounts.token_program.to_account_info();
+    thaw_iou_account(
+        &ctx.accounts.pool,
+        &pool_iou_mint_ai,
+        &ctx.accounts.owner_iou_token_account,
+        &token_program_ai,
+    )?;
+    thaw_iou_account(
+        &ctx.accounts.pool,
+        &pool_iou_mint_ai,
+        &ctx.accounts.recipient_iou_token_account,
+        &token_program_ai,
+    )?;
+
+    let transfer_accounts = Transfer {
+        from: ctx.accounts.owner_iou_token_account.to_account_info(),
+        to: ctx.accounts.recipient_iou_token_account.to_account_info(),
+        authority: ctx.accounts.owner.to_account_info(),
+    };
+    token::transfer(
+        CpiContext::new(token_program_ai.clone(), transfer_accounts),
+        amount,
+    )?;
+
+    if recipient_frozen {
+        freeze_iou_account(
+            &ctx.accounts.pool,
+            &pool_iou_mint_ai,
+            &ctx.accounts.recipient_iou_token_account,
+            &token_program_ai,
+        )?;
+    }
+    Ok(())
+}
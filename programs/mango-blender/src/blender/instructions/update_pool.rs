@@ -0,0 +1,436 @@
+use anchor_lang::prelude::*;
+use mango::state::QUOTE_INDEX;
+
+use crate::blender::errors::BlenderError;
+use crate::blender::events::{MarketAllowlistChanged, PoolSettingsChanged, RiskLimitsChanged};
+use crate::blender::state::{Pool, RiskLimits, MAX_DEPOSIT_LOCK_SLOTS, MAX_NAV_AGE_SLOTS};
+
+#[derive(Accounts)]
+pub struct UpdatePool<'info> {
+    #[account(
+        mut,
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = admin)]
+    pub pool: Account<'info, Pool>,
+    #[account(signer)]
+    pub admin: AccountInfo<'info>,
+}
+
+/// Withdrawals are blocked until `deposit_lock_slots` slots have passed since the withdrawer's last deposit
+/// (withdrawals in the same slot as a deposit are always blocked), at most `MAX_DEPOSIT_LOCK_SLOTS`
+/// Only deposits made from here on are locked for the new number of slots
+pub fn set_deposit_lock_slots(ctx: Context<UpdatePool>, deposit_lock_slots: u64) -> ProgramResult {
+    require!(
+        deposit_lock_slots <= MAX_DEPOSIT_LOCK_SLOTS,
+        BlenderError::InvalidPoolSetting
+    );
+    let old_value = ctx.accounts.pool.deposit_lock_slots;
+    ctx.accounts.pool.deposit_lock_slots = deposit_lock_slots;
+    emit_settings_changed(
+        &ctx.accounts.pool,
+        "deposit_lock_slots",
+        old_value,
+        deposit_lock_slots,
+    );
+    Ok(())
+}
+
+/// Caps how much QUOTE can be withdrawn in a rolling 24 hour window, in bps of the pool value at window start (0 = no limit)
+pub fn set_withdrawal_limit(ctx: Context<UpdatePool>, withdrawal_limit_bps: u16) -> ProgramResult {
+    require!(
+        withdrawal_limit_bps <= 10_000,
+        BlenderError::InvalidPoolSetting
+    );
+    if withdrawal_limit_bps == 0 {
+        // unhealthy withdrawals are queued through the window
+        require!(
+            !ctx.accounts.pool.queue_unhealthy_withdrawals,
+            BlenderError::InvalidPoolSetting
+        );
+        ctx.accounts.pool.clear_withdrawal_queue();
+    }
+    let old_value = ctx.accounts.pool.withdrawal_limit_bps;
+    ctx.accounts.pool.withdrawal_limit_bps = withdrawal_limit_bps;
+    emit_settings_changed(
+        &ctx.accounts.pool,
+        "withdrawal_limit_bps",
+        old_value as u64,
+        withdrawal_limit_bps as u64,
+    );
+    Ok(())
+}
+
+/// Below maintenance health withdrawals are rejected, or with this on queued for a keeper to pay out
+/// once the account is healthy again (needs a withdrawal limit)
+pub fn set_queue_unhealthy_withdrawals(
+    ctx: Context<UpdatePool>,
+    queue_unhealthy_withdrawals: bool,
+) -> ProgramResult {
+    require!(
+        !queue_unhealthy_withdrawals || ctx.accounts.pool.withdrawal_limit_bps > 0,
+        BlenderError::InvalidPoolSetting
+    );
+    let old_value = ctx.accounts.pool.queue_unhealthy_withdrawals;
+    ctx.accounts.pool.queue_unhealthy_withdrawals = queue_unhealthy_withdrawals;
+    emit_settings_changed(
+        &ctx.accounts.pool,
+        "queue_unhealthy_withdrawals",
+        old_value as u64,
+        queue_unhealthy_withdrawals as u64,
+    );
+    Ok(())
+}
+
+/// After a withdrawal the pool's init health must stay above `withdraw_health_floor_bps` of the pool value (0 = only Mango's own check)
+pub fn set_withdraw_health_floor(
+    ctx: Context<UpdatePool>,
+    withdraw_health_floor_bps: u16,
+) -> ProgramResult {
+    require!(
+        withdraw_health_floor_bps <= 10_000,
+        BlenderError::InvalidPoolSetting
+    );
+    // borrowing withdrawals are only safe with a floor
+    require!(
+        withdraw_health_floor_bps > 0 || ctx.accounts.pool.max_withdraw_leverage_bps == 0,
+        BlenderError::InvalidPoolSetting
+    );
+    let old_value = ctx.accounts.pool.withdraw_health_floor_bps;
+    ctx.accounts.pool.withdraw_health_floor_bps = withdraw_health_floor_bps;
+    emit_settings_changed(
+        &ctx.accounts.pool,
+        "withdraw_health_floor_bps",
+        old_value as u64,
+        withdraw_health_floor_bps as u64,
+    );
+    Ok(())
+}
+
+/// Lets withdrawals borrow QUOTE when the pool's equity isn't sitting in QUOTE, as long as borrows stay under
+/// `max_withdraw_leverage_bps` of the pool value (0 = no borrowing). Requires a health floor to be set first
+pub fn set_max_withdraw_leverage(
+    ctx: Context<UpdatePool>,
+    max_withdraw_leverage_bps: u32,
+) -> ProgramResult {
+    require!(
+        max_withdraw_leverage_bps == 0 || ctx.accounts.pool.withdraw_health_floor_bps > 0,
+        BlenderError::InvalidPoolSetting
+    );
+    let old_value = ctx.accounts.pool.max_withdraw_leverage_bps;
+    ctx.accounts.pool.max_withdraw_leverage_bps = max_withdraw_leverage_bps;
+    emit_settings_changed(
+        &ctx.accounts.pool,
+        "max_withdraw_leverage_bps",
+        old_value as u64,
+        max_withdraw_leverage_bps as u64,
+    );
+    Ok(())
+}
+
+/// Replaces the limits the proxy trading instructions are checked against, tightening applies now,
+/// loosening waits for the allowlist timelock and is then moved in with `apply_risk_limits`
+pub fn set_risk_limits(ctx: Context<UpdatePool>, risk_limits: RiskLimits) -> ProgramResult {
+    let now_ts = Clock::get()?.unix_timestamp;
+    let old_limits = ctx.accounts.pool.risk_limits;
+    ctx.accounts.pool.update_risk_limits(risk_limits, now_ts);
+    emit!(RiskLimitsChanged {
+        pool: ctx.accounts.pool.key(),
+        old_limits,
+        new_limits: risk_limits,
+        effective_ts: ctx.accounts.pool.pending_risk_limits_ts,
+    });
+    Ok(())
+}
+
+/// Sets the markets the pool may trade (bit i = Mango market index i), narrowing applies now,
+/// widening waits for the allowlist timelock and is then moved in with `apply_market_allowlist`
+pub fn set_market_allowlist(
+    ctx: Context<UpdatePool>,
+    spot_markets: u16,
+    perp_markets: u16,
+) -> ProgramResult {
+    let now_ts = Clock::get()?.unix_timestamp;
+    let old_allowlist = ctx.accounts.pool.market_allowlist;
+    ctx.accounts
+        .pool
+        .market_allowlist
+        .update(spot_markets, perp_markets, now_ts);
+    emit!(MarketAllowlistChanged {
+        pool: ctx.accounts.pool.key(),
+        old_spot_markets: old_allowlist.spot_markets,
+        old_perp_markets: old_allowlist.perp_markets,
+        new_spot_markets: spot_markets,
+        new_perp_markets: perp_markets,
+        effective_ts: ctx.accounts.pool.market_allowlist.pending_effective_ts,
+    });
+    Ok(())
+}
+
+/// The timelock can only go up, otherwise it could be dropped right before widening the allowlist
+/// (it also holds back looser risk limits)
+pub fn set_allowlist_timelock(ctx: Context<UpdatePool>, timelock_seconds: i64) -> ProgramResult {
+    require!(
+        timelock_seconds >= ctx.accounts.pool.market_allowlist.timelock_seconds,
+        BlenderError::InvalidPoolSetting
+    );
+    let old_value = ctx.accounts.pool.market_allowlist.timelock_seconds;
+    ctx.accounts.pool.market_allowlist.timelock_seconds = timelock_seconds;
+    emit_settings_changed(
+        &ctx.accounts.pool,
+        "allowlist_timelock_seconds",
+        old_value as u64,
+        timelock_seconds as u64,
+    );
+    Ok(())
+}
+
+/// Drawdown of NAV per IOU from its high-water mark at which anyone can trip the circuit breaker (0 = off)
+/// Lowering it applies now, raising it or turning it off waits for the allowlist timelock (see `apply_max_drawdown`)
+pub fn set_max_drawdown(ctx: Context<UpdatePool>, max_drawdown_bps: u16) -> ProgramResult {
+    require!(max_drawdown_bps <= 10_000, BlenderError::InvalidPoolSetting);
+    let now_ts = Clock::get()?.unix_timestamp;
+    let old_value = ctx.accounts.pool.max_drawdown_bps;
+    ctx.accounts
+        .pool
+        .update_max_drawdown(max_drawdown_bps, now_ts);
+    emit!(PoolSettingsChanged {
+        pool: ctx.accounts.pool.key(),
+        setting: "max_drawdown_bps".to_string(),
+        old_value: old_value as u64,
+        new_value: max_drawdown_bps as u64,
+        effective_ts: ctx.accounts.pool.pending_max_drawdown_ts,
+    });
+    Ok(())
+}
+
+/// Counts MNGO rewards accrued on perp markets (not yet redeemed) in the pool value, priced as token `mngo_token_index`
+pub fn set_accrued_mngo_valuation(
+    ctx: Context<UpdatePool>,
+    value_accrued_mngo: bool,
+    mngo_token_index: u8,
+) -> ProgramResult {
+    require!(
+        (mngo_token_index as usize) < QUOTE_INDEX,
+        BlenderError::InvalidPoolSetting
+    );
+    let pool = &mut ctx.accounts.pool;
+    let old_value_accrued_mngo = pool.value_accrued_mngo;
+    let old_mngo_token_index = pool.mngo_token_index;
+    pool.value_accrued_mngo = value_accrued_mngo;
+    pool.mngo_token_index = mngo_token_index;
+    emit_settings_changed(
+        pool,
+        "value_accrued_mngo",
+        old_value_accrued_mngo as u64,
+        value_accrued_mngo as u64,
+    );
+    emit_settings_changed(
+        pool,
+        "mngo_token_index",
+        old_mngo_token_index as u64,
+        mngo_token_index as u64,
+    );
+    Ok(())
+}
+
+/// Lets deposits and withdrawals use the pool's NAV snapshot while it is at most `nav_max_age_slots` old
+/// instead of valuing the MangoAccount themselves (0 = always value live), at most `MAX_NAV_AGE_SLOTS`
+pub fn set_nav_max_age_slots(ctx: Context<UpdatePool>, nav_max_age_slots: u64) -> ProgramResult {
+    require!(
+        nav_max_age_slots <= MAX_NAV_AGE_SLOTS,
+        BlenderError::InvalidPoolSetting
+    );
+    let old_value = ctx.accounts.pool.nav_max_age_slots;
+    ctx.accounts.pool.nav_max_age_slots = nav_max_age_slots;
+    emit_settings_changed(
+        &ctx.accounts.pool,
+        "nav_max_age_slots",
+        old_value,
+        nav_max_age_slots,
+    );
+    Ok(())
+}
+
+/// refresh_nav adds to the NAV history at most once every `nav_history_interval_seconds`
+pub fn set_nav_history_interval(
+    ctx: Context<UpdatePool>,
+    nav_history_interval_seconds: i64,
+) -> ProgramResult {
+    require!(
+        nav_history_interval_seconds >= 0,
+        BlenderError::InvalidPoolSetting
+    );
+    let old_value = ctx.accounts.pool.nav_history_interval_seconds;
+    ctx.accounts.pool.nav_history_interval_seconds = nav_history_interval_seconds;
+    emit_settings_changed(
+        &ctx.accounts.pool,
+        "nav_history_interval_seconds",
+        old_value as u64,
+        nav_history_interval_seconds as u64,
+    );
+    Ok(())
+}
+
+/// Confidence interval published alongside the NAV price, in bps of the price
+pub fn set_price_confidence(ctx: Context<UpdatePool>, price_confidence_bps: u16) -> ProgramResult {
+    require!(
+        price_confidence_bps <= 10_000,
+        BlenderError::InvalidPoolSetting
+    );
+    let old_value = ctx.accounts.pool.price_confidence_bps;
+    ctx.accounts.pool.price_confidence_bps = price_confidence_bps;
+    emit_settings_changed(
+        &ctx.accounts.pool,
+        "price_confidence_bps",
+        old_value as u64,
+        price_confidence_bps as u64,
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ApplyMarketAllowlist<'info> {
+    #[account(mut, seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()], bump = pool.pool_bump)]
+    pub pool: Account<'info, Pool>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyRiskLimits<'info> {
+    #[account(mut, seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()], bump = pool.pool_bump)]
+    pub pool: Account<'info, Pool>,
+}
+
+#[derive(Accounts)]
+pub struct ApplyMaxDrawdown<'info> {
+    #[account(mut, seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()], bump = pool.pool_bump)]
+    pub pool: Account<'info, Pool>,
+}
+
+/// Permissionless, moves a pending allowlist in once its timelock has passed
+pub fn apply_market_allowlist(ctx: Context<ApplyMarketAllowlist>) -> ProgramResult {
+    let now_ts = Clock::get()?.unix_timestamp;
+    let old_allowlist = ctx.accounts.pool.market_allowlist;
+    require!(
+        ctx.accounts.pool.market_allowlist.apply_pending(now_ts),
+        BlenderError::AllowlistTimelocked
+    );
+    let allowlist = &ctx.accounts.pool.market_allowlist;
+    emit!(MarketAllowlistChanged {
+        pool: ctx.accounts.pool.key(),
+        old_spot_markets: old_allowlist.spot_markets,
+        old_perp_markets: old_allowlist.perp_markets,
+        new_spot_markets: allowlist.spot_markets,
+        new_perp_markets: allowlist.perp_markets,
+        effective_ts: 0,
+    });
+    Ok(())
+}
+
+/// Permissionless, moves pending risk limits in once their timelock has passed
+pub fn apply_risk_limits(ctx: Context<ApplyRiskLimits>) -> ProgramResult {
+    let now_ts = Clock::get()?.unix_timestamp;
+    let old_limits = ctx.accounts.pool.risk_limits;
+    require!(
+        ctx.accounts.pool.apply_pending_risk_limits(now_ts),
+        BlenderError::RiskLimitsTimelocked
+    );
+    emit!(RiskLimitsChanged {
+        pool: ctx.accounts.pool.key(),
+        old_limits,
+        new_limits: ctx.accounts.pool.risk_limits,
+        effective_ts: 0,
+    });
+    Ok(())
+}
+
+/// Permissionless, moves a pending drawdown limit in once its timelock has passed
+pub fn apply_max_drawdown(ctx: Context<ApplyMaxDrawdown>) -> ProgramResult {
+    let now_ts = Clock::get()?.unix_timestamp;
+    let old_value = ctx.accounts.pool.max_drawdown_bps;
+    require!(
+        ctx.accounts.pool.apply_pending_max_drawdown(now_ts),
+        BlenderError::MaxDrawdownTimelocked
+    );
+    let new_value = ctx.accounts.pool.max_drawdown_bps;
+    emit_settings_changed(
+        &ctx.accounts.pool,
+        "max_drawdown_bps",
+        old_value as u64,
+        new_value as u64,
+    );
+    Ok(())
+}
+
+/// Annual return accrued to the senior tranche, what was accrued at the old rate is kept
+pub fn set_senior_rate(ctx: Context<UpdatePool>, senior_rate_bps: u16) -> ProgramResult {
+    require!(
+        ctx.accounts.pool.has_senior_tranche(),
+        BlenderError::InvalidPoolSetting
+    );
+    require!(senior_rate_bps <= 10_000, BlenderError::InvalidPoolSetting);
+    let now_ts = Clock::get()?.unix_timestamp;
+    ctx.accounts.pool.accrue_senior_claim(now_ts);
+    let old_value = ctx.accounts.pool.senior_rate_bps;
+    ctx.accounts.pool.senior_rate_bps = senior_rate_bps;
+    emit_settings_changed(
+        &ctx.accounts.pool,
+        "senior_rate_bps",
+        old_value as u64,
+        senior_rate_bps as u64,
+    );
+    Ok(())
+}
+
+/// Pays NAV growth out to junior IOU holders every `distribution_interval_seconds` instead of compounding it
+/// Turning the mode on starts measuring growth from the next distribute crank
+/// Distributions are paid on the junior IOUs recorded on depositor positions, which deposits and transfer_ious keep
+/// up to date, IOUs moved any other way stop earning
+pub fn set_distribution_mode(
+    ctx: Context<UpdatePool>,
+    distribution_mode: bool,
+    distribution_interval_seconds: i64,
+) -> ProgramResult {
+    require!(
+        distribution_interval_seconds >= 0,
+        BlenderError::InvalidPoolSetting
+    );
+    let pool = &mut ctx.accounts.pool;
+    let old_distribution_mode = pool.distribution_mode;
+    let old_distribution_interval_seconds = pool.distribution_interval_seconds;
+    if distribution_mode && !pool.distribution_mode {
+        pool.distribution_nav_per_iou = 0;
+        pool.last_distribution_ts = 0;
+    }
+    pool.distribution_mode = distribution_mode;
+    pool.distribution_interval_seconds = distribution_interval_seconds;
+    emit_settings_changed(
+        pool,
+        "distribution_mode",
+        old_distribution_mode as u64,
+        distribution_mode as u64,
+    );
+    emit_settings_changed(
+        pool,
+        "distribution_interval_seconds",
+        old_distribution_interval_seconds as u64,
+        distribution_interval_seconds as u64,
+    );
+    Ok(())
+}
+
+/// For a setting that changed right away
+pub(crate) fn emit_settings_changed(
+    pool: &Account<Pool>,
+    setting: &str,
+    old_value: u64,
+    new_value: u64,
+) {
+    emit!(PoolSettingsChanged {
+        pool: pool.key(),
+        setting: setting.to_string(),
+        old_value,
+        new_value,
+        effective_ts: 0,
+    });
+}
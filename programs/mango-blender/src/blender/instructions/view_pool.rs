@@ -0,0 +1,56 @@
+use anchor_lang::prelude::*;
+use mango::state::MAX_PAIRS;
+
+use crate::blender::state::{Pool, WITHDRAWAL_WINDOW_SECONDS};
+
+#[derive(Accounts)]
+pub struct ViewPool<'info> {
+    #[account(seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()], bump = pool.pool_bump)]
+    pub pool: Account<'info, Pool>,
+}
+
+/// Read-only, logs the state of the current withdrawal window so front ends can show the remaining capacity
+/// If the window has run out, the next withdrawal starts a new one at the then current pool value
+pub fn view_withdrawal_capacity(ctx: Context<ViewPool>) -> ProgramResult {
+    let pool = &ctx.accounts.pool;
+    let now_ts = Clock::get()?.unix_timestamp;
+    let expired = now_ts >= pool.withdrawal_window_start + WITHDRAWAL_WINDOW_SECONDS;
+
+    msg!("withdrawal_limit_bps: {}", pool.withdrawal_limit_bps);
+    msg!("window_start: {}", pool.withdrawal_window_start);
+    msg!(
+        "window_end: {}",
+        pool.withdrawal_window_start + WITHDRAWAL_WINDOW_SECONDS
+    );
+    msg!("window_expired: {}", expired);
+    msg!("window_nav: {}", pool.withdrawal_window_nav);
+    msg!("window_limit: {}", pool.withdrawal_window_limit());
+    msg!("window_used: {}", pool.withdrawal_window_used);
+    msg!("window_reserved: {}", pool.withdrawal_window_reserved);
+    msg!("window_remaining: {}", pool.withdrawal_window_remaining());
+    msg!("queued_for_next_window: {}", pool.withdrawal_queued);
+    Ok(())
+}
+
+/// Read-only, logs the markets the pool can trade plus any pending change
+pub fn view_market_allowlist(ctx: Context<ViewPool>) -> ProgramResult {
+    let allowlist = &ctx.accounts.pool.market_allowlist;
+
+    msg!("spot_markets: {:?}", market_indexes(allowlist.spot_markets));
+    msg!("perp_markets: {:?}", market_indexes(allowlist.perp_markets));
+    msg!(
+        "pending_spot_markets: {:?}",
+        market_indexes(allowlist.pending_spot_markets)
+    );
+    msg!(
+        "pending_perp_markets: {:?}",
+        market_indexes(allowlist.pending_perp_markets)
+    );
+    msg!("pending_effective_ts: {}", allowlist.pending_effective_ts);
+    msg!("timelock_seconds: {}", allowlist.timelock_seconds);
+    Ok(())
+}
+
+fn market_indexes(bitmap: u16) -> Vec<usize> {
+    (0..MAX_PAIRS).filter(|i| bitmap & (1 << i) != 0).collect()
+}
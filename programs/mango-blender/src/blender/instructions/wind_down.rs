@@ -0,0 +1,864 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Burn, Token};
+use fixed::types::I80F48;
+use mango::declare_check_assert_macros;
+use mango::error::{check_assert, MangoErrorCode, SourceFileId};
+use mango::instruction as MangoInstructions;
+use mango::matching::{OrderType, Side};
+use mango::state::{
+    load_open_orders, AssetType, MangoAccount, MangoCache, MangoGroup, UserActiveAssets, MAX_PAIRS,
+    ONE_I80F48, QUOTE_INDEX, ZERO_I80F48,
+};
+use serum_dex::instruction::{CancelOrderInstructionV2, NewOrderInstructionV3, SelfTradeBehavior};
+use serum_dex::matching::{OrderType as SpotOrderType, Side as SpotSide};
+use serum_dex::state::MarketState;
+use solana_program::program::invoke_signed_unchecked;
+use std::num::NonZeroU64;
+
+use crate::blender::errors::BlenderError;
+use crate::blender::events::{StatusChanged, Withdraw};
+use crate::blender::instructions::withdraw_from_pool::{
+    settle_burned_distribution, withdraw_from_mango, WithdrawFromPool,
+};
+use crate::blender::instructions::withdraw_with_unwind::SpotMarketAccounts;
+use crate::blender::state::{Pool, PoolStatus, Tranche};
+use crate::helpers::*;
+
+declare_check_assert_macros!(SourceFileId::Processor);
+
+#[derive(Accounts)]
+pub struct StartWindDown<'info> {
+    #[account(
+        mut,
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = admin,
+        has_one = mango_account)]
+    pub pool: Account<'info, Pool>,
+    #[account(signer)]
+    pub admin: AccountInfo<'info>,
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
+    pub mango_program: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_group: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked against the pool
+    pub mango_account: UncheckedAccount<'info>,
+    // the default pubkey, revokes the delegate
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnwindPerp<'info> {
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
+    pub mango_program: UncheckedAccount<'info>,
+    #[account(
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = mango_account)]
+    pub pool: Box<Account<'info, Pool>>,
+    ///CHECK: checked in mango program
+    pub mango_group: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked against the pool
+    pub mango_account: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_cache: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub perp_market: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub bids: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub asks: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub event_queue: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelAllOrders<'info> {
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
+    pub mango_program: UncheckedAccount<'info>,
+    #[account(
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = mango_account)]
+    pub pool: Box<Account<'info, Pool>>,
+    ///CHECK: checked in mango program
+    pub mango_group: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_group_signer: UncheckedAccount<'info>,
+    ///CHECK: checked against the pool
+    pub mango_account: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub dex_program: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub spot_market: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub bids: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub asks: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub spot_open_orders: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub dex_event_queue: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UnwindSpot<'info> {
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
+    pub mango_program: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump = pool.pool_bump,
+        has_one = mango_account)]
+    pub pool: Box<Account<'info, Pool>>,
+    ///CHECK: checked in mango program
+    pub mango_group: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked against the pool
+    pub mango_account: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_cache: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub mango_group_signer: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub quote_root_bank: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub quote_node_bank: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub quote_vault: UncheckedAccount<'info>,
+    pub spot: SpotMarketAccounts<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Shuts the pool down for good: revokes the delegate and lets keepers close out the MangoAccount
+/// Keeper perp orders can be at most `wind_down_slippage_bps` away from the oracle
+pub fn start_wind_down(ctx: Context<StartWindDown>, wind_down_slippage_bps: u16) -> ProgramResult {
+    require!(
+        ctx.accounts.pool.status != PoolStatus::WindDown,
+        BlenderError::PoolNotActive
+    );
+    require!(
+        wind_down_slippage_bps < 10_000,
+        BlenderError::InvalidSlippage
+    );
+
+    set_mango_delegate(
+        &ctx.accounts.pool,
+        &ctx.accounts.mango_program,
+        &ctx.accounts.mango_group,
+        &ctx.accounts.mango_account,
+        &ctx.accounts.system_program,
+    )?;
+    let old_status = ctx.accounts.pool.status;
+    ctx.accounts.pool.status = PoolStatus::WindDown;
+    ctx.accounts.pool.wind_down_slippage_bps = wind_down_slippage_bps;
+    emit!(StatusChanged {
+        pool: ctx.accounts.pool.key(),
+        old_status,
+        new_status: PoolStatus::WindDown,
+    });
+    Ok(())
+}
+
+struct PerpUnwindOrder {
+    side: Side,
+    price: i64,
+    quantity: i64,
+    open_orders_keys: [Pubkey; MAX_PAIRS],
+}
+
+/// Permissionless during wind down, cancels the pool's resting orders on a perp market
+/// and closes its position there with a reduce-only IOC order
+pub fn unwind_perp<'a, 'b, 'c, 'info>(
+    ctx: Context<'a, 'b, 'c, 'info, UnwindPerp<'info>>,
+    limit: u8,
+) -> ProgramResult {
+    require!(
+        ctx.accounts.pool.status == PoolStatus::WindDown,
+        BlenderError::NotWindingDown
+    );
+    let order = prepare_perp_unwind(&ctx)?;
+
+    let accounts = &ctx.accounts;
+    let seeds = &[
+        &accounts.pool.pool_name.as_ref(),
+        accounts.pool.admin.as_ref(),
+        &[accounts.pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    let cancel_instruction = MangoInstructions::cancel_all_perp_orders(
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+        accounts.mango_account.key,
+        accounts.pool.to_account_info().key,
+        accounts.perp_market.key,
+        accounts.bids.key,
+        accounts.asks.key,
+        limit,
+    )
+    .unwrap();
+
+    invoke_signed_unchecked(
+        &cancel_instruction,
+        &[
+            accounts.mango_program.to_account_info(),
+            accounts.mango_group.to_account_info(),
+            accounts.mango_account.to_account_info(),
+            accounts.pool.to_account_info(),
+            accounts.perp_market.to_account_info(),
+            accounts.bids.to_account_info(),
+            accounts.asks.to_account_info(),
+        ],
+        cpi_seed,
+    )?;
+
+    if order.quantity == 0 {
+        msg!("no perp position to unwind");
+        return Ok(());
+    }
+
+    let place_instruction = MangoInstructions::place_perp_order(
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+        accounts.mango_account.key,
+        accounts.pool.to_account_info().key,
+        accounts.mango_cache.key,
+        accounts.perp_market.key,
+        accounts.bids.key,
+        accounts.asks.key,
+        accounts.event_queue.key,
+        None,
+        &order.open_orders_keys,
+        order.side,
+        order.price,
+        order.quantity,
+        0,
+        OrderType::ImmediateOrCancel,
+        true,
+    )
+    .unwrap();
+
+    let mut account_infos = vec![
+        accounts.mango_program.to_account_info(),
+        accounts.mango_group.to_account_info(),
+        accounts.mango_account.to_account_info(),
+        accounts.pool.to_account_info(),
+        accounts.mango_cache.to_account_info(),
+        accounts.perp_market.to_account_info(),
+        accounts.bids.to_account_info(),
+        accounts.asks.to_account_info(),
+        accounts.event_queue.to_account_info(),
+    ];
+    account_infos.extend(ctx.remaining_accounts.iter().cloned());
+    invoke_signed_unchecked(&place_instruction, &account_infos, cpi_seed)?;
+    Ok(())
+}
+
+/// Sizes and prices the closing order: the whole position, on the other side, at the oracle price
+/// moved against the pool by the wind down slippage (in quote lots per base lot)
+#[inline(never)]
+fn prepare_perp_unwind<'a, 'b, 'c, 'info>(
+    ctx: &Context<'a, 'b, 'c, 'info, UnwindPerp<'info>>,
+) -> Result<PerpUnwindOrder, ProgramError> {
+    let accounts = &ctx.accounts;
+    let mango_account = MangoAccount::load_checked(
+        &accounts.mango_account,
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+    )?;
+    let mango_group = MangoGroup::load_checked(&accounts.mango_group, accounts.mango_program.key)?;
+    let mango_cache = MangoCache::load_checked(
+        &accounts.mango_cache,
+        accounts.mango_program.key,
+        &mango_group,
+    )?;
+
+    let market_index = mango_group
+        .find_perp_market_index(accounts.perp_market.key)
+        .ok_or(BlenderError::UnknownMarket)?;
+    // the order is priced off the cached oracle price
+    let active_assets = UserActiveAssets::new(
+        &mango_group,
+        &mango_account,
+        vec![(AssetType::Perp, market_index)],
+    );
+    mango_cache.check_valid(
+        &mango_group,
+        &active_assets,
+        Clock::get()?.unix_timestamp as u64,
+    )?;
+    let base_position = mango_account.perp_accounts[market_index].base_position;
+    let perp_market_info = &mango_group.perp_markets[market_index];
+
+    let lot_price = mango_cache.get_price(market_index)
+        * I80F48::from_num(perp_market_info.base_lot_size)
+        / I80F48::from_num(perp_market_info.quote_lot_size);
+    let slippage =
+        I80F48::from_num(accounts.pool.wind_down_slippage_bps) / I80F48::from_num(10_000);
+    let (side, price) = if base_position > 0 {
+        let price: i64 = (lot_price * (ONE_I80F48 - slippage))
+            .checked_floor()
+            .unwrap()
+            .checked_to_num()
+            .unwrap();
+        (Side::Ask, price.max(1))
+    } else {
+        let price: i64 = (lot_price * (ONE_I80F48 + slippage))
+            .checked_ceil()
+            .unwrap()
+            .checked_to_num()
+            .unwrap();
+        (Side::Bid, price)
+    };
+
+    Ok(PerpUnwindOrder {
+        side,
+        price,
+        quantity: base_position.abs(),
+        open_orders_keys: mango_account.spot_open_orders,
+    })
+}
+
+struct SpotUnwindOrder {
+    market_index: usize,
+    base_lot_size: u64,
+    new_order: Option<NewOrderInstructionV3>,
+    open_orders_keys: [Pubkey; MAX_PAIRS],
+}
+
+/// Permissionless during wind down, sells the pool's deposit of a spot token (or buys back its borrow)
+/// with an IOC order at most `wind_down_slippage_bps` away from the oracle and settles it into the MangoAccount
+/// Less than a Serum lot can't be traded, the lot size is recorded so that dust doesn't block redemption
+pub fn unwind_spot<'a, 'b, 'c, 'info>(
+    ctx: Context<'a, 'b, 'c, 'info, UnwindSpot<'info>>,
+) -> ProgramResult {
+    require!(
+        ctx.accounts.pool.status == PoolStatus::WindDown,
+        BlenderError::NotWindingDown
+    );
+    let order = prepare_spot_unwind(&ctx)?;
+    ctx.accounts.pool.unwind_base_lot_sizes[order.market_index] = order.base_lot_size;
+
+    match order.new_order {
+        Some(new_order) => {
+            place_spot_unwind_order(&ctx, &order.open_orders_keys, order.market_index, new_order)?;
+            settle_spot_unwind(&ctx)
+        }
+        None => {
+            msg!("no spot position to unwind");
+            Ok(())
+        }
+    }
+}
+
+/// Sizes and prices the closing order: every whole lot of the deposit on the ask, or enough lots
+/// to repay the borrow on the bid, at the oracle price moved against the pool by the wind down slippage
+#[inline(never)]
+fn prepare_spot_unwind<'a, 'b, 'c, 'info>(
+    ctx: &Context<'a, 'b, 'c, 'info, UnwindSpot<'info>>,
+) -> Result<SpotUnwindOrder, ProgramError> {
+    let accounts = &ctx.accounts;
+    let mango_account = MangoAccount::load_checked(
+        &accounts.mango_account,
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+    )?;
+    let mango_group = MangoGroup::load_checked(&accounts.mango_group, accounts.mango_program.key)?;
+    let mango_cache = MangoCache::load_checked(
+        &accounts.mango_cache,
+        accounts.mango_program.key,
+        &mango_group,
+    )?;
+    check_dex_program(&mango_group, &accounts.spot.dex_program)?;
+
+    let market_index = mango_group
+        .find_spot_market_index(accounts.spot.spot_market.key)
+        .ok_or(BlenderError::UnknownMarket)?;
+    require!(
+        mango_account.spot_open_orders[market_index] == accounts.spot.spot_open_orders.key(),
+        BlenderError::InvalidOpenOrders
+    );
+    let active_assets = UserActiveAssets::new(
+        &mango_group,
+        &mango_account,
+        vec![
+            (AssetType::Token, QUOTE_INDEX),
+            (AssetType::Token, market_index),
+        ],
+    );
+    mango_cache.check_valid(
+        &mango_group,
+        &active_assets,
+        Clock::get()?.unix_timestamp as u64,
+    )?;
+
+    let (coin_lot_size, pc_lot_size) = {
+        let market = MarketState::load(
+            &accounts.spot.spot_market,
+            accounts.spot.dex_program.key,
+            false,
+        )?;
+        (market.coin_lot_size, market.pc_lot_size)
+    };
+    let base_net = get_mango_account_base_net(
+        &mango_account,
+        &mango_cache.root_bank_cache[market_index],
+        market_index,
+    );
+
+    // in serum lots, quote lots per base lot
+    let lot_price = mango_cache.get_price(market_index) * I80F48::from_num(coin_lot_size)
+        / I80F48::from_num(pc_lot_size);
+    let slippage =
+        I80F48::from_num(accounts.pool.wind_down_slippage_bps) / I80F48::from_num(10_000);
+    let new_order = if base_net >= I80F48::from_num(coin_lot_size) {
+        let lots: u64 = (base_net / I80F48::from_num(coin_lot_size))
+            .checked_floor()
+            .unwrap()
+            .checked_to_num()
+            .unwrap();
+        let price: u64 = (lot_price * (ONE_I80F48 - slippage))
+            .checked_floor()
+            .unwrap()
+            .checked_to_num()
+            .unwrap();
+        Some(NewOrderInstructionV3 {
+            side: SpotSide::Ask,
+            limit_price: NonZeroU64::new(price.max(1)).unwrap(),
+            max_coin_qty: NonZeroU64::new(lots).unwrap(),
+            max_native_pc_qty_including_fees: NonZeroU64::new(u64::MAX).unwrap(),
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            order_type: SpotOrderType::ImmediateOrCancel,
+            client_order_id: 0,
+            limit: 32,
+        })
+    } else if base_net < ZERO_I80F48 {
+        let lots: u64 = (-base_net / I80F48::from_num(coin_lot_size))
+            .checked_ceil()
+            .unwrap()
+            .checked_to_num()
+            .unwrap();
+        let price: u64 = (lot_price * (ONE_I80F48 + slippage))
+            .checked_ceil()
+            .unwrap()
+            .checked_to_num()
+            .unwrap();
+        // the QUOTE locked for the bid, with 1% on top for taker fees
+        let max_native_pc: u64 = (I80F48::from_num(lots)
+            * I80F48::from_num(price)
+            * I80F48::from_num(pc_lot_size)
+            * I80F48::from_num(101)
+            / I80F48::from_num(100))
+        .checked_ceil()
+        .unwrap()
+        .checked_to_num()
+        .unwrap();
+        Some(NewOrderInstructionV3 {
+            side: SpotSide::Bid,
+            limit_price: NonZeroU64::new(price.max(1)).unwrap(),
+            max_coin_qty: NonZeroU64::new(lots).unwrap(),
+            max_native_pc_qty_including_fees: NonZeroU64::new(max_native_pc.max(1)).unwrap(),
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            order_type: SpotOrderType::ImmediateOrCancel,
+            client_order_id: 0,
+            limit: 32,
+        })
+    } else {
+        None
+    };
+
+    Ok(SpotUnwindOrder {
+        market_index,
+        base_lot_size: coin_lot_size,
+        new_order,
+        open_orders_keys: mango_account.spot_open_orders,
+    })
+}
+
+/// Places the closing order through Mango, signed by the pool
+#[inline(never)]
+fn place_spot_unwind_order<'a, 'b, 'c, 'info>(
+    ctx: &Context<'a, 'b, 'c, 'info, UnwindSpot<'info>>,
+    open_orders_keys: &[Pubkey; MAX_PAIRS],
+    market_index: usize,
+    new_order: NewOrderInstructionV3,
+) -> ProgramResult {
+    let accounts = &ctx.accounts;
+    let spot = &accounts.spot;
+    let seeds = &[
+        &accounts.pool.pool_name.as_ref(),
+        accounts.pool.admin.as_ref(),
+        &[accounts.pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    let place_instruction = MangoInstructions::place_spot_order2(
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+        accounts.mango_account.key,
+        accounts.pool.to_account_info().key,
+        accounts.mango_cache.key,
+        spot.dex_program.key,
+        spot.spot_market.key,
+        spot.bids.key,
+        spot.asks.key,
+        spot.dex_request_queue.key,
+        spot.dex_event_queue.key,
+        spot.dex_base.key,
+        spot.dex_quote.key,
+        spot.base_root_bank.key,
+        spot.base_node_bank.key,
+        spot.base_vault.key,
+        accounts.quote_root_bank.key,
+        accounts.quote_node_bank.key,
+        accounts.quote_vault.key,
+        accounts.mango_group_signer.key,
+        spot.dex_signer.key,
+        spot.msrm_or_srm_vault.key,
+        open_orders_keys,
+        market_index,
+        new_order,
+    )
+    .unwrap();
+
+    let mut account_infos = vec![
+        accounts.mango_program.to_account_info(),
+        accounts.mango_group.to_account_info(),
+        accounts.mango_account.to_account_info(),
+        accounts.pool.to_account_info(),
+        accounts.mango_cache.to_account_info(),
+        spot.dex_program.to_account_info(),
+        spot.spot_market.to_account_info(),
+        spot.bids.to_account_info(),
+        spot.asks.to_account_info(),
+        spot.dex_request_queue.to_account_info(),
+        spot.dex_event_queue.to_account_info(),
+        spot.dex_base.to_account_info(),
+        spot.dex_quote.to_account_info(),
+        spot.base_root_bank.to_account_info(),
+        spot.base_node_bank.to_account_info(),
+        spot.base_vault.to_account_info(),
+        accounts.quote_root_bank.to_account_info(),
+        accounts.quote_node_bank.to_account_info(),
+        accounts.quote_vault.to_account_info(),
+        accounts.token_program.to_account_info(),
+        accounts.mango_group_signer.to_account_info(),
+        spot.dex_signer.to_account_info(),
+        spot.msrm_or_srm_vault.to_account_info(),
+        spot.spot_open_orders.to_account_info(),
+    ];
+    account_infos.extend(ctx.remaining_accounts.iter().cloned());
+    invoke_signed_unchecked(&place_instruction, &account_infos, cpi_seed)?;
+    Ok(())
+}
+
+/// Moves the fill from the open orders account back into the MangoAccount
+#[inline(never)]
+fn settle_spot_unwind<'a, 'b, 'c, 'info>(
+    ctx: &Context<'a, 'b, 'c, 'info, UnwindSpot<'info>>,
+) -> ProgramResult {
+    let accounts = &ctx.accounts;
+    let spot = &accounts.spot;
+    let seeds = &[
+        &accounts.pool.pool_name.as_ref(),
+        accounts.pool.admin.as_ref(),
+        &[accounts.pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    let settle_instruction = MangoInstructions::settle_funds(
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+        accounts.mango_cache.key,
+        accounts.pool.to_account_info().key,
+        accounts.mango_account.key,
+        spot.dex_program.key,
+        spot.spot_market.key,
+        spot.spot_open_orders.key,
+        accounts.mango_group_signer.key,
+        spot.dex_base.key,
+        spot.dex_quote.key,
+        spot.base_root_bank.key,
+        spot.base_node_bank.key,
+        accounts.quote_root_bank.key,
+        accounts.quote_node_bank.key,
+        spot.base_vault.key,
+        accounts.quote_vault.key,
+        spot.dex_signer.key,
+    )
+    .unwrap();
+
+    invoke_signed_unchecked(
+        &settle_instruction,
+        &[
+            accounts.mango_program.to_account_info(),
+            accounts.mango_group.to_account_info(),
+            accounts.mango_cache.to_account_info(),
+            accounts.pool.to_account_info(),
+            accounts.mango_account.to_account_info(),
+            spot.dex_program.to_account_info(),
+            spot.spot_market.to_account_info(),
+            spot.spot_open_orders.to_account_info(),
+            accounts.mango_group_signer.to_account_info(),
+            spot.dex_base.to_account_info(),
+            spot.dex_quote.to_account_info(),
+            spot.base_root_bank.to_account_info(),
+            spot.base_node_bank.to_account_info(),
+            accounts.quote_root_bank.to_account_info(),
+            accounts.quote_node_bank.to_account_info(),
+            spot.base_vault.to_account_info(),
+            accounts.quote_vault.to_account_info(),
+            spot.dex_signer.to_account_info(),
+            accounts.token_program.to_account_info(),
+        ],
+        cpi_seed,
+    )?;
+    Ok(())
+}
+
+/// Permissionless during wind down, cancels up to `limit` of the pool's resting orders on a Serum market
+pub fn cancel_all_orders(ctx: Context<CancelAllOrders>, limit: u8) -> ProgramResult {
+    require!(
+        ctx.accounts.pool.status == PoolStatus::WindDown,
+        BlenderError::NotWindingDown
+    );
+
+    {
+        let mango_group =
+            MangoGroup::load_checked(&ctx.accounts.mango_group, ctx.accounts.mango_program.key)?;
+        check_dex_program(&mango_group, &ctx.accounts.dex_program)?;
+    }
+
+    // collect the orders first, the open orders account is written to by the cancels
+    let orders: Vec<(SpotSide, u128)> = {
+        let open_orders = load_open_orders(&ctx.accounts.spot_open_orders)?;
+        let free_slot_bits = open_orders.free_slot_bits;
+        let is_bid_bits = open_orders.is_bid_bits;
+        let order_ids = open_orders.orders;
+        (0..order_ids.len())
+            .filter(|slot| free_slot_bits & (1u128 << slot) == 0)
+            .map(|slot| {
+                let side = if is_bid_bits & (1u128 << slot) != 0 {
+                    SpotSide::Bid
+                } else {
+                    SpotSide::Ask
+                };
+                (side, order_ids[slot])
+            })
+            .take(limit as usize)
+            .collect()
+    };
+
+    let accounts = &ctx.accounts;
+    let seeds = &[
+        &accounts.pool.pool_name.as_ref(),
+        accounts.pool.admin.as_ref(),
+        &[accounts.pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    for (side, order_id) in orders {
+        let cancel_instruction = MangoInstructions::cancel_spot_order(
+            accounts.mango_program.key,
+            accounts.mango_group.key,
+            accounts.pool.to_account_info().key,
+            accounts.mango_account.key,
+            accounts.dex_program.key,
+            accounts.spot_market.key,
+            accounts.bids.key,
+            accounts.asks.key,
+            accounts.spot_open_orders.key,
+            accounts.mango_group_signer.key,
+            accounts.dex_event_queue.key,
+            CancelOrderInstructionV2 { side, order_id },
+        )
+        .unwrap();
+
+        invoke_signed_unchecked(
+            &cancel_instruction,
+            &[
+                accounts.mango_program.to_account_info(),
+                accounts.mango_group.to_account_info(),
+                accounts.pool.to_account_info(),
+                accounts.mango_account.to_account_info(),
+                accounts.dex_program.to_account_info(),
+                accounts.spot_market.to_account_info(),
+                accounts.bids.to_account_info(),
+                accounts.asks.to_account_info(),
+                accounts.spot_open_orders.to_account_info(),
+                accounts.mango_group_signer.to_account_info(),
+                accounts.dex_event_queue.to_account_info(),
+            ],
+            cpi_seed,
+        )?;
+    }
+    Ok(())
+}
+
+/// Once a wound down pool is flat, holders burn IOUs for their pro-rata share of the pool in QUOTE
+/// (of their tranche, senior holders are paid their claim first)
+/// No deposit lock or withdrawal window, everyone in a tranche gets the same price
+pub fn redeem<'a, 'b, 'c, 'info>(
+    ctx: Context<'a, 'b, 'c, 'info, WithdrawFromPool<'info>>,
+    iou_amount: u64,
+) -> ProgramResult {
+    require!(
+        ctx.accounts.withdrawer.is_signer,
+        BlenderError::WithdrawerNotSigner
+    );
+    require!(
+        ctx.accounts.pool.status == PoolStatus::WindDown,
+        BlenderError::NotWindingDown
+    );
+    let (quantity, tranche_value, open_orders_keys) = value_redemption(&ctx, iou_amount)?;
+    check!(quantity > 0, MangoErrorCode::Default)?;
+
+    let tranche = ctx
+        .accounts
+        .pool
+        .tranche_of(&ctx.accounts.pool_iou_mint.key())
+        .unwrap();
+    if tranche == Tranche::Senior {
+        let supply = ctx.accounts.pool_iou_mint.supply;
+        let pool = &mut ctx.accounts.pool;
+        pool.accrue_senior_claim(Clock::get()?.unix_timestamp);
+        pool.remove_senior_claim(iou_amount, supply);
+    } else {
+        settle_burned_distribution(ctx.accounts, iou_amount)?;
+    }
+
+    let seeds = &[
+        &ctx.accounts.pool.pool_name.as_ref(),
+        ctx.accounts.pool.admin.as_ref(),
+        &[ctx.accounts.pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    // no deposit lock while winding down
+    thaw_iou_account(
+        &ctx.accounts.pool,
+        &ctx.accounts.pool_iou_mint.to_account_info(),
+        &ctx.accounts.withdrawer_iou_token_account,
+        &ctx.accounts.token_program.to_account_info(),
+    )?;
+    let burn_accounts = Burn {
+        to: ctx.accounts.withdrawer_iou_token_account.to_account_info(),
+        mint: ctx.accounts.pool_iou_mint.to_account_info(),
+        authority: ctx.accounts.withdrawer.to_account_info(),
+    };
+    let token_program_ai = ctx.accounts.token_program.to_account_info();
+    let iou_burn_ctx = CpiContext::new_with_signer(token_program_ai, burn_accounts, cpi_seed);
+    token::burn(iou_burn_ctx, iou_amount)?;
+
+    emit!(Withdraw {
+        pool: ctx.accounts.pool.key(),
+        withdrawer: ctx.accounts.withdrawer.key(),
+        quantity,
+        ious_burned: iou_amount,
+        nav: tranche_value,
+        iou_supply_before: ctx.accounts.pool_iou_mint.supply,
+        iou_mint: ctx.accounts.pool_iou_mint.key(),
+    });
+    withdraw_from_mango(ctx, &open_orders_keys, quantity)
+}
+
+/// QUOTE owed for `iou_amount` of the tranche being redeemed, rounded down in favour of the pool
+#[inline(never)]
+fn value_redemption<'a, 'b, 'c, 'info>(
+    ctx: &Context<'a, 'b, 'c, 'info, WithdrawFromPool<'info>>,
+    iou_amount: u64,
+) -> Result<(u64, u64, [Pubkey; MAX_PAIRS]), ProgramError> {
+    let accounts = &ctx.accounts;
+    let mango_account = MangoAccount::load_checked(
+        &accounts.mango_account,
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+    )?;
+    let mango_group = MangoGroup::load_checked(&accounts.mango_group, accounts.mango_program.key)?;
+    let mango_cache = MangoCache::load_checked(
+        &accounts.mango_cache,
+        accounts.mango_program.key,
+        &mango_group,
+    )?;
+
+    //check that user is redeeming for QUOTE
+    check!(
+        mango_group.tokens[QUOTE_INDEX].mint == accounts.withdrawer_token_account.mint,
+        MangoErrorCode::InvalidToken
+    )?;
+    check!(
+        iou_amount <= accounts.withdrawer_iou_token_account.amount,
+        MangoErrorCode::InsufficientFunds
+    )?;
+
+    let active_assets = UserActiveAssets::new(
+        &mango_group,
+        &mango_account,
+        pool_valuation_assets(&accounts.pool, vec![]),
+    );
+    let clock = Clock::get()?;
+    mango_cache.check_valid(&mango_group, &active_assets, clock.unix_timestamp as u64)?;
+
+    let open_orders_ais =
+        mango_account.checked_unpack_open_orders(&mango_group, &ctx.remaining_accounts)?;
+    check_mango_account_flat(
+        &accounts.pool,
+        &mango_account,
+        &mango_cache,
+        &open_orders_ais,
+    )?;
+
+    // paid out of the QUOTE deposit, spot dust and perp pnl nobody has settled yet can't be paid
+    let quote_deposit = get_mango_account_base_net(
+        &mango_account,
+        &mango_cache.root_bank_cache[QUOTE_INDEX],
+        QUOTE_INDEX,
+    );
+    let pool_value_quote = calculate_pool_value(
+        &mango_account,
+        &mango_cache,
+        &mango_group,
+        open_orders_ais,
+        &active_assets,
+        accounts.pool.mngo_index(),
+    )
+    .min(quote_deposit);
+    let tranche = accounts
+        .pool
+        .tranche_of(&accounts.pool_iou_mint.key())
+        .unwrap();
+    let tranche_value_quote =
+        accounts
+            .pool
+            .tranche_value(tranche, pool_value_quote, clock.unix_timestamp);
+    let outstanding_iou_tokens = I80F48::from_num(accounts.pool_iou_mint.supply);
+    let quantity: u64 = (tranche_value_quote * I80F48::from_num(iou_amount)
+        / outstanding_iou_tokens)
+        .checked_floor()
+        .unwrap()
+        .checked_to_num()
+        .unwrap();
+    Ok((
+        quantity,
+        quote_to_native(tranche_value_quote),
+        mango_account.spot_open_orders,
+    ))
+}
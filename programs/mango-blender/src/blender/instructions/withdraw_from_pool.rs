@@ -1,22 +1,31 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Burn, Mint, Token, TokenAccount};
+use anchor_spl::token::{self, Approve, Burn, Mint, Token, TokenAccount};
 use fixed::types::I80F48;
 use mango::declare_check_assert_macros;
 use mango::error::{check_assert, MangoErrorCode, SourceFileId};
 use mango::instruction as MangoInstructions;
 use mango::state::{AssetType, MangoAccount, MangoCache, MangoGroup, UserActiveAssets, MAX_PAIRS, QUOTE_INDEX};
-use solana_program::program::invoke_signed_unchecked;
+use solana_program::program::{invoke, invoke_signed_unchecked};
+use solana_program::program_option::COption;
+use std::cmp::min;
 
-use crate::blender::state::Pool;
+use crate::blender::errors::BlenderError;
+use crate::blender::events::{Withdraw, WithdrawalQueued};
+use crate::blender::state::{DepositorPosition, NavSnapshot, Pool, PoolStatus, Tranche};
 use crate::helpers::*;
 
 declare_check_assert_macros!(SourceFileId::Processor);
 
 #[derive(Accounts)]
 pub struct WithdrawFromPool<'info> {
-    ///CHECK: checked in mango program
+    #[account(address = mango::ID)]
+    ///CHECK: address is checked
     pub mango_program: UncheckedAccount<'info>,
-    #[account(mut, seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()], bump)]
+    #[account(
+        mut,
+        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref()],
+        bump,
+        has_one = mango_account)]
     pub pool: Box<Account<'info, Pool>>,
     #[account(mut)]
     ///CHECK: checked in mango program
@@ -24,10 +33,10 @@ pub struct WithdrawFromPool<'info> {
     ///CHECK: checked in mango program
     pub mango_group_signer: UncheckedAccount<'info>,
     #[account(mut)]
-    ///CHECK: checked in mango program
+    ///CHECK: checked against the pool
     pub mango_account: AccountInfo<'info>,
-    #[account(signer)]
-    pub withdrawer: AccountInfo<'info>,
+    ///CHECK: signs everything but a keeper paying out a queued request (the pool burns as delegate then)
+    pub withdrawer: UncheckedAccount<'info>,
     ///CHECK: checked in mango program
     pub mango_cache: UncheckedAccount<'info>,
     ///CHECK: checked in mango program
@@ -40,10 +49,10 @@ pub struct WithdrawFromPool<'info> {
     pub vault: UncheckedAccount<'info>,
     #[account(mut, constraint = withdrawer_token_account.owner == withdrawer.key())]
     pub withdrawer_token_account: Box<Account<'info, TokenAccount>>,
+    // the IOU mint of the tranche being withdrawn from
     #[account(
         mut,
-        seeds = [pool.pool_name.as_ref(), pool.admin.as_ref(), b"iou"],
-        bump = pool.iou_mint_bump,
+        constraint = pool.tranche_of(&pool_iou_mint.key()).is_some() @ BlenderError::InvalidTrancheMint,
     )]
     pub pool_iou_mint: Box<Account<'info, Mint>>,
 
@@ -52,14 +61,64 @@ pub struct WithdrawFromPool<'info> {
         associated_token::mint = pool_iou_mint
     )]
     pub withdrawer_iou_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [pool.key().as_ref(), withdrawer.key.as_ref(), b"position"],
+        bump = withdrawer_position.bump,
+        has_one = pool,
+        constraint = withdrawer_position.owner == withdrawer.key()
+    )]
+    pub withdrawer_position: Box<Account<'info, DepositorPosition>>,
+    #[account(
+        seeds = [pool.key().as_ref(), b"nav"],
+        bump = nav_snapshot.bump,
+        has_one = pool)]
+    pub nav_snapshot: Box<Account<'info, NavSnapshot>>,
+    #[account(mut, seeds = [pool.key().as_ref(), b"price"], bump = pool.price_feed_bump)]
+    ///CHECK: pool's price account, layout checked when written
+    pub price_feed: UncheckedAccount<'info>,
     pub token_program: Program<'info, Token>,
 }
 
 /// A user can withdraw whatever token that they want from the pool, up to whatever % of the pool they own (as dictated by their iou tokens)
+/// In a pool with a senior tranche that is a % of the tranche the IOU mint passed belongs to
+/// Whatever doesn't fit in the withdrawal window is queued and paid out by `process_queued_withdrawal` in the next one
 pub fn handler<'a, 'b, 'c, 'info>(
     ctx: Context<'a, 'b, 'c, 'info, WithdrawFromPool<'info>>,
     quantity: u64,
 ) -> ProgramResult {
+    require!(
+        ctx.accounts.withdrawer.is_signer,
+        BlenderError::WithdrawerNotSigner
+    );
+    process_withdrawal(ctx, Some(quantity))
+}
+
+/// Pays out the request queued on a position in the previous withdrawal window, out of the capacity reserved for it
+/// Permissionless (keeper crank), the pool burns the IOUs as the delegate approved when the request was queued
+/// An expired request (or one left over after the limit was turned off) is dropped instead
+pub fn process_queued_withdrawal<'a, 'b, 'c, 'info>(
+    ctx: Context<'a, 'b, 'c, 'info, WithdrawFromPool<'info>>,
+) -> ProgramResult {
+    require!(
+        ctx.accounts.withdrawer_position.queued_withdrawal > 0,
+        BlenderError::NothingQueued
+    );
+    process_withdrawal(ctx, None)
+}
+
+/// `quantity` is None when a keeper pays out the position's queued request
+fn process_withdrawal<'a, 'b, 'c, 'info>(
+    ctx: Context<'a, 'b, 'c, 'info, WithdrawFromPool<'info>>,
+    quantity: Option<u64>,
+) -> ProgramResult {
+    let from_queue = quantity.is_none();
+    // a wound down pool pays everyone the same price through redeem
+    require!(
+        ctx.accounts.pool.status != PoolStatus::WindDown,
+        BlenderError::WindingDown
+    );
+
     // load mango account, group, cache
     let mango_account_ai = ctx.accounts.mango_account.to_account_info();
     let mango_group_ai = ctx.accounts.mango_group.to_account_info();
@@ -88,45 +147,104 @@ pub fn handler<'a, 'b, 'c, 'info>(
     let active_assets = UserActiveAssets::new(
         &mango_group,
         &mango_account,
-        vec![(AssetType::Token, QUOTE_INDEX)],
+        pool_valuation_assets(&ctx.accounts.pool, vec![(AssetType::Token, QUOTE_INDEX)]),
     );
     let clock = Clock::get()?;
     let now_ts = clock.unix_timestamp as u64;
     mango_cache.check_valid(&mango_group, &active_assets, now_ts)?;
 
+    // no withdrawing in the same slot (or within the lock window) as a deposit
+    require!(
+        !ctx.accounts
+            .withdrawer_position
+            .is_deposit_locked(clock.slot),
+        BlenderError::WithdrawLocked
+    );
+
     //load open orders
     let open_orders_ais =
         mango_account.checked_unpack_open_orders(&mango_group, &ctx.remaining_accounts)?;
 
-    // prepare iou burn
-    let seeds = &[
-        &ctx.accounts.pool.pool_name.as_ref(),
-        ctx.accounts.pool.admin.as_ref(),
-        &[ctx.accounts.pool.pool_bump],
-    ];
-    let cpi_seed = &[&seeds[..]];
-
-    let burn_accounts = Burn {
-        to: ctx.accounts.withdrawer_iou_token_account.to_account_info(),
-        mint: ctx.accounts.pool_iou_mint.to_account_info(),
-        authority: ctx.accounts.withdrawer.to_account_info(),
-    };
-    let token_program_ai = ctx.accounts.token_program.to_account_info();
-    let iou_burn_ctx = CpiContext::new_with_signer(token_program_ai, burn_accounts, cpi_seed);
-
-    // get values and burn amount
-    let outstanding_iou_tokens = I80F48::from_num(ctx.accounts.pool_iou_mint.supply);
-    let withdraw_value_quote = I80F48::from_num(quantity);
-    let pool_value_quote = calculate_pool_value(
+    // no paying out withdrawals while the pooled account is in (or close to) liquidation,
+    // the pool can queue them for when it is healthy again
+    let healthy = is_mango_account_healthy(
         &mango_account,
         &mango_cache,
         &mango_group,
-        open_orders_ais,
+        &open_orders_ais,
         &active_assets,
+    )?;
+    require!(
+        healthy || (!from_queue && ctx.accounts.pool.queue_unhealthy_withdrawals),
+        BlenderError::MangoAccountUnhealthy
+    );
+
+    // get values and burn amount
+    let tranche = ctx
+        .accounts
+        .pool
+        .tranche_of(&ctx.accounts.pool_iou_mint.key())
+        .unwrap();
+    let outstanding_iou_tokens = I80F48::from_num(ctx.accounts.pool_iou_mint.supply);
+    // a recent enough snapshot saves valuing the MangoAccount again
+    let cached_pool_value = ctx.accounts.nav_snapshot.cached_pool_value(
+        &ctx.accounts.pool,
+        ctx.accounts.pool_iou_mint.supply,
+        clock.slot,
     );
+    let pool_value_quote = match cached_pool_value {
+        Some(pool_value_quote) => pool_value_quote,
+        None => calculate_pool_value(
+            &mango_account,
+            &mango_cache,
+            &mango_group,
+            open_orders_ais,
+            &active_assets,
+            ctx.accounts.pool.mngo_index(),
+        ),
+    };
+    ctx.accounts.pool.accrue_senior_claim(clock.unix_timestamp);
+    let tranche_value_quote =
+        ctx.accounts
+            .pool
+            .tranche_value(tranche, pool_value_quote, clock.unix_timestamp);
+
+    // keep the circuit breaker's high-water mark and the price account (both per junior IOU) up to date
+    if tranche == Tranche::Junior && outstanding_iou_tokens > 0 {
+        let nav_per_iou = tranche_value_quote / outstanding_iou_tokens;
+        ctx.accounts.pool.record_nav_per_iou(nav_per_iou);
+        publish_nav_price(
+            &ctx.accounts.price_feed,
+            &ctx.accounts.pool,
+            nav_per_iou,
+            mango_group.tokens[QUOTE_INDEX].decimals,
+        )?;
+    }
+
+    let requested = match quantity {
+        Some(quantity) => quantity,
+        None => {
+            let accounts = &mut *ctx.accounts;
+            let claimable = accounts.pool.claimable_queued_withdrawal(
+                &mut accounts.withdrawer_position,
+                quote_to_native(pool_value_quote),
+                clock.unix_timestamp,
+            );
+            if accounts.withdrawer_position.queued_withdrawal == 0 {
+                msg!("queued withdrawal expired (or the limit is off), dropped");
+                return Ok(());
+            }
+            // a keeper can only pay out a queued request once the next window has room for it
+            require!(claimable > 0, BlenderError::WithdrawalLimitExceeded);
+            min(
+                claimable,
+                approved_burn_value(accounts, tranche_value_quote, outstanding_iou_tokens)?,
+            )
+        }
+    };
     let burn_amount = calculate_iou_burn_amount(
-        withdraw_value_quote,
-        pool_value_quote,
+        I80F48::from_num(requested),
+        tranche_value_quote,
         outstanding_iou_tokens,
     );
 
@@ -137,33 +255,182 @@ pub fn handler<'a, 'b, 'c, 'info>(
         MangoErrorCode::InsufficientFunds
     )?;
 
+    // cap the withdrawal to what is left in the daily window, the rest is queued for the next one
+    let accounts = &mut *ctx.accounts;
+    let quantity = if healthy {
+        accounts.pool.apply_withdrawal_gate(
+            &mut accounts.withdrawer_position,
+            requested,
+            quote_to_native(pool_value_quote),
+            clock.unix_timestamp,
+        )
+    } else {
+        accounts.pool.queue_withdrawal(
+            &mut accounts.withdrawer_position,
+            requested,
+            quote_to_native(pool_value_quote),
+            clock.unix_timestamp,
+        );
+        0
+    };
+    let queued = quantity < requested;
+    if queued {
+        emit!(WithdrawalQueued {
+            pool: accounts.pool.key(),
+            withdrawer: accounts.withdrawer.key(),
+            quantity: accounts.withdrawer_position.queued_withdrawal,
+            window_id: accounts.pool.withdrawal_window_id,
+        });
+    }
+    require!(
+        !from_queue || quantity > 0,
+        BlenderError::WithdrawalLimitExceeded
+    );
+
+    // past the deposit lock, IOUs frozen by the last deposit can be burned (or approved)
+    thaw_iou_account(
+        &ctx.accounts.pool,
+        &ctx.accounts.pool_iou_mint.to_account_info(),
+        &ctx.accounts.withdrawer_iou_token_account,
+        &ctx.accounts.token_program.to_account_info(),
+    )?;
+    if !from_queue {
+        update_queued_burn_approval(ctx.accounts, tranche_value_quote, outstanding_iou_tokens)?;
+    }
+    if quantity == 0 {
+        msg!("request queued for the next withdrawal window");
+        return Ok(());
+    }
+    let accounts = &mut *ctx.accounts;
+    let burn_amount = calculate_iou_burn_amount(
+        I80F48::from_num(quantity),
+        tranche_value_quote,
+        outstanding_iou_tokens,
+    );
+    if tranche == Tranche::Senior {
+        accounts
+            .pool
+            .remove_senior_claim(burn_amount, accounts.pool_iou_mint.supply);
+    } else {
+        settle_burned_distribution(accounts, burn_amount)?;
+    }
+
+    // prepare iou burn
+    let seeds = &[
+        &ctx.accounts.pool.pool_name.as_ref(),
+        ctx.accounts.pool.admin.as_ref(),
+        &[ctx.accounts.pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    // a queued request is paid out without the withdrawer, the pool burns as their delegate
+    let burn_authority = if from_queue {
+        ctx.accounts.pool.to_account_info()
+    } else {
+        ctx.accounts.withdrawer.to_account_info()
+    };
+    let burn_accounts = Burn {
+        to: ctx.accounts.withdrawer_iou_token_account.to_account_info(),
+        mint: ctx.accounts.pool_iou_mint.to_account_info(),
+        authority: burn_authority,
+    };
+    let token_program_ai = ctx.accounts.token_program.to_account_info();
+    let iou_burn_ctx = CpiContext::new_with_signer(token_program_ai, burn_accounts, cpi_seed);
+
     // FUTURE: add "withdrawal fee", either transfer some iou or QUOTE tokens to the mango manager
 
     token::burn(iou_burn_ctx, burn_amount)?;
 
+    emit!(Withdraw {
+        pool: ctx.accounts.pool.key(),
+        withdrawer: ctx.accounts.withdrawer.key(),
+        quantity,
+        ious_burned: burn_amount,
+        nav: quote_to_native(tranche_value_quote),
+        iou_supply_before: ctx.accounts.pool_iou_mint.supply,
+        iou_mint: ctx.accounts.pool_iou_mint.key(),
+    });
     withdraw_from_mango(ctx, &mango_account.spot_open_orders, quantity)
 }
 
-/// Calculate how many iou tokens should be burned for a withdrawak
-/// We want to ensure that a withdrawer can only withdraw what they are entitled to and that they burn the correct amount of iou tokens
-/// e.g. If the pool is worth $100 and I own 10% of all minted iou tokens, I should be entitled to withdraw $10 worth of quote (aka 10% of the pool)
-///
-/// To achieve this: (withdraw value / starting pool value) = (my burnable iou tokens / outstanding iou tokens)
-fn calculate_iou_burn_amount(
-    withdraw_value_quote: I80F48,
-    pool_value_quote: I80F48,
+/// Lets the pool burn just the IOUs the withdrawer's queued request needs at the current price, revoking once
+/// nothing is queued. The pool can't revoke by itself, an allowance left after a keeper paid the queue
+/// goes on the holder's next withdrawal (and can't be used without a queue in the meantime)
+pub(crate) fn update_queued_burn_approval(
+    accounts: &WithdrawFromPool,
+    tranche_value_quote: I80F48,
     outstanding_iou_tokens: I80F48,
-) -> u64 {
-    let burn_amount: u64 = ((withdraw_value_quote / pool_value_quote) * outstanding_iou_tokens)
-        .checked_ceil()
-        .unwrap()
-        .checked_to_num()
-        .unwrap();
-    burn_amount
+) -> ProgramResult {
+    let queued_withdrawal = accounts.withdrawer_position.queued_withdrawal;
+    let iou_account = &accounts.withdrawer_iou_token_account;
+    if queued_withdrawal > 0 {
+        let approve_accounts = Approve {
+            to: iou_account.to_account_info(),
+            delegate: accounts.pool.to_account_info(),
+            authority: accounts.withdrawer.to_account_info(),
+        };
+        token::approve(
+            CpiContext::new(accounts.token_program.to_account_info(), approve_accounts),
+            calculate_iou_burn_amount(
+                I80F48::from_num(queued_withdrawal),
+                tranche_value_quote,
+                outstanding_iou_tokens,
+            ),
+        )
+    } else if iou_account.delegate == COption::Some(accounts.pool.key()) {
+        let revoke_instruction = spl_token::instruction::revoke(
+            &spl_token::ID,
+            &iou_account.key(),
+            accounts.withdrawer.key,
+            &[],
+        )?;
+        invoke(
+            &revoke_instruction,
+            &[
+                iou_account.to_account_info(),
+                accounts.withdrawer.to_account_info(),
+                accounts.token_program.to_account_info(),
+            ],
+        )
+    } else {
+        Ok(())
+    }
+}
+
+/// QUOTE value of the IOUs the withdrawer approved the pool to burn, what a keeper can pay out of their queue
+fn approved_burn_value(
+    accounts: &WithdrawFromPool,
+    tranche_value_quote: I80F48,
+    outstanding_iou_tokens: I80F48,
+) -> Result<u64, ProgramError> {
+    let iou_account = &accounts.withdrawer_iou_token_account;
+    let approved = if iou_account.delegate == COption::Some(accounts.pool.key()) {
+        iou_account.delegated_amount
+    } else {
+        0
+    };
+    require!(approved > 0, BlenderError::QueueNotApproved);
+    let approved_value = I80F48::from_num(approved) * tranche_value_quote / outstanding_iou_tokens;
+    Ok(approved_value.floor().to_num())
+}
+
+/// Settles distributions owed to the withdrawer's junior IOUs before `burn_amount` of them are burned,
+/// burned IOUs come off the balance distributions are paid on
+pub(crate) fn settle_burned_distribution(
+    accounts: &mut WithdrawFromPool,
+    burn_amount: u64,
+) -> ProgramResult {
+    let iou_balance = accounts.withdrawer_iou_token_account.amount;
+    let position = &mut accounts.withdrawer_position;
+    position.settle_distribution(&mut accounts.pool, iou_balance)?;
+    let distribution_balance = position
+        .distribution_balance
+        .min(iou_balance.saturating_sub(burn_amount));
+    position.record_distribution_balance(&mut accounts.pool, distribution_balance)
 }
 
 #[inline(never)]
-fn withdraw_from_mango<'a, 'b, 'c, 'info>(
+pub(crate) fn withdraw_from_mango<'a, 'b, 'c, 'info>(
     ctx: Context<'a, 'b, 'c, 'info, WithdrawFromPool<'info>>,
     open_orders_keys: &[Pubkey; MAX_PAIRS],
     quantity: u64,
@@ -175,7 +442,7 @@ fn withdraw_from_mango<'a, 'b, 'c, 'info>(
     ];
     let cpi_seed = &[&seeds[..]];
 
-    // handle withdraw (Mango will prevent if the account is too leveraged -- no borrows allowed)
+    // handle withdraw (Mango will prevent if the account is too leveraged -- borrows only if the pool allows them and is active)
     let withdraw_instruction = MangoInstructions::withdraw(
         ctx.accounts.mango_program.key,
         ctx.accounts.mango_group.key,
@@ -189,7 +456,8 @@ fn withdraw_from_mango<'a, 'b, 'c, 'info>(
         ctx.accounts.mango_group_signer.key,
         open_orders_keys,
         quantity,
-        false,
+        ctx.accounts.pool.status == PoolStatus::Active
+            && ctx.accounts.pool.max_withdraw_leverage_bps > 0,
     )
     .unwrap();
 
@@ -228,5 +496,21 @@ fn withdraw_from_mango<'a, 'b, 'c, 'info>(
         cpi_seed,
     )?;
 
+    // Mango only makes sure health stays >= 0, the pool can ask for more headroom and cap borrowing
+    let health_floor_bps = ctx.accounts.pool.withdraw_health_floor_bps;
+    let max_leverage_bps = ctx.accounts.pool.max_withdraw_leverage_bps;
+    if health_floor_bps > 0 || max_leverage_bps > 0 {
+        check_post_withdraw_health(
+            ctx.accounts.mango_program.key,
+            &ctx.accounts.mango_account,
+            &ctx.accounts.mango_group.to_account_info(),
+            &ctx.accounts.mango_cache.to_account_info(),
+            ctx.remaining_accounts,
+            health_floor_bps,
+            max_leverage_bps,
+            ctx.accounts.pool.mngo_index(),
+        )?;
+    }
+
     Ok(())
 }
@@ -0,0 +1,529 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Burn};
+use fixed::types::I80F48;
+use mango::declare_check_assert_macros;
+use mango::error::{check_assert, MangoErrorCode, SourceFileId};
+use mango::instruction as MangoInstructions;
+use mango::state::{
+    AssetType, MangoAccount, MangoCache, MangoGroup, UserActiveAssets, MAX_PAIRS, QUOTE_INDEX,
+    ZERO_I80F48,
+};
+use serum_dex::instruction::{NewOrderInstructionV3, SelfTradeBehavior};
+use serum_dex::matching::{OrderType, Side};
+use serum_dex::state::MarketState;
+use solana_program::program::invoke_signed_unchecked;
+use std::num::NonZeroU64;
+
+use crate::blender::errors::BlenderError;
+use crate::blender::events::Withdraw;
+use crate::blender::instructions::withdraw_from_pool::{
+    settle_burned_distribution, update_queued_burn_approval, withdraw_from_mango, WithdrawFromPool,
+};
+use crate::blender::state::{PoolStatus, Tranche};
+use crate::helpers::*;
+
+declare_check_assert_macros!(SourceFileId::Processor);
+
+/// Serum market accounts needed to trade one spot token through the pooled MangoAccount
+#[derive(Accounts)]
+pub struct SpotMarketAccounts<'info> {
+    ///CHECK: checked in mango program
+    pub dex_program: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub spot_market: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub bids: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub asks: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub dex_request_queue: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub dex_event_queue: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub dex_base: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub dex_quote: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub dex_signer: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub base_root_bank: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub base_node_bank: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked in mango program
+    pub base_vault: UncheckedAccount<'info>,
+    #[account(mut)]
+    ///CHECK: checked against the MangoAccount
+    pub spot_open_orders: UncheckedAccount<'info>,
+    ///CHECK: checked in mango program
+    pub msrm_or_srm_vault: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawWithUnwind<'info> {
+    pub withdraw: WithdrawFromPool<'info>,
+    pub unwind: SpotMarketAccounts<'info>,
+}
+
+/// IOC sell order sized to cover a withdrawal
+struct UnwindOrder {
+    market_index: usize,
+    pool_value_quote: I80F48,
+    max_coin_qty: u64,
+    limit_price: u64,
+}
+
+/// Same as withdraw_from_pool, but first sells enough of one spot token (IOC through Serum, signed by the pool)
+/// to cover the QUOTE being withdrawn when the pool's QUOTE deposit is too small
+///
+/// The withdrawer bears the cost of the sale: IOUs are burned for the withdrawal plus whatever value
+/// the pool lost between the oracle price and the fill (never more than `max_slippage_bps` below oracle)
+pub fn handler<'a, 'b, 'c, 'info>(
+    ctx: Context<'a, 'b, 'c, 'info, WithdrawWithUnwind<'info>>,
+    quantity: u64,
+    max_slippage_bps: u16,
+) -> ProgramResult {
+    require!(
+        ctx.accounts.withdraw.withdrawer.is_signer,
+        BlenderError::WithdrawerNotSigner
+    );
+    require!(
+        ctx.accounts.withdraw.pool.status != PoolStatus::WindDown,
+        BlenderError::WindingDown
+    );
+    require!(max_slippage_bps < 10_000, BlenderError::InvalidSlippage);
+
+    let order = prepare_unwind(&ctx, quantity, max_slippage_bps)?;
+    if order.max_coin_qty > 0 {
+        place_unwind_order(&ctx, &order)?;
+        settle_unwind(&ctx)?;
+    }
+
+    // the withdrawer pays for the value lost in the sale, in IOUs of their tranche
+    let (pool_value_after, open_orders_keys) = value_pool(&ctx)?;
+    let slippage_quote = (order.pool_value_quote - pool_value_after).max(ZERO_I80F48);
+    let now_ts = Clock::get()?.unix_timestamp;
+    let accounts = &mut ctx.accounts.withdraw;
+    let tranche = accounts
+        .pool
+        .tranche_of(&accounts.pool_iou_mint.key())
+        .unwrap();
+    accounts.pool.accrue_senior_claim(now_ts);
+    let tranche_value_quote = accounts
+        .pool
+        .tranche_value(tranche, order.pool_value_quote, now_ts);
+    let outstanding_iou_tokens = I80F48::from_num(accounts.pool_iou_mint.supply);
+    let burn_amount = calculate_iou_burn_amount(
+        I80F48::from_num(quantity) + slippage_quote,
+        tranche_value_quote,
+        outstanding_iou_tokens,
+    );
+    check!(burn_amount > 0, MangoErrorCode::Default)?;
+    check!(
+        burn_amount <= ctx.accounts.withdraw.withdrawer_iou_token_account.amount,
+        MangoErrorCode::InsufficientFunds
+    )?;
+
+    // an unwind can't be queued, it has to fit in the current withdrawal window
+    let accounts = &mut ctx.accounts.withdraw;
+    let allowed = accounts.pool.apply_withdrawal_gate(
+        &mut accounts.withdrawer_position,
+        quantity,
+        quote_to_native(order.pool_value_quote),
+        now_ts,
+    );
+    require!(allowed == quantity, BlenderError::WithdrawalLimitExceeded);
+    if tranche == Tranche::Senior {
+        accounts
+            .pool
+            .remove_senior_claim(burn_amount, accounts.pool_iou_mint.supply);
+    } else {
+        settle_burned_distribution(accounts, burn_amount)?;
+    }
+
+    // past the deposit lock, IOUs frozen by the last deposit can be burned
+    let withdraw = &ctx.accounts.withdraw;
+    thaw_iou_account(
+        &withdraw.pool,
+        &withdraw.pool_iou_mint.to_account_info(),
+        &withdraw.withdrawer_iou_token_account,
+        &withdraw.token_program.to_account_info(),
+    )?;
+    update_queued_burn_approval(withdraw, tranche_value_quote, outstanding_iou_tokens)?;
+
+    let burn_accounts = Burn {
+        to: ctx
+            .accounts
+            .withdraw
+            .withdrawer_iou_token_account
+            .to_account_info(),
+        mint: ctx.accounts.withdraw.pool_iou_mint.to_account_info(),
+        authority: ctx.accounts.withdraw.withdrawer.to_account_info(),
+    };
+    let token_program_ai = ctx.accounts.withdraw.token_program.to_account_info();
+    token::burn(
+        CpiContext::new(token_program_ai, burn_accounts),
+        burn_amount,
+    )?;
+
+    emit!(Withdraw {
+        pool: ctx.accounts.withdraw.pool.key(),
+        withdrawer: ctx.accounts.withdraw.withdrawer.key(),
+        quantity,
+        ious_burned: burn_amount,
+        nav: quote_to_native(tranche_value_quote),
+        iou_supply_before: ctx.accounts.withdraw.pool_iou_mint.supply,
+        iou_mint: ctx.accounts.withdraw.pool_iou_mint.key(),
+    });
+    let withdraw_ctx = Context::new(
+        ctx.program_id,
+        &mut ctx.accounts.withdraw,
+        ctx.remaining_accounts,
+    );
+    withdraw_from_mango(withdraw_ctx, &open_orders_keys, quantity)
+}
+
+/// Runs the usual withdrawal checks and sizes the sell order needed to cover `quantity` QUOTE
+#[inline(never)]
+fn prepare_unwind<'a, 'b, 'c, 'info>(
+    ctx: &Context<'a, 'b, 'c, 'info, WithdrawWithUnwind<'info>>,
+    quantity: u64,
+    max_slippage_bps: u16,
+) -> Result<UnwindOrder, ProgramError> {
+    let accounts = &ctx.accounts.withdraw;
+    let mango_group_ai = accounts.mango_group.to_account_info();
+    let mango_cache_ai = accounts.mango_cache.to_account_info();
+
+    let mango_account = MangoAccount::load_checked(
+        &accounts.mango_account,
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+    )?;
+    let mango_group = MangoGroup::load_checked(&mango_group_ai, accounts.mango_program.key)?;
+    let mango_cache =
+        MangoCache::load_checked(&mango_cache_ai, accounts.mango_program.key, &mango_group)?;
+
+    //check that user is withdrawing QUOTE from pool
+    check!(
+        mango_group.tokens[QUOTE_INDEX].mint == accounts.withdrawer_token_account.mint,
+        MangoErrorCode::InvalidToken
+    )?;
+
+    check_dex_program(&mango_group, &ctx.accounts.unwind.dex_program)?;
+
+    // find the market being unwound
+    let market_index = mango_group
+        .find_spot_market_index(ctx.accounts.unwind.spot_market.key)
+        .ok_or(BlenderError::UnknownMarket)?;
+    require!(
+        mango_account.spot_open_orders[market_index] == ctx.accounts.unwind.spot_open_orders.key(),
+        BlenderError::InvalidOpenOrders
+    );
+
+    // check that cache is valid
+    let active_assets = UserActiveAssets::new(
+        &mango_group,
+        &mango_account,
+        pool_valuation_assets(
+            &accounts.pool,
+            vec![
+                (AssetType::Token, QUOTE_INDEX),
+                (AssetType::Token, market_index),
+            ],
+        ),
+    );
+    let clock = Clock::get()?;
+    mango_cache.check_valid(&mango_group, &active_assets, clock.unix_timestamp as u64)?;
+
+    // no withdrawing in the same slot (or within the lock window) as a deposit
+    require!(
+        !accounts.withdrawer_position.is_deposit_locked(clock.slot),
+        BlenderError::WithdrawLocked
+    );
+
+    let open_orders_ais =
+        mango_account.checked_unpack_open_orders(&mango_group, &ctx.remaining_accounts)?;
+    check_mango_account_healthy(
+        &mango_account,
+        &mango_cache,
+        &mango_group,
+        &open_orders_ais,
+        &active_assets,
+    )?;
+    let pool_value_quote = calculate_pool_value(
+        &mango_account,
+        &mango_cache,
+        &mango_group,
+        open_orders_ais,
+        &active_assets,
+        accounts.pool.mngo_index(),
+    );
+
+    // only sell what the QUOTE deposit can't cover
+    let quote_available = get_mango_account_base_net(
+        &mango_account,
+        &mango_cache.root_bank_cache[QUOTE_INDEX],
+        QUOTE_INDEX,
+    );
+    let shortfall_quote = I80F48::from_num(quantity) - quote_available;
+    if shortfall_quote <= ZERO_I80F48 {
+        return Ok(UnwindOrder {
+            market_index,
+            pool_value_quote,
+            max_coin_qty: 0,
+            limit_price: 0,
+        });
+    }
+
+    let base_available = get_mango_account_base_net(
+        &mango_account,
+        &mango_cache.root_bank_cache[market_index],
+        market_index,
+    );
+    check!(
+        base_available > ZERO_I80F48,
+        MangoErrorCode::InsufficientFunds
+    )?;
+
+    let (coin_lot_size, pc_lot_size) = {
+        let market = MarketState::load(
+            &ctx.accounts.unwind.spot_market,
+            ctx.accounts.unwind.dex_program.key,
+            false,
+        )?;
+        (
+            I80F48::from_num(market.coin_lot_size),
+            I80F48::from_num(market.pc_lot_size),
+        )
+    };
+
+    // worst price we accept, in native QUOTE per native base and in serum lots
+    let min_price = mango_cache.get_price(market_index)
+        * I80F48::from_num(10_000 - max_slippage_bps)
+        / I80F48::from_num(10_000);
+    let limit_price: u64 = (min_price * coin_lot_size / pc_lot_size)
+        .checked_ceil()
+        .unwrap()
+        .checked_to_num()
+        .unwrap();
+    check!(limit_price > 0, MangoErrorCode::InvalidParam)?;
+
+    // enough lots to cover the shortfall at the worst price, capped by what the pool holds
+    let needed_lots = (shortfall_quote / min_price / coin_lot_size)
+        .checked_ceil()
+        .unwrap();
+    let available_lots = (base_available / coin_lot_size).checked_floor().unwrap();
+    let max_coin_qty: u64 = needed_lots.min(available_lots).checked_to_num().unwrap();
+
+    Ok(UnwindOrder {
+        market_index,
+        pool_value_quote,
+        max_coin_qty,
+        limit_price,
+    })
+}
+
+/// Immediate-or-cancel ask on the Serum market, placed through Mango and signed by the pool
+#[inline(never)]
+fn place_unwind_order<'a, 'b, 'c, 'info>(
+    ctx: &Context<'a, 'b, 'c, 'info, WithdrawWithUnwind<'info>>,
+    order: &UnwindOrder,
+) -> ProgramResult {
+    let withdraw = &ctx.accounts.withdraw;
+    let unwind = &ctx.accounts.unwind;
+    let open_orders_keys = {
+        let mango_account = MangoAccount::load_checked(
+            &withdraw.mango_account,
+            withdraw.mango_program.key,
+            withdraw.mango_group.key,
+        )?;
+        mango_account.spot_open_orders
+    };
+
+    let seeds = &[
+        &withdraw.pool.pool_name.as_ref(),
+        withdraw.pool.admin.as_ref(),
+        &[withdraw.pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    let new_order = NewOrderInstructionV3 {
+        side: Side::Ask,
+        limit_price: NonZeroU64::new(order.limit_price).unwrap(),
+        max_coin_qty: NonZeroU64::new(order.max_coin_qty).unwrap(),
+        max_native_pc_qty_including_fees: NonZeroU64::new(u64::MAX).unwrap(),
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        order_type: OrderType::ImmediateOrCancel,
+        client_order_id: 0,
+        limit: 32,
+    };
+
+    let place_instruction = MangoInstructions::place_spot_order2(
+        withdraw.mango_program.key,
+        withdraw.mango_group.key,
+        withdraw.mango_account.key,
+        withdraw.pool.to_account_info().key,
+        withdraw.mango_cache.key,
+        unwind.dex_program.key,
+        unwind.spot_market.key,
+        unwind.bids.key,
+        unwind.asks.key,
+        unwind.dex_request_queue.key,
+        unwind.dex_event_queue.key,
+        unwind.dex_base.key,
+        unwind.dex_quote.key,
+        unwind.base_root_bank.key,
+        unwind.base_node_bank.key,
+        unwind.base_vault.key,
+        withdraw.root_bank.key,
+        withdraw.node_bank.key,
+        withdraw.vault.key,
+        withdraw.mango_group_signer.key,
+        unwind.dex_signer.key,
+        unwind.msrm_or_srm_vault.key,
+        &open_orders_keys,
+        order.market_index,
+        new_order,
+    )
+    .unwrap();
+
+    let mut account_infos = vec![
+        withdraw.mango_program.to_account_info(),
+        withdraw.mango_group.to_account_info(),
+        withdraw.mango_account.to_account_info(),
+        withdraw.pool.to_account_info(),
+        withdraw.mango_cache.to_account_info(),
+        unwind.dex_program.to_account_info(),
+        unwind.spot_market.to_account_info(),
+        unwind.bids.to_account_info(),
+        unwind.asks.to_account_info(),
+        unwind.dex_request_queue.to_account_info(),
+        unwind.dex_event_queue.to_account_info(),
+        unwind.dex_base.to_account_info(),
+        unwind.dex_quote.to_account_info(),
+        unwind.base_root_bank.to_account_info(),
+        unwind.base_node_bank.to_account_info(),
+        unwind.base_vault.to_account_info(),
+        withdraw.root_bank.to_account_info(),
+        withdraw.node_bank.to_account_info(),
+        withdraw.vault.to_account_info(),
+        withdraw.token_program.to_account_info(),
+        withdraw.mango_group_signer.to_account_info(),
+        unwind.dex_signer.to_account_info(),
+        unwind.msrm_or_srm_vault.to_account_info(),
+        unwind.spot_open_orders.to_account_info(),
+    ];
+    account_infos.extend(ctx.remaining_accounts.iter().cloned());
+
+    invoke_signed_unchecked(&place_instruction, &account_infos, cpi_seed)?;
+    Ok(())
+}
+
+/// Moves the proceeds of the sale from the open orders account back into the MangoAccount
+#[inline(never)]
+fn settle_unwind<'a, 'b, 'c, 'info>(
+    ctx: &Context<'a, 'b, 'c, 'info, WithdrawWithUnwind<'info>>,
+) -> ProgramResult {
+    let withdraw = &ctx.accounts.withdraw;
+    let unwind = &ctx.accounts.unwind;
+
+    let seeds = &[
+        &withdraw.pool.pool_name.as_ref(),
+        withdraw.pool.admin.as_ref(),
+        &[withdraw.pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    let settle_instruction = MangoInstructions::settle_funds(
+        withdraw.mango_program.key,
+        withdraw.mango_group.key,
+        withdraw.mango_cache.key,
+        withdraw.pool.to_account_info().key,
+        withdraw.mango_account.key,
+        unwind.dex_program.key,
+        unwind.spot_market.key,
+        unwind.spot_open_orders.key,
+        withdraw.mango_group_signer.key,
+        unwind.dex_base.key,
+        unwind.dex_quote.key,
+        unwind.base_root_bank.key,
+        unwind.base_node_bank.key,
+        withdraw.root_bank.key,
+        withdraw.node_bank.key,
+        unwind.base_vault.key,
+        withdraw.vault.key,
+        unwind.dex_signer.key,
+    )
+    .unwrap();
+
+    invoke_signed_unchecked(
+        &settle_instruction,
+        &[
+            withdraw.mango_program.to_account_info(),
+            withdraw.mango_group.to_account_info(),
+            withdraw.mango_cache.to_account_info(),
+            withdraw.pool.to_account_info(),
+            withdraw.mango_account.to_account_info(),
+            unwind.dex_program.to_account_info(),
+            unwind.spot_market.to_account_info(),
+            unwind.spot_open_orders.to_account_info(),
+            withdraw.mango_group_signer.to_account_info(),
+            unwind.dex_base.to_account_info(),
+            unwind.dex_quote.to_account_info(),
+            unwind.base_root_bank.to_account_info(),
+            unwind.base_node_bank.to_account_info(),
+            withdraw.root_bank.to_account_info(),
+            withdraw.node_bank.to_account_info(),
+            unwind.base_vault.to_account_info(),
+            withdraw.vault.to_account_info(),
+            unwind.dex_signer.to_account_info(),
+            withdraw.token_program.to_account_info(),
+        ],
+        cpi_seed,
+    )?;
+    Ok(())
+}
+
+/// Re-values the pool after the sale, returning the (possibly updated) open orders keys for the withdraw CPI
+#[inline(never)]
+fn value_pool<'a, 'b, 'c, 'info>(
+    ctx: &Context<'a, 'b, 'c, 'info, WithdrawWithUnwind<'info>>,
+) -> Result<(I80F48, [Pubkey; MAX_PAIRS]), ProgramError> {
+    let accounts = &ctx.accounts.withdraw;
+    let mango_group_ai = accounts.mango_group.to_account_info();
+    let mango_cache_ai = accounts.mango_cache.to_account_info();
+
+    let mango_account = MangoAccount::load_checked(
+        &accounts.mango_account,
+        accounts.mango_program.key,
+        accounts.mango_group.key,
+    )?;
+    let mango_group = MangoGroup::load_checked(&mango_group_ai, accounts.mango_program.key)?;
+    let mango_cache =
+        MangoCache::load_checked(&mango_cache_ai, accounts.mango_program.key, &mango_group)?;
+
+    let active_assets = UserActiveAssets::new(
+        &mango_group,
+        &mango_account,
+        pool_valuation_assets(&accounts.pool, vec![(AssetType::Token, QUOTE_INDEX)]),
+    );
+    let open_orders_ais =
+        mango_account.checked_unpack_open_orders(&mango_group, &ctx.remaining_accounts)?;
+    let pool_value_quote = calculate_pool_value(
+        &mango_account,
+        &mango_cache,
+        &mango_group,
+        open_orders_ais,
+        &active_assets,
+        accounts.pool.mngo_index(),
+    );
+    Ok((pool_value_quote, mango_account.spot_open_orders))
+}
@@ -1,2 +1,4 @@
+pub mod errors;
+pub mod events;
 pub mod instructions;
 pub mod state;
@@ -0,0 +1,74 @@
+use anchor_lang::prelude::*;
+use fixed::types::I80F48;
+
+use crate::blender::errors::BlenderError;
+use crate::blender::state::Pool;
+
+#[account]
+/// Per-depositor bookkeeping for a pool, derived from [pool, depositor, "position"]
+pub struct DepositorPosition {
+    pub pool: Pubkey,                // 32
+    pub owner: Pubkey,               // 32
+    pub bump: u8,                    // 1
+    pub last_deposit_slot: u64,      // 8
+    pub deposit_unlock_slot: u64,    // 8, deposit slot + lock
+    pub queued_withdrawal: u64,      // 8, QUOTE carried over to the next withdrawal window
+    pub queued_window_id: u64,       // 8
+    pub distribution_index: i128,    // 16, I80F48 bits, pool's index at the last settlement
+    pub unclaimed_distribution: u64, // 8, QUOTE owed from distributions
+    pub distribution_balance: u64,   // 8, junior IOUs distributions are paid on
+}
+
+pub const DEPOSITOR_POSITION_SIZE: usize = 8 + 32 + 32 + 1 + 8 + 8 + 8 + 8 + 16 + 8 + 8;
+
+impl DepositorPosition {
+    /// Records a deposit at `slot`, locked for the pool's current `deposit_lock_slots`
+    /// A later change to the pool's lock doesn't move an unlock slot already recorded
+    pub fn record_deposit(&mut self, slot: u64, deposit_lock_slots: u64) {
+        self.last_deposit_slot = slot;
+        self.deposit_unlock_slot = self
+            .deposit_unlock_slot
+            .max(slot.saturating_add(deposit_lock_slots));
+    }
+
+    /// Whether IOUs from the last deposit are still locked at `slot` (always the case in the deposit's own slot)
+    pub fn is_deposit_locked(&self, slot: u64) -> bool {
+        slot <= self.last_deposit_slot || slot <= self.deposit_unlock_slot
+    }
+
+    /// Credits distributions since the last settlement to the holder's junior IOUs, call before their balance changes
+    /// Only IOUs recorded on the position (by deposits and transfer_ious) and still held earn, IOUs moved out
+    /// of the account any other way stop earning from here on
+    pub fn settle_distribution(&mut self, pool: &mut Pool, iou_balance: u64) -> ProgramResult {
+        let held = self.distribution_balance.min(iou_balance);
+        let owed: u64 = pool
+            .distribution_index()
+            .checked_sub(I80F48::from_bits(self.distribution_index))
+            .and_then(|per_iou| per_iou.checked_mul(I80F48::from_num(held)))
+            .and_then(|owed| owed.checked_floor())
+            .and_then(|owed| owed.checked_to_num())
+            .ok_or(BlenderError::MathError)?;
+        self.unclaimed_distribution = self
+            .unclaimed_distribution
+            .checked_add(owed)
+            .ok_or(BlenderError::MathError)?;
+        self.distribution_index = pool.distribution_index;
+        self.record_distribution_balance(pool, held)
+    }
+
+    /// Sets the junior IOUs distributions are paid on, keeping the pool's `distribution_weight` in step
+    /// Settle first, the new balance only earns from the next distribution
+    pub fn record_distribution_balance(
+        &mut self,
+        pool: &mut Pool,
+        distribution_balance: u64,
+    ) -> ProgramResult {
+        pool.distribution_weight = pool
+            .distribution_weight
+            .checked_sub(self.distribution_balance)
+            .and_then(|weight| weight.checked_add(distribution_balance))
+            .ok_or(BlenderError::MathError)?;
+        self.distribution_balance = distribution_balance;
+        Ok(())
+    }
+}
@@ -1,3 +1,11 @@
+pub use depositor_position::*;
+pub use nav_history::*;
+pub use nav_snapshot::*;
 pub use pool::*;
+pub use price_feed::*;
 
+pub mod depositor_position;
+pub mod nav_history;
+pub mod nav_snapshot;
 pub mod pool;
+pub mod price_feed;
@@ -0,0 +1,46 @@
+use anchor_lang::prelude::*;
+
+/// Number of entries kept before the oldest is overwritten
+pub const NAV_HISTORY_LEN: usize = 240;
+
+#[account(zero_copy)]
+/// Ring buffer of NAV snapshots for charting and performance fees, derived from [pool, "nav_history"]
+pub struct NavHistory {
+    pub pool: Pubkey,
+    pub head: u64,  // next slot to write
+    pub count: u64, // filled entries, up to NAV_HISTORY_LEN
+    pub entries: [NavHistoryEntry; NAV_HISTORY_LEN],
+}
+
+#[zero_copy]
+pub struct NavHistoryEntry {
+    pub timestamp: i64,
+    pub nav_per_iou: i128, // I80F48 bits
+    pub nav: i128,         // I80F48 bits, QUOTE
+}
+
+pub const NAV_HISTORY_SIZE: usize = 8 + std::mem::size_of::<NavHistory>();
+
+impl NavHistory {
+    /// Most recent entry, if any
+    pub fn last(&self) -> Option<&NavHistoryEntry> {
+        if self.count == 0 {
+            return None;
+        }
+        let last = (self.head as usize + NAV_HISTORY_LEN - 1) % NAV_HISTORY_LEN;
+        Some(&self.entries[last])
+    }
+
+    /// Appends an entry unless the last one is less than `min_interval_seconds` old, returns whether it did
+    pub fn append(&mut self, entry: NavHistoryEntry, min_interval_seconds: i64) -> bool {
+        if let Some(last) = self.last() {
+            if entry.timestamp < last.timestamp + min_interval_seconds {
+                return false;
+            }
+        }
+        self.entries[self.head as usize] = entry;
+        self.head = (self.head + 1) % NAV_HISTORY_LEN as u64;
+        self.count = (self.count + 1).min(NAV_HISTORY_LEN as u64);
+        true
+    }
+}
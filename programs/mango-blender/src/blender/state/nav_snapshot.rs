@@ -0,0 +1,38 @@
+use anchor_lang::prelude::*;
+use fixed::types::I80F48;
+
+use crate::blender::state::Pool;
+
+#[account]
+/// Last pool valuation written by refresh_nav, derived from [pool, "nav"]
+pub struct NavSnapshot {
+    pub pool: Pubkey,    // 32
+    pub bump: u8,        // 1
+    pub nav: i128,       // 16, I80F48 bits, QUOTE
+    pub iou_supply: u64, // 8
+    pub slot: u64,       // 8
+    pub timestamp: i64,  // 8
+}
+
+pub const NAV_SNAPSHOT_SIZE: usize = 8 + 32 + 1 + 16 + 8 + 8 + 8;
+
+impl NavSnapshot {
+    pub fn nav(&self) -> I80F48 {
+        I80F48::from_bits(self.nav)
+    }
+
+    /// Pool value for `iou_supply` junior IOUs at the snapshot's NAV per IOU, if the snapshot is at most the pool's
+    /// `nav_max_age_slots` old. NAV per IOU doesn't move with junior deposits or withdrawals, so the snapshot stays
+    /// usable as the supply changes. Senior deposits move NAV without moving the junior supply, so a pool with a
+    /// senior tranche never prices off the snapshot, for either tranche
+    pub fn cached_pool_value(&self, pool: &Pool, iou_supply: u64, slot: u64) -> Option<I80F48> {
+        if pool.has_senior_tranche()
+            || pool.nav_max_age_slots == 0
+            || self.iou_supply == 0
+            || slot > self.slot.saturating_add(pool.nav_max_age_slots)
+        {
+            return None;
+        }
+        Some(self.nav() * I80F48::from_num(iou_supply) / I80F48::from_num(self.iou_supply))
+    }
+}
@@ -1,12 +1,498 @@
 use anchor_lang::prelude::*;
+use fixed::types::I80F48;
+use mango::state::{MAX_PAIRS, ZERO_I80F48};
+use std::cmp::min;
+
+use crate::blender::state::DepositorPosition;
+
+/// Length of a withdrawal window in seconds
+pub const WITHDRAWAL_WINDOW_SECONDS: i64 = 24 * 60 * 60;
+
+/// Upper bound on the deposit lock, about an hour of 400ms slots
+pub const MAX_DEPOSIT_LOCK_SLOTS: u64 = 9_000;
+
+/// Upper bound on how stale a NAV snapshot deposits and withdrawals may price off, about 10 seconds
+pub const MAX_NAV_AGE_SLOTS: u64 = 25;
+
+/// The senior rate is a simple annual rate over 365 days
+pub const SECONDS_PER_YEAR: i64 = 365 * 24 * 60 * 60;
 
 #[account]
-///Comments here
+/// A pooled MangoAccount with its IOU mints and settings, derived from [pool_name, admin]
 pub struct Pool {
-    pub pool_name: String, // Max of 32 characters
-    pub pool_bump: u8,     //1
-    pub iou_mint_bump: u8, //1
-    pub iou_mint: Pubkey,  // 32
-    pub admin: Pubkey,     // 32
+    pub pool_name: String,                  // Max of 32 characters
+    pub pool_bump: u8,                      //1
+    pub iou_mint_bump: u8,                  //1
+    pub iou_mint: Pubkey,                   // 32
+    pub admin: Pubkey,                      // 32
+    pub deposit_lock_slots: u64,            // 8
+    pub withdrawal_limit_bps: u16,          // 2, 0 = no limit
+    pub withdrawal_window_id: u64,          // 8
+    pub withdrawal_window_start: i64,       // 8
+    pub withdrawal_window_nav: u64,         // 8
+    pub withdrawal_window_used: u64,        // 8
+    pub withdrawal_window_reserved: u64,    // 8, carried over from the previous window
+    pub withdrawal_queued: u64,             // 8, to be carried over into the next window
+    pub queue_unhealthy_withdrawals: bool,  // 1, queue unhealthy withdrawals
+    pub withdraw_health_floor_bps: u16, // 2, min init health as bps of pool value after a withdrawal
+    pub max_withdraw_leverage_bps: u32, // 4, max borrows as bps of pool value after a withdrawal, 0 = no borrowing
+    pub risk_limits: RiskLimits,        // RISK_LIMITS_SIZE
+    pub pending_risk_limits: RiskLimits, // RISK_LIMITS_SIZE, looser limits on hold
+    pub pending_risk_limits_ts: i64,    // 8, when they can be applied, 0 = none pending
+    pub proxy_only: bool,               // 1, trading only through the pool's proxy instructions
+    pub market_allowlist: MarketAllowlist, // MARKET_ALLOWLIST_SIZE
+    pub mango_account: Pubkey,          // 32
+    pub status: PoolStatus,             // 1
+    pub max_drawdown_bps: u16, // 2, drawdown from the high-water mark that trips the circuit breaker, 0 = off
+    pub pending_max_drawdown_bps: u16, // 2, a looser drawdown limit waiting out the allowlist timelock
+    pub pending_max_drawdown_ts: i64,  // 8, when it can be applied, 0 = none pending
+    pub nav_per_iou_high_water_mark: i128, // 16, I80F48 bits
+    pub wind_down_slippage_bps: u16,   // 2, max distance from oracle for keeper unwind orders
+    pub value_accrued_mngo: bool,      // 1, count unredeemed MNGO rewards in the pool value
+    pub mngo_token_index: u8,          // 1
+    pub nav_max_age_slots: u64, // 8, deposits and withdrawals price off the NAV snapshot up to this age, 0 = always live
+    pub nav_history_interval_seconds: i64, // 8, min time between NAV history entries
+    pub price_feed_bump: u8,    // 1
+    pub price_confidence_bps: u16, // 2, confidence published with the NAV price, in bps of the price
+    pub senior_iou_mint: Pubkey,   // 32, default pubkey = no senior tranche
+    pub senior_iou_mint_bump: u8,  // 1
+    pub senior_rate_bps: u16,      // 2, annual return accrued to the senior tranche
+    pub senior_claim: i128, // 16, I80F48 bits, QUOTE owed to the senior tranche as of senior_accrual_ts
+    pub senior_accrual_ts: i64, // 8
+    pub distribution_mode: bool, // 1, pay NAV growth out to junior IOU holders, no compounding
+    pub distribution_interval_seconds: i64, // 8, min time between distributions
+    pub last_distribution_ts: i64, // 8
+    pub distribution_nav_per_iou: i128, // 16, I80F48 bits, NAV per IOU after the last distribution, 0 = unset
+    pub distribution_index: i128,       // 16, I80F48 bits, QUOTE distributed per IOU to date
+    pub distribution_vault_bump: u8,    // 1
+    pub distribution_weight: u64, // 8, junior IOUs recorded on positions, what distributions are split over
+    pub unwind_base_lot_sizes: [u64; 15], // 120, Serum base lot size per non-QUOTE token, recorded by unwind_spot
+}
+
+/// Pools aren't resized or migrated, changing the layout means a redeploy (see CHANGELOG.md)
+pub const POOL_SIZE: usize = 8 // discriminator
+    + (4 + 32) // name
+    + 1 + 1 // bumps
+    + 32 // iou mint
+    + 32 // admin
+    + 8 // deposit lock
+    + (2 + 8 + 8 + 8 + 8 + 8 + 8 + 1) // withdrawal window
+    + 2 // withdraw health floor
+    + 4 // withdraw leverage
+    + RISK_LIMITS_SIZE
+    + RISK_LIMITS_SIZE + 8 // pending risk limits
+    + 1 // proxy only
+    + MARKET_ALLOWLIST_SIZE
+    + 32 // mango account
+    + 1 // status
+    + 2 // max drawdown
+    + 2 + 8 // pending max drawdown
+    + 16 // high-water mark
+    + 2 // wind down slippage
+    + 1 + 1 // accrued mngo valuation
+    + 8 // nav max age
+    + 8 // nav history interval
+    + 1 + 2 // price feed
+    + 32 + 1 + 2 + 16 + 8 // senior tranche
+    + 1 + 8 + 8 + 16 + 16 + 1 + 8 // distributions
+    + 8 * 15; // unwind lot sizes
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub enum PoolStatus {
+    Active,
+    WithdrawOnly, // circuit breaker tripped: no deposits, no trading, withdrawals only
+    WindDown,     // shut down: keepers close positions, holders redeem pro-rata once flat
+}
+
+/// Senior IOUs are paid their accrued claim first and take losses last, junior IOUs (the pool's
+/// original `iou_mint`) absorb first losses and keep everything above the senior claim
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Tranche {
+    Senior,
+    Junior,
+}
+
+impl Default for PoolStatus {
+    fn default() -> Self {
+        PoolStatus::Active
+    }
+}
+
+/// Limits checked by the pool before it signs a trade for the admin, a limit of 0 is not checked
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct RiskLimits {
+    pub max_position_notional: [u64; MAX_PAIRS], // QUOTE, per market index (spot and perp)
+    pub max_leverage_bps: u32,                   // gross exposure as bps of pool value
+    pub max_order_notional: u64,                 // QUOTE
+}
+
+pub const RISK_LIMITS_SIZE: usize = 8 * MAX_PAIRS + 4 + 8;
+
+impl RiskLimits {
+    /// Whether `new` allows anything these limits don't, 0 (unchecked) being the loosest limit
+    pub fn loosened_by(&self, new: &RiskLimits) -> bool {
+        fn loosens(old: u64, new: u64) -> bool {
+            old != 0 && (new == 0 || new > old)
+        }
+        loosens(self.max_leverage_bps as u64, new.max_leverage_bps as u64)
+            || loosens(self.max_order_notional, new.max_order_notional)
+            || self
+                .max_position_notional
+                .iter()
+                .zip(new.max_position_notional.iter())
+                .any(|(old, new)| loosens(*old, *new))
+    }
+}
+
+/// The markets the pool will sign trades on, by Mango market index (bit i set = market i allowed)
+/// Widening the list waits `timelock_seconds` in `pending_*`, narrowing it is immediate
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct MarketAllowlist {
+    pub spot_markets: u16,
+    pub perp_markets: u16,
+    pub pending_spot_markets: u16,
+    pub pending_perp_markets: u16,
+    pub pending_effective_ts: i64, // 0 = nothing pending
+    pub timelock_seconds: i64,
+}
+
+pub const MARKET_ALLOWLIST_SIZE: usize = 2 + 2 + 2 + 2 + 8 + 8;
+
+impl MarketAllowlist {
+    pub fn is_spot_allowed(&self, market_index: usize) -> bool {
+        self.spot_markets & (1 << market_index) != 0
+    }
+
+    pub fn is_perp_allowed(&self, market_index: usize) -> bool {
+        self.perp_markets & (1 << market_index) != 0
+    }
+
+    /// Applies a new list right away if it only removes markets (or there is no timelock), otherwise queues it
+    /// A new list replaces anything already pending
+    pub fn update(&mut self, spot_markets: u16, perp_markets: u16, now_ts: i64) {
+        let widens =
+            spot_markets & !self.spot_markets != 0 || perp_markets & !self.perp_markets != 0;
+        if widens && self.timelock_seconds > 0 {
+            self.pending_spot_markets = spot_markets;
+            self.pending_perp_markets = perp_markets;
+            self.pending_effective_ts = now_ts + self.timelock_seconds;
+        } else {
+            self.spot_markets = spot_markets;
+            self.perp_markets = perp_markets;
+            self.clear_pending();
+        }
+    }
+
+    /// Moves the pending list in once its timelock has passed, returns whether it did
+    pub fn apply_pending(&mut self, now_ts: i64) -> bool {
+        if self.pending_effective_ts == 0 || now_ts < self.pending_effective_ts {
+            return false;
+        }
+        self.spot_markets = self.pending_spot_markets;
+        self.perp_markets = self.pending_perp_markets;
+        self.clear_pending();
+        true
+    }
+
+    fn clear_pending(&mut self) {
+        self.pending_spot_markets = 0;
+        self.pending_perp_markets = 0;
+        self.pending_effective_ts = 0;
+    }
+}
+
+impl Pool {
+    /// Applies limits that only tighten right away, looser ones wait the allowlist timelock in `pending_risk_limits`
+    /// New limits replace anything already pending
+    pub fn update_risk_limits(&mut self, risk_limits: RiskLimits, now_ts: i64) {
+        let timelock_seconds = self.market_allowlist.timelock_seconds;
+        if self.risk_limits.loosened_by(&risk_limits) && timelock_seconds > 0 {
+            self.pending_risk_limits = risk_limits;
+            self.pending_risk_limits_ts = now_ts + timelock_seconds;
+        } else {
+            self.risk_limits = risk_limits;
+            self.pending_risk_limits = RiskLimits::default();
+            self.pending_risk_limits_ts = 0;
+        }
+    }
+
+    /// Moves the pending limits in once their timelock has passed, returns whether it did
+    pub fn apply_pending_risk_limits(&mut self, now_ts: i64) -> bool {
+        if self.pending_risk_limits_ts == 0 || now_ts < self.pending_risk_limits_ts {
+            return false;
+        }
+        self.risk_limits = self.pending_risk_limits;
+        self.pending_risk_limits = RiskLimits::default();
+        self.pending_risk_limits_ts = 0;
+        true
+    }
+
+    /// Token index to value accrued MNGO at, if the pool counts it
+    pub fn mngo_index(&self) -> Option<usize> {
+        if self.value_accrued_mngo {
+            Some(self.mngo_token_index as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn nav_per_iou_high_water_mark(&self) -> I80F48 {
+        I80F48::from_bits(self.nav_per_iou_high_water_mark)
+    }
+
+    /// Raises the high-water mark if NAV per IOU is above it
+    pub fn record_nav_per_iou(&mut self, nav_per_iou: I80F48) {
+        if nav_per_iou > self.nav_per_iou_high_water_mark() {
+            self.nav_per_iou_high_water_mark = nav_per_iou.to_bits();
+        }
+    }
+
+    /// Applies a lower (non-zero) drawdown limit right away, a higher one or turning it off waits the allowlist
+    /// timelock in `pending_max_drawdown_bps`. A new limit replaces anything already pending
+    pub fn update_max_drawdown(&mut self, max_drawdown_bps: u16, now_ts: i64) {
+        let loosens = self.max_drawdown_bps != 0
+            && (max_drawdown_bps == 0 || max_drawdown_bps > self.max_drawdown_bps);
+        let timelock_seconds = self.market_allowlist.timelock_seconds;
+        if loosens && timelock_seconds > 0 {
+            self.pending_max_drawdown_bps = max_drawdown_bps;
+            self.pending_max_drawdown_ts = now_ts + timelock_seconds;
+        } else {
+            self.max_drawdown_bps = max_drawdown_bps;
+            self.pending_max_drawdown_bps = 0;
+            self.pending_max_drawdown_ts = 0;
+        }
+    }
+
+    /// Moves the pending drawdown limit in once its timelock has passed, returns whether it did
+    pub fn apply_pending_max_drawdown(&mut self, now_ts: i64) -> bool {
+        if self.pending_max_drawdown_ts == 0 || now_ts < self.pending_max_drawdown_ts {
+            return false;
+        }
+        self.max_drawdown_bps = self.pending_max_drawdown_bps;
+        self.pending_max_drawdown_bps = 0;
+        self.pending_max_drawdown_ts = 0;
+        true
+    }
+
+    /// Whether NAV per IOU has fallen `max_drawdown_bps` or more below the high-water mark
+    pub fn is_drawdown_breached(&self, nav_per_iou: I80F48) -> bool {
+        if self.max_drawdown_bps == 0 {
+            return false;
+        }
+        let retained = I80F48::from_num(10_000 - self.max_drawdown_bps) / I80F48::from_num(10_000);
+        nav_per_iou <= self.nav_per_iou_high_water_mark() * retained
+    }
+
+    /// Lowers the high-water mark by QUOTE per IOU paid out, a distribution isn't a drawdown
+    pub fn lower_high_water_mark(&mut self, distributed_per_iou: I80F48) {
+        let high_water_mark =
+            (self.nav_per_iou_high_water_mark() - distributed_per_iou).max(ZERO_I80F48);
+        self.nav_per_iou_high_water_mark = high_water_mark.to_bits();
+    }
+
+    pub fn distribution_nav_per_iou(&self) -> I80F48 {
+        I80F48::from_bits(self.distribution_nav_per_iou)
+    }
+
+    pub fn distribution_index(&self) -> I80F48 {
+        I80F48::from_bits(self.distribution_index)
+    }
+
+    pub fn has_senior_tranche(&self) -> bool {
+        self.senior_iou_mint != Pubkey::default()
+    }
+
+    /// The tranche `iou_mint` is the IOU mint of, pools without a senior tranche only have the junior one
+    pub fn tranche_of(&self, iou_mint: &Pubkey) -> Option<Tranche> {
+        if *iou_mint == self.iou_mint {
+            Some(Tranche::Junior)
+        } else if self.has_senior_tranche() && *iou_mint == self.senior_iou_mint {
+            Some(Tranche::Senior)
+        } else {
+            None
+        }
+    }
+
+    /// QUOTE owed to the senior tranche at `now_ts`, simple interest at `senior_rate_bps` since the last accrual
+    pub fn senior_claim_at(&self, now_ts: i64) -> I80F48 {
+        let claim = I80F48::from_bits(self.senior_claim);
+        let elapsed = (now_ts - self.senior_accrual_ts).max(0);
+        let accrual = I80F48::from_num(self.senior_rate_bps) * I80F48::from_num(elapsed)
+            / I80F48::from_num(10_000 * SECONDS_PER_YEAR);
+        claim + claim * accrual
+    }
+
+    /// Moves the accrued senior return into `senior_claim`, before the claim changes or the rate does
+    pub fn accrue_senior_claim(&mut self, now_ts: i64) {
+        self.senior_claim = self.senior_claim_at(now_ts).to_bits();
+        self.senior_accrual_ts = now_ts;
+    }
+
+    /// Adds the claim of `minted` senior IOUs to the claim of the `supply` already out, at the same claim per IOU
+    /// The first IOUs minted are owed `deposit_value`, call after `accrue_senior_claim`
+    pub fn add_senior_claim(&mut self, deposit_value: I80F48, minted: u64, supply: u64) {
+        let claim = I80F48::from_bits(self.senior_claim);
+        let added = if supply == 0 {
+            deposit_value
+        } else {
+            claim * I80F48::from_num(minted) / I80F48::from_num(supply)
+        };
+        self.senior_claim = (claim + added).to_bits();
+    }
+
+    /// With no junior IOUs out, whatever the pool holds above the senior claim belongs to the senior holders
+    /// and is added to their claim, so the first junior IOUs minted again don't take it, call after `accrue_senior_claim`
+    pub fn sweep_junior_residual(&mut self, junior_value: I80F48) {
+        if self.senior_claim != 0 && junior_value > ZERO_I80F48 {
+            self.senior_claim = (I80F48::from_bits(self.senior_claim) + junior_value).to_bits();
+        }
+    }
+
+    /// Drops the claim of `burned` senior IOUs out of `supply`, pro-rata, call after `accrue_senior_claim`
+    pub fn remove_senior_claim(&mut self, burned: u64, supply: u64) {
+        let claim = I80F48::from_bits(self.senior_claim);
+        let remaining = claim * I80F48::from_num(supply - burned) / I80F48::from_num(supply);
+        self.senior_claim = remaining.to_bits();
+    }
+
+    /// Splits `pool_value` into (senior value, junior value) at `now_ts`
+    /// The senior tranche is worth its claim for as long as the pool covers it, the junior tranche gets the rest
+    pub fn tranche_values(&self, pool_value: I80F48, now_ts: i64) -> (I80F48, I80F48) {
+        if !self.has_senior_tranche() {
+            return (ZERO_I80F48, pool_value);
+        }
+        let senior_value = self
+            .senior_claim_at(now_ts)
+            .min(pool_value.max(ZERO_I80F48));
+        (senior_value, pool_value - senior_value)
+    }
+
+    pub fn tranche_value(&self, tranche: Tranche, pool_value: I80F48, now_ts: i64) -> I80F48 {
+        let (senior_value, junior_value) = self.tranche_values(pool_value, now_ts);
+        match tranche {
+            Tranche::Senior => senior_value,
+            Tranche::Junior => junior_value,
+        }
+    }
+
+    /// Starts a new withdrawal window valued at `pool_value` if the current one has run out
+    /// Requests queued in the old window get first claim on the new one
+    pub fn roll_withdrawal_window(&mut self, now_ts: i64, pool_value: u64) {
+        if now_ts < self.withdrawal_window_start + WITHDRAWAL_WINDOW_SECONDS {
+            return;
+        }
+        self.withdrawal_window_id += 1;
+        self.withdrawal_window_start = now_ts;
+        self.withdrawal_window_nav = pool_value;
+        self.withdrawal_window_used = 0;
+        self.withdrawal_window_reserved = self.withdrawal_queued;
+        self.withdrawal_queued = 0;
+    }
+
+    /// Total QUOTE that can leave the pool in the current window
+    pub fn withdrawal_window_limit(&self) -> u64 {
+        (self.withdrawal_window_nav as u128 * self.withdrawal_limit_bps as u128 / 10_000) as u64
+    }
+
+    /// QUOTE still available in the current window (including capacity reserved for carried over requests)
+    pub fn withdrawal_window_remaining(&self) -> u64 {
+        self.withdrawal_window_limit()
+            .saturating_sub(self.withdrawal_window_used)
+    }
+
+    /// Drops the limit, anything queued goes with it (holders can withdraw it directly)
+    pub fn clear_withdrawal_queue(&mut self) {
+        self.withdrawal_window_reserved = 0;
+        self.withdrawal_queued = 0;
+    }
+
+    /// Rolls the window and returns how much of the position's queued request can be paid out of the capacity
+    /// reserved for it, requests queued last window are served now, older ones have expired and are dropped
+    /// Without a limit nothing stays queued
+    pub fn claimable_queued_withdrawal(
+        &mut self,
+        position: &mut DepositorPosition,
+        pool_value: u64,
+        now_ts: i64,
+    ) -> u64 {
+        if self.withdrawal_limit_bps == 0 {
+            position.queued_withdrawal = 0;
+            return 0;
+        }
+        self.roll_withdrawal_window(now_ts, pool_value);
+        if position.queued_withdrawal == 0 {
+            return 0;
+        }
+        if position.queued_window_id + 1 == self.withdrawal_window_id {
+            min(position.queued_withdrawal, self.withdrawal_window_reserved)
+        } else {
+            if position.queued_window_id + 1 < self.withdrawal_window_id {
+                position.queued_withdrawal = 0;
+            }
+            0
+        }
+    }
+
+    /// Caps a withdrawal request to what the window allows, returning the amount that can be withdrawn now
+    /// Anything over the cap is queued on the position and gets priority in the next window
+    pub fn apply_withdrawal_gate(
+        &mut self,
+        position: &mut DepositorPosition,
+        quantity: u64,
+        pool_value: u64,
+        now_ts: i64,
+    ) -> u64 {
+        let claimable_queue = self.claimable_queued_withdrawal(position, pool_value, now_ts);
+        if self.withdrawal_limit_bps == 0 {
+            return quantity;
+        }
+        let priority = min(
+            min(claimable_queue, quantity),
+            self.withdrawal_window_remaining(),
+        );
+        self.withdrawal_window_used += priority;
+        self.withdrawal_window_reserved -= priority;
+        position.queued_withdrawal -= priority;
+
+        let unreserved = self
+            .withdrawal_window_remaining()
+            .saturating_sub(self.withdrawal_window_reserved);
+        let fresh = min(quantity - priority, unreserved);
+        self.withdrawal_window_used += fresh;
+
+        let allowed = priority + fresh;
+        let excess = quantity - allowed;
+        if excess > 0 {
+            self.replace_queued_withdrawal(position, excess);
+        }
+        allowed
+    }
+
+    /// Queues the whole request for the next window, for withdrawals that can't be paid out now
+    pub fn queue_withdrawal(
+        &mut self,
+        position: &mut DepositorPosition,
+        quantity: u64,
+        pool_value: u64,
+        now_ts: i64,
+    ) {
+        self.claimable_queued_withdrawal(position, pool_value, now_ts);
+        self.replace_queued_withdrawal(position, quantity);
+    }
+
+    /// The new request replaces whatever this position still had queued
+    fn replace_queued_withdrawal(&mut self, position: &mut DepositorPosition, quantity: u64) {
+        if position.queued_window_id == self.withdrawal_window_id {
+            self.withdrawal_queued = self
+                .withdrawal_queued
+                .saturating_sub(position.queued_withdrawal);
+        } else if position.queued_window_id + 1 == self.withdrawal_window_id {
+            self.withdrawal_window_reserved = self
+                .withdrawal_window_reserved
+                .saturating_sub(position.queued_withdrawal);
+        }
+        position.queued_withdrawal = quantity;
+        position.queued_window_id = self.withdrawal_window_id;
+        self.withdrawal_queued += quantity;
+    }
 }
-// const_assert!(std::mem::size_of::<Pool>() == 1 + 1 + 32 + 32 + 32);
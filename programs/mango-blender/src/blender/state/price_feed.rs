@@ -0,0 +1,135 @@
+use anchor_lang::prelude::*;
+use fixed::types::I80F48;
+
+use crate::blender::errors::BlenderError;
+
+/// Size of a Pyth v2 price account
+pub const PRICE_FEED_SIZE: usize = 3312;
+/// Prices are published as QUOTE per IOU (UI units) * 10^-PRICE_FEED_EXPO
+pub const PRICE_FEED_EXPO: i32 = -9;
+
+const MAGIC: u32 = 0xa1b2c3d4;
+const VERSION: u32 = 2;
+const ACCOUNT_TYPE_PRICE: u32 = 3;
+const PRICE_TYPE_PRICE: u32 = 1;
+const STATUS_TRADING: u32 = 1;
+const STATUS_HALTED: u32 = 2;
+const IOU_DECIMALS: i32 = 6;
+
+// field offsets of the Pyth v2 price account
+const MAGIC_OFFSET: usize = 0;
+const VERSION_OFFSET: usize = 4;
+const ACCOUNT_TYPE_OFFSET: usize = 8;
+const SIZE_OFFSET: usize = 12;
+const PRICE_TYPE_OFFSET: usize = 16;
+const EXPO_OFFSET: usize = 20;
+const LAST_SLOT_OFFSET: usize = 32;
+const VALID_SLOT_OFFSET: usize = 40;
+const TIMESTAMP_OFFSET: usize = 96;
+const PREV_SLOT_OFFSET: usize = 176;
+const PREV_PRICE_OFFSET: usize = 184;
+const PREV_CONF_OFFSET: usize = 192;
+const PREV_TIMESTAMP_OFFSET: usize = 200;
+const AGG_PRICE_OFFSET: usize = 208;
+const AGG_CONF_OFFSET: usize = 216;
+const AGG_STATUS_OFFSET: usize = 224;
+const AGG_PUB_SLOT_OFFSET: usize = 232;
+
+/// A NAV per IOU reading to publish
+pub struct PriceUpdate {
+    pub price: i64,
+    pub conf: u64,
+    pub trading: bool,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+impl PriceUpdate {
+    /// `nav_per_iou` is in native units (QUOTE native per IOU native), confidence is `confidence_bps` of the price
+    /// Fails if the price doesn't fit the feed's exponent
+    pub fn new(
+        nav_per_iou: I80F48,
+        quote_decimals: u8,
+        confidence_bps: u16,
+        trading: bool,
+        clock: &Clock,
+    ) -> Result<Self, ProgramError> {
+        let scale = 10i64.pow((IOU_DECIMALS - PRICE_FEED_EXPO - quote_decimals as i32) as u32);
+        let price: i64 = nav_per_iou
+            .checked_mul(I80F48::from_num(scale))
+            .and_then(|price| price.checked_floor())
+            .and_then(|price| price.checked_to_num())
+            .ok_or(BlenderError::MathError)?;
+        let conf = (price.max(0) as u128 * confidence_bps as u128 / 10_000) as u64;
+        Ok(PriceUpdate {
+            price,
+            conf,
+            trading,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        })
+    }
+}
+
+/// Writes the Pyth header of a freshly created price account
+pub fn init_price_feed(data: &mut [u8]) -> ProgramResult {
+    require!(
+        data.len() == PRICE_FEED_SIZE,
+        BlenderError::InvalidPriceFeed
+    );
+    write(data, MAGIC_OFFSET, &MAGIC.to_le_bytes());
+    write(data, VERSION_OFFSET, &VERSION.to_le_bytes());
+    write(data, ACCOUNT_TYPE_OFFSET, &ACCOUNT_TYPE_PRICE.to_le_bytes());
+    write(data, SIZE_OFFSET, &(PRICE_FEED_SIZE as u32).to_le_bytes());
+    write(data, PRICE_TYPE_OFFSET, &PRICE_TYPE_PRICE.to_le_bytes());
+    write(data, EXPO_OFFSET, &PRICE_FEED_EXPO.to_le_bytes());
+    Ok(())
+}
+
+/// Publishes a new aggregate price, the previous one moves to the prev_* fields
+/// There's no averaging, the twap/twac fields are left at zero and shouldn't be read
+pub fn write_price_feed(data: &mut [u8], update: &PriceUpdate) -> ProgramResult {
+    require!(
+        data.len() == PRICE_FEED_SIZE && read_u32(data, MAGIC_OFFSET) == MAGIC,
+        BlenderError::InvalidPriceFeed
+    );
+
+    let prev_slot = read_u64(data, AGG_PUB_SLOT_OFFSET);
+    let prev_price = read_u64(data, AGG_PRICE_OFFSET);
+    let prev_conf = read_u64(data, AGG_CONF_OFFSET);
+    let prev_timestamp = read_u64(data, TIMESTAMP_OFFSET);
+    write(data, PREV_SLOT_OFFSET, &prev_slot.to_le_bytes());
+    write(data, PREV_PRICE_OFFSET, &prev_price.to_le_bytes());
+    write(data, PREV_CONF_OFFSET, &prev_conf.to_le_bytes());
+    write(data, PREV_TIMESTAMP_OFFSET, &prev_timestamp.to_le_bytes());
+
+    let status = if update.trading {
+        STATUS_TRADING
+    } else {
+        STATUS_HALTED
+    };
+    write(data, LAST_SLOT_OFFSET, &update.slot.to_le_bytes());
+    write(data, VALID_SLOT_OFFSET, &update.slot.to_le_bytes());
+    write(data, TIMESTAMP_OFFSET, &update.timestamp.to_le_bytes());
+    write(data, AGG_PRICE_OFFSET, &update.price.to_le_bytes());
+    write(data, AGG_CONF_OFFSET, &update.conf.to_le_bytes());
+    write(data, AGG_STATUS_OFFSET, &status.to_le_bytes());
+    write(data, AGG_PUB_SLOT_OFFSET, &update.slot.to_le_bytes());
+    Ok(())
+}
+
+fn write(data: &mut [u8], offset: usize, bytes: &[u8]) {
+    data[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
@@ -0,0 +1,108 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, FreezeAccount, ThawAccount, TokenAccount};
+use mango::instruction as MangoInstructions;
+use solana_program::program::invoke_signed;
+
+use crate::blender::events::DelegateChanged;
+use crate::blender::state::Pool;
+
+/// Sets the delegate of the pooled MangoAccount, signed by the pool
+/// Passing the system program as delegate (the default pubkey) revokes delegation
+pub fn set_mango_delegate<'info>(
+    pool: &Account<'info, Pool>,
+    mango_program: &AccountInfo<'info>,
+    mango_group: &AccountInfo<'info>,
+    mango_account: &AccountInfo<'info>,
+    delegate: &AccountInfo<'info>,
+) -> ProgramResult {
+    let seeds = &[
+        &pool.pool_name.as_ref(),
+        pool.admin.as_ref(),
+        &[pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    let delegate_instruction = MangoInstructions::set_delegate(
+        mango_program.key,
+        mango_group.key,
+        mango_account.key,
+        pool.to_account_info().key,
+        delegate.key,
+    )
+    .unwrap();
+
+    invoke_signed(
+        &delegate_instruction,
+        &[
+            mango_program.clone(),
+            mango_group.clone(),
+            mango_account.clone(),
+            pool.to_account_info().clone(),
+            delegate.clone(),
+        ],
+        cpi_seed,
+    )?;
+
+    emit!(DelegateChanged {
+        pool: pool.key(),
+        delegate: *delegate.key,
+    });
+    Ok(())
+}
+
+/// Freezes a holder's IOU account, signed by the pool (the freeze authority of its IOU mints)
+/// IOUs minted by a deposit stay frozen until the deposit lock has passed, so they can't be moved to a
+/// wallet without a lock and withdrawn from there
+pub fn freeze_iou_account<'info>(
+    pool: &Account<'info, Pool>,
+    iou_mint: &AccountInfo<'info>,
+    iou_account: &Account<'info, TokenAccount>,
+    token_program: &AccountInfo<'info>,
+) -> ProgramResult {
+    let seeds = &[
+        &pool.pool_name.as_ref(),
+        pool.admin.as_ref(),
+        &[pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    let freeze_accounts = FreezeAccount {
+        account: iou_account.to_account_info(),
+        mint: iou_mint.clone(),
+        authority: pool.to_account_info(),
+    };
+    token::freeze_account(CpiContext::new_with_signer(
+        token_program.clone(),
+        freeze_accounts,
+        cpi_seed,
+    ))
+}
+
+/// Thaws a holder's IOU account if it is frozen, signed by the pool
+pub fn thaw_iou_account<'info>(
+    pool: &Account<'info, Pool>,
+    iou_mint: &AccountInfo<'info>,
+    iou_account: &Account<'info, TokenAccount>,
+    token_program: &AccountInfo<'info>,
+) -> ProgramResult {
+    if !iou_account.is_frozen() {
+        return Ok(());
+    }
+    let seeds = &[
+        &pool.pool_name.as_ref(),
+        pool.admin.as_ref(),
+        &[pool.pool_bump],
+    ];
+    let cpi_seed = &[&seeds[..]];
+
+    let thaw_accounts = ThawAccount {
+        account: iou_account.to_account_info(),
+        mint: iou_mint.clone(),
+        authority: pool.to_account_info(),
+    };
+    token::thaw_account(CpiContext::new_with_signer(
+        token_program.clone(),
+        thaw_accounts,
+        cpi_seed,
+    ))
+}
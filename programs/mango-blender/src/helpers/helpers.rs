@@ -1,127 +1,310 @@
 use anchor_lang::prelude::*;
 use fixed::types::I80F48;
-use mango::error::MangoResult;
+use mango::instruction::MangoInstruction;
 use mango::state::{
-    load_open_orders, MangoAccount, MangoCache, MangoGroup, RootBankCache, UserActiveAssets,
-    MAX_PAIRS, QUOTE_INDEX, ZERO_I80F48,
+    load_open_orders, AssetType, HealthCache, HealthType, MangoAccount, MangoCache, MangoGroup,
+    UserActiveAssets, MAX_PAIRS, MAX_TOKENS, QUOTE_INDEX, ZERO_I80F48,
 };
-use mango::utils::split_open_orders;
+use solana_program::sysvar::instructions::{
+    load_current_index_checked, load_instruction_at_checked,
+};
+
+use crate::blender::errors::BlenderError;
+use crate::blender::state::{write_price_feed, Pool, PoolStatus, PriceUpdate};
+use crate::helpers::valuation::{calculate_pool_value, get_mango_account_base_net};
+
+/// Assets whose cache entries have to be valid to value the pool: `extra` plus the MNGO token if accrued MNGO is counted
+pub fn pool_valuation_assets(
+    pool: &Pool,
+    mut extra: Vec<(AssetType, usize)>,
+) -> Vec<(AssetType, usize)> {
+    if let Some(mngo_index) = pool.mngo_index() {
+        extra.push((AssetType::Token, mngo_index));
+    }
+    extra
+}
+
+/// Rejects a pooled MangoAccount that is being liquidated, is bankrupt or is below maintenance health
+/// Pricing deposits or withdrawals off such an account would use a NAV that is about to drop
+pub fn check_mango_account_healthy(
+    mango_account: &MangoAccount,
+    mango_cache: &MangoCache,
+    mango_group: &MangoGroup,
+    open_orders_ais: &Vec<Option<&AccountInfo>>,
+    active_assets: &UserActiveAssets,
+) -> ProgramResult {
+    let healthy = is_mango_account_healthy(
+        mango_account,
+        mango_cache,
+        mango_group,
+        open_orders_ais,
+        active_assets,
+    )?;
+    require!(healthy, BlenderError::MangoAccountUnhealthy);
+    Ok(())
+}
 
-/// Calculates the total value of the pooled MangoAccount in QUOTE (includes open orders)
 #[inline(never)]
-pub fn calculate_pool_value(
+pub fn is_mango_account_healthy(
     mango_account: &MangoAccount,
     mango_cache: &MangoCache,
     mango_group: &MangoGroup,
-    open_orders_ais: Vec<Option<&AccountInfo>>,
+    open_orders_ais: &Vec<Option<&AccountInfo>>,
     active_assets: &UserActiveAssets,
-) -> I80F48 {
-    let mut pool_value_quote = ZERO_I80F48;
+) -> Result<bool, ProgramError> {
+    if mango_account.being_liquidated || mango_account.is_bankrupt {
+        return Ok(false);
+    }
+    let mut health_cache = HealthCache::new(active_assets.clone());
+    health_cache.init_vals(mango_group, mango_cache, mango_account, open_orders_ais)?;
+    let maint_health = health_cache.get_health(mango_group, HealthType::Maint);
+    Ok(maint_health >= ZERO_I80F48)
+}
 
-    for i in 0..MAX_PAIRS {
-        //spot
-        if active_assets.spot[i] {
-            let base_net =
-                get_mango_account_base_net(mango_account, &mango_cache.root_bank_cache[i], i);
-            let price = mango_cache.get_price(i);
-            let market_value_quote = get_spot_val_in_quote(
-                base_net,
-                price,
-                open_orders_ais[i],
-                mango_account.in_margin_basket[i],
-            )
-            .unwrap();
-            pool_value_quote += market_value_quote;
+/// Rejects a pooled MangoAccount that still has perp positions, resting perp orders, resting or unsettled
+/// Serum orders, borrows, or a deposit of a non-QUOTE token that a keeper could still sell
+/// Deposits under the lot size recorded by unwind_spot are dust, a token never unwound has to be at zero
+#[inline(never)]
+pub fn check_mango_account_flat(
+    pool: &Pool,
+    mango_account: &MangoAccount,
+    mango_cache: &MangoCache,
+    open_orders_ais: &Vec<Option<&AccountInfo>>,
+) -> ProgramResult {
+    for i in 0..MAX_TOKENS {
+        require!(
+            !mango_account.borrows[i].is_positive(),
+            BlenderError::PoolNotFlat
+        );
+        if i == QUOTE_INDEX {
+            continue;
         }
-        //perp
-        if active_assets.perps[i] {
-            let (perp_base, perp_quote) = mango_account.perp_accounts[i]
-                .get_val(
-                    &mango_group.perp_markets[i],
-                    &mango_cache.perp_market_cache[i],
-                    mango_cache.price_cache[i].price,
-                )
-                .unwrap();
-            pool_value_quote += perp_base + perp_quote;
+        let deposit = get_mango_account_base_net(mango_account, &mango_cache.root_bank_cache[i], i);
+        require!(
+            deposit.is_zero() || deposit < I80F48::from_num(pool.unwind_base_lot_sizes[i]),
+            BlenderError::PoolNotFlat
+        );
+    }
+    for i in 0..MAX_PAIRS {
+        let perp_account = &mango_account.perp_accounts[i];
+        require!(
+            perp_account.base_position == 0
+                && perp_account.bids_quantity == 0
+                && perp_account.asks_quantity == 0,
+            BlenderError::PoolNotFlat
+        );
+        if let Some(open_orders_ai) = open_orders_ais[i] {
+            let open_orders = load_open_orders(open_orders_ai)?;
+            require!(
+                open_orders.free_slot_bits == u128::MAX
+                    && open_orders.native_coin_total == 0
+                    && open_orders.native_pc_total == 0,
+                BlenderError::PoolNotFlat
+            );
         }
     }
+    Ok(())
+}
+
+/// Rejects a DEX program other than the Mango group's, Mango CPIs signed by the pool pass it along
+pub fn check_dex_program(mango_group: &MangoGroup, dex_program: &AccountInfo) -> ProgramResult {
+    require!(
+        *dex_program.key == mango_group.dex_program_id,
+        BlenderError::InvalidDexProgram
+    );
+    Ok(())
+}
+
+/// Re-loads the pooled MangoAccount after a withdraw CPI and checks the pool's own limits:
+/// - init health is still at least `floor_bps` of the pool value, e.g. 2000 bps keeps 20% headroom above Mango's init requirement
+/// - borrows are at most `max_leverage_bps` of the pool value
+/// A limit of 0 is not checked
+#[inline(never)]
+pub fn check_post_withdraw_health(
+    mango_program_id: &Pubkey,
+    mango_account_ai: &AccountInfo,
+    mango_group_ai: &AccountInfo,
+    mango_cache_ai: &AccountInfo,
+    open_orders: &[AccountInfo],
+    floor_bps: u16,
+    max_leverage_bps: u32,
+    mngo_index: Option<usize>,
+) -> ProgramResult {
+    let mango_account =
+        MangoAccount::load_checked(mango_account_ai, mango_program_id, mango_group_ai.key)?;
+    let mango_group = MangoGroup::load_checked(mango_group_ai, mango_program_id)?;
+    let mango_cache = MangoCache::load_checked(mango_cache_ai, mango_program_id, &mango_group)?;
+    let open_orders_ais = mango_account.checked_unpack_open_orders(&mango_group, open_orders)?;
+    let active_assets = UserActiveAssets::new(&mango_group, &mango_account, vec![]);
+
+    let mut health_cache = HealthCache::new(active_assets.clone());
+    health_cache.init_vals(&mango_group, &mango_cache, &mango_account, &open_orders_ais)?;
+    let init_health = health_cache.get_health(&mango_group, HealthType::Init);
+    let pool_value_quote = calculate_pool_value(
+        &mango_account,
+        &mango_cache,
+        &mango_group,
+        open_orders_ais,
+        &active_assets,
+        mngo_index,
+    )
+    .max(ZERO_I80F48);
+
+    if floor_bps > 0 {
+        let required_health =
+            pool_value_quote * I80F48::from_num(floor_bps) / I80F48::from_num(10_000);
+        require!(
+            init_health >= required_health,
+            BlenderError::HealthFloorBreached
+        );
+    }
+    if max_leverage_bps > 0 {
+        let max_borrows =
+            pool_value_quote * I80F48::from_num(max_leverage_bps) / I80F48::from_num(10_000);
+        require!(
+            calculate_pool_borrows(&mango_account, &mango_cache) <= max_borrows,
+            BlenderError::LeverageTooHigh
+        );
+    }
+    Ok(())
+}
+
+/// Re-loads the pooled MangoAccount after a trade and checks that its gross exposure is at most
+/// `max_leverage_bps` of the pool value
+#[inline(never)]
+pub fn check_post_trade_leverage(
+    mango_program_id: &Pubkey,
+    mango_account_ai: &AccountInfo,
+    mango_group_ai: &AccountInfo,
+    mango_cache_ai: &AccountInfo,
+    open_orders: &[AccountInfo],
+    max_leverage_bps: u32,
+    mngo_index: Option<usize>,
+) -> ProgramResult {
+    let mango_account =
+        MangoAccount::load_checked(mango_account_ai, mango_program_id, mango_group_ai.key)?;
+    let mango_group = MangoGroup::load_checked(mango_group_ai, mango_program_id)?;
+    let mango_cache = MangoCache::load_checked(mango_cache_ai, mango_program_id, &mango_group)?;
+    let open_orders_ais = mango_account.checked_unpack_open_orders(&mango_group, open_orders)?;
+    let active_assets = UserActiveAssets::new(&mango_group, &mango_account, vec![]);
 
-    //quote
-    let quote_value = get_mango_account_base_net(
+    let exposure_quote = calculate_pool_exposure(&mango_account, &mango_cache, &mango_group);
+    let pool_value_quote = calculate_pool_value(
+        &mango_account,
+        &mango_cache,
+        &mango_group,
+        open_orders_ais,
+        &active_assets,
+        mngo_index,
+    )
+    .max(ZERO_I80F48);
+    let max_exposure =
+        pool_value_quote * I80F48::from_num(max_leverage_bps) / I80F48::from_num(10_000);
+    require!(
+        exposure_quote <= max_exposure,
+        BlenderError::LeverageTooHigh
+    );
+    Ok(())
+}
+
+/// Calculates the gross exposure of the pooled MangoAccount in QUOTE:
+/// the absolute value of every spot balance and perp position at oracle price, plus any QUOTE borrowed
+pub fn calculate_pool_exposure(
+    mango_account: &MangoAccount,
+    mango_cache: &MangoCache,
+    mango_group: &MangoGroup,
+) -> I80F48 {
+    let mut exposure_quote = ZERO_I80F48;
+    for i in 0..MAX_PAIRS {
+        let price = mango_cache.get_price(i);
+        let base_net =
+            get_mango_account_base_net(mango_account, &mango_cache.root_bank_cache[i], i);
+        exposure_quote += base_net.abs() * price;
+        exposure_quote += get_perp_position_notional(
+            mango_account.perp_accounts[i].base_position,
+            mango_group.perp_markets[i].base_lot_size,
+            price,
+        );
+    }
+    let quote_net = get_mango_account_base_net(
         mango_account,
         &mango_cache.root_bank_cache[QUOTE_INDEX],
         QUOTE_INDEX,
     );
-    pool_value_quote += quote_value;
-    pool_value_quote
+    if quote_net.is_negative() {
+        exposure_quote -= quote_net;
+    }
+    exposure_quote
 }
 
-/// Copypasta of private fn get_net in mango-v3
-pub fn get_mango_account_base_net(
-    mango_account: &MangoAccount,
-    bank_cache: &RootBankCache,
-    token_index: usize,
-) -> I80F48 {
-    if mango_account.deposits[token_index].is_positive() {
-        mango_account.deposits[token_index]
-            .checked_mul(bank_cache.deposit_index)
-            .unwrap()
-    } else if mango_account.borrows[token_index].is_positive() {
-        -mango_account.borrows[token_index]
-            .checked_mul(bank_cache.borrow_index)
-            .unwrap()
-    } else {
-        ZERO_I80F48
+/// Absolute QUOTE value of a perp position given in base lots
+pub fn get_perp_position_notional(base_lots: i64, base_lot_size: i64, price: I80F48) -> I80F48 {
+    I80F48::from_num(base_lots).abs() * I80F48::from_num(base_lot_size) * price
+}
+
+/// Calculates the value of everything the pooled MangoAccount has borrowed in QUOTE
+pub fn calculate_pool_borrows(mango_account: &MangoAccount, mango_cache: &MangoCache) -> I80F48 {
+    let mut borrows_quote = ZERO_I80F48;
+    for i in 0..MAX_TOKENS {
+        if mango_account.borrows[i].is_positive() {
+            borrows_quote += mango_account.borrows[i]
+                .checked_mul(mango_cache.root_bank_cache[i].borrow_index)
+                .unwrap()
+                * mango_cache.get_price(i);
+        }
     }
+    borrows_quote
+}
+
+/// Floors a QUOTE value to native units, a negative value is treated as zero
+pub fn quote_to_native(value_quote: I80F48) -> u64 {
+    value_quote
+        .max(ZERO_I80F48)
+        .checked_floor()
+        .unwrap()
+        .checked_to_num()
+        .unwrap_or(u64::MAX)
 }
 
-/// Copypasta of private fn get_spot_val in mango-v3
-/// Return the value (in quote tokens) for this market taking into account open orders
-/// but not doing asset weighting
-pub fn get_spot_val_in_quote(
-    base_net: I80F48,
-    price: I80F48,
-    open_orders_ai: Option<&AccountInfo>,
-    in_margin_basket: bool,
-) -> MangoResult<I80F48> {
-    if !in_margin_basket || open_orders_ai.is_none() {
-        Ok(base_net * price)
-    } else {
-        let open_orders = load_open_orders(open_orders_ai.unwrap())?;
-        let (quote_free, quote_locked, base_free, base_locked) = split_open_orders(&open_orders);
-
-        // Two "worst-case" scenarios are considered:
-        // 1. All bids are executed at current price, producing a base amount of bids_base_net
-        //    when all quote_locked are converted to base.
-        // 2. All asks are executed at current price, producing a base amount of asks_base_net
-        //    because base_locked would be converted to quote.
-        let bids_base_net: I80F48 = base_net + base_free + base_locked + quote_locked / price;
-        let asks_base_net = base_net + base_free;
-
-        // Report the scenario that would have a worse outcome on health.
-        //
-        // Explanation: This function returns (base, quote) and the values later get used in
-        //     health += (if base > 0 { asset_weight } else { liab_weight }) * base + quote
-        // and here we return the scenario that will increase health the least.
-        //
-        // Correctness proof:
-        // - always bids_base_net >= asks_base_net
-        // - note that scenario 1 returns (a + b, c)
-        //         and scenario 2 returns (a,     c + b), and b >= 0, c >= 0
-        // - if a >= 0: scenario 1 will lead to less health as asset_weight <= 1.
-        // - if a < 0 and b <= -a: scenario 2 will lead to less health as liab_weight >= 1.
-        // - if a < 0 and b > -a:
-        //   The health contributions of both scenarios are identical if
-        //       asset_weight * (a + b) + c = liab_weight * a + c + b
-        //   <=> b = (asset_weight - liab_weight) / (1 - asset_weight) * a
-        //   <=> b = -2 a  since asset_weight + liab_weight = 2 by weight construction
-        //   So the worse scenario switches when a + b = -a.
-        // That means scenario 1 leads to less health whenever |a + b| > |a|.
-
-        if bids_base_net.abs() > asks_base_net.abs() {
-            Ok((bids_base_net * price) + quote_free)
-        } else {
-            Ok((asks_base_net * price) + (base_locked * price + quote_free + quote_locked))
+/// Uses instruction introspection to make sure no Mango instruction that moves the cache or oracle prices
+/// ran earlier in this transaction (e.g. a depositor refreshing a price they just pushed)
+/// Only top-level instructions are visible: a cache update made through CPI (by another program's instruction,
+/// or by a program that calls buy_into_pool) goes unseen, the deposit lock is what covers those
+pub fn check_no_prior_cache_update(instructions_ai: &AccountInfo) -> ProgramResult {
+    let current_index = load_current_index_checked(instructions_ai)? as usize;
+    for i in 0..current_index {
+        let ix = load_instruction_at_checked(i, instructions_ai)?;
+        if ix.program_id != mango::ID {
+            continue;
+        }
+        match MangoInstruction::unpack(&ix.data) {
+            Some(MangoInstruction::CacheRootBanks { .. })
+            | Some(MangoInstruction::CachePrices { .. })
+            | Some(MangoInstruction::CachePerpMarkets { .. })
+            | Some(MangoInstruction::UpdateRootBank { .. })
+            | Some(MangoInstruction::UpdateFunding { .. })
+            | Some(MangoInstruction::SetOracle { .. }) => {
+                return Err(BlenderError::CacheUpdatedInTransaction.into())
+            }
+            _ => {}
         }
     }
+    Ok(())
+}
+
+/// Publishes the pool's NAV per IOU to its price account, the price is halted unless the pool is active
+pub fn publish_nav_price(
+    price_feed_ai: &AccountInfo,
+    pool: &Pool,
+    nav_per_iou: I80F48,
+    quote_decimals: u8,
+) -> ProgramResult {
+    let update = PriceUpdate::new(
+        nav_per_iou,
+        quote_decimals,
+        pool.price_confidence_bps,
+        pool.status == PoolStatus::Active,
+        &Clock::get()?,
+    )?;
+    write_price_feed(&mut price_feed_ai.try_borrow_mut_data()?, &update)
 }
@@ -1,3 +1,9 @@
+pub use cpi::*;
 pub use helpers::*;
+pub use valuation::*;
 
+pub mod cpi;
 pub mod helpers;
+#[cfg(feature = "no-entrypoint")]
+pub mod quote;
+pub mod valuation;
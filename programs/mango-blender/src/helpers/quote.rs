@@ -0,0 +1,236 @@
+//! Off-chain deposit and withdrawal quotes
+//!
+//! Loads the Mango accounts from raw account data and runs the same valuation and IOU math as
+//! `buy_into_pool` and `withdraw_from_pool`, so the results match the program bit for bit.
+//! Quotes don't apply the withdrawal window, a withdrawal can still be capped (and queued) by it.
+//! In a pool with a senior tranche IOUs are priced off the value of the tranche being quoted.
+
+use anchor_lang::prelude::*;
+use bytemuck::Pod;
+use fixed::types::I80F48;
+use mango::state::{
+    AssetType, HealthCache, HealthType, MangoAccount, MangoCache, MangoGroup, UserActiveAssets,
+    MAX_PAIRS, QUOTE_INDEX,
+};
+
+use crate::blender::errors::BlenderError;
+use crate::blender::state::{NavSnapshot, Pool, Tranche};
+use crate::helpers::{
+    calculate_iou_burn_amount, calculate_iou_mint_amount, calculate_pool_value,
+    pool_valuation_assets,
+};
+
+/// Raw data of the accounts the pool is valued from
+pub struct PoolAccountsData<'a> {
+    pub mango_group: &'a [u8],
+    pub mango_account: &'a [u8],
+    pub mango_cache: &'a [u8],
+    /// Open orders account data by market index, `None` where the MangoAccount has none
+    pub open_orders: [Option<&'a [u8]>; MAX_PAIRS],
+}
+
+/// Everything a quote depends on
+pub struct QuoteInput<'a> {
+    pub pool: &'a Pool,
+    /// The tranche being quoted, `Tranche::Junior` for pools without a senior tranche
+    pub tranche: Tranche,
+    /// Supply of the tranche's IOU mint
+    pub iou_supply: u64,
+    pub accounts: PoolAccountsData<'a>,
+    /// The pool's NAV snapshot, used like on chain while it is at most `pool.nav_max_age_slots` old at `slot`
+    pub nav_snapshot: Option<&'a NavSnapshot>,
+    pub slot: u64,
+    /// Unix timestamp the senior claim is accrued to
+    pub unix_timestamp: i64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepositQuote {
+    pub pool_value: I80F48,
+    pub tranche_value: I80F48,
+    pub deposit_value: I80F48,
+    pub ious_minted: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WithdrawQuote {
+    pub pool_value: I80F48,
+    pub tranche_value: I80F48,
+    pub ious_burned: u64,
+}
+
+/// Value of the pooled MangoAccount in native QUOTE, as calculated by refresh_nav
+pub fn quote_pool_value(input: &QuoteInput) -> Result<I80F48, ProgramError> {
+    live_pool_value(input, vec![])
+}
+
+/// IOUs minted for depositing `quantity` native units of `mint`
+pub fn quote_deposit(
+    input: &QuoteInput,
+    mint: &Pubkey,
+    quantity: u64,
+) -> Result<DepositQuote, ProgramError> {
+    let mango_group = AlignedData::new(input.accounts.mango_group);
+    let mango_group: &MangoGroup = mango_group.load()?;
+    let mango_cache = AlignedData::new(input.accounts.mango_cache);
+    let mango_cache: &MangoCache = mango_cache.load()?;
+    let token_index = mango_group
+        .find_token_index(mint)
+        .ok_or(BlenderError::UnknownMarket)?;
+
+    let deposit_value = I80F48::from_num(quantity) * mango_cache.get_price(token_index);
+    let pool_value = pool_value(
+        input,
+        vec![
+            (AssetType::Token, QUOTE_INDEX),
+            (AssetType::Token, token_index),
+        ],
+    )?;
+    let tranche_value = tranche_value(input, pool_value);
+    if input.iou_supply > 0 && tranche_value <= 0 {
+        return Err(BlenderError::TrancheWipedOut.into());
+    }
+    let ious_minted = calculate_iou_mint_amount(
+        deposit_value,
+        tranche_value,
+        I80F48::from_num(input.iou_supply),
+    );
+    Ok(DepositQuote {
+        pool_value,
+        tranche_value,
+        deposit_value,
+        ious_minted,
+    })
+}
+
+/// IOUs burned for withdrawing `quantity` native QUOTE
+pub fn quote_withdraw(input: &QuoteInput, quantity: u64) -> Result<WithdrawQuote, ProgramError> {
+    let pool_value = pool_value(input, vec![(AssetType::Token, QUOTE_INDEX)])?;
+    let tranche_value = tranche_value(input, pool_value);
+    let ious_burned = calculate_iou_burn_amount(
+        I80F48::from_num(quantity),
+        tranche_value,
+        I80F48::from_num(input.iou_supply),
+    );
+    Ok(WithdrawQuote {
+        pool_value,
+        tranche_value,
+        ious_burned,
+    })
+}
+
+/// The snapshot's value while it is fresh, the live value otherwise
+fn pool_value(input: &QuoteInput, extra: Vec<(AssetType, usize)>) -> Result<I80F48, ProgramError> {
+    let cached_pool_value = input
+        .nav_snapshot
+        .and_then(|snapshot| snapshot.cached_pool_value(input.pool, input.iou_supply, input.slot));
+    match cached_pool_value {
+        Some(pool_value) => Ok(pool_value),
+        None => live_pool_value(input, extra),
+    }
+}
+
+fn tranche_value(input: &QuoteInput, pool_value: I80F48) -> I80F48 {
+    input
+        .pool
+        .tranche_value(input.tranche, pool_value, input.unix_timestamp)
+}
+
+fn live_pool_value(
+    input: &QuoteInput,
+    extra: Vec<(AssetType, usize)>,
+) -> Result<I80F48, ProgramError> {
+    let mango_group = AlignedData::new(input.accounts.mango_group);
+    let mango_group: &MangoGroup = mango_group.load()?;
+    let mango_account = AlignedData::new(input.accounts.mango_account);
+    let mango_account: &MangoAccount = mango_account.load()?;
+    let mango_cache = AlignedData::new(input.accounts.mango_cache);
+    let mango_cache: &MangoCache = mango_cache.load()?;
+
+    let active_assets = UserActiveAssets::new(
+        mango_group,
+        mango_account,
+        pool_valuation_assets(input.pool, extra),
+    );
+    Ok(with_open_orders(
+        &input.accounts.open_orders,
+        |open_orders_ais| {
+            calculate_pool_value(
+                mango_account,
+                mango_cache,
+                mango_group,
+                open_orders_ais,
+                &active_assets,
+                input.pool.mngo_index(),
+            )
+        },
+    ))
+}
+
+/// Init and maint health of the pooled MangoAccount
+pub fn quote_health(input: &QuoteInput) -> Result<(I80F48, I80F48), ProgramError> {
+    let mango_group = AlignedData::new(input.accounts.mango_group);
+    let mango_group: &MangoGroup = mango_group.load()?;
+    let mango_account = AlignedData::new(input.accounts.mango_account);
+    let mango_account: &MangoAccount = mango_account.load()?;
+    let mango_cache = AlignedData::new(input.accounts.mango_cache);
+    let mango_cache: &MangoCache = mango_cache.load()?;
+
+    let active_assets = UserActiveAssets::new(
+        mango_group,
+        mango_account,
+        pool_valuation_assets(input.pool, vec![]),
+    );
+    with_open_orders(&input.accounts.open_orders, |open_orders_ais| {
+        let mut health_cache = HealthCache::new(active_assets);
+        health_cache.init_vals(mango_group, mango_cache, mango_account, &open_orders_ais)?;
+        Ok((
+            health_cache.get_health(mango_group, HealthType::Init),
+            health_cache.get_health(mango_group, HealthType::Maint),
+        ))
+    })
+}
+
+/// Mango reads open orders through AccountInfos, only their data is used
+fn with_open_orders<R>(
+    open_orders: &[Option<&[u8]>; MAX_PAIRS],
+    f: impl FnOnce(Vec<Option<&AccountInfo>>) -> R,
+) -> R {
+    let key = Pubkey::default();
+    let mut lamports = [0u64; MAX_PAIRS];
+    let mut open_orders_data: Vec<Option<Vec<u8>>> = open_orders
+        .iter()
+        .map(|data| data.map(|data| data.to_vec()))
+        .collect();
+    let open_orders_infos: Vec<Option<AccountInfo>> = open_orders_data
+        .iter_mut()
+        .zip(lamports.iter_mut())
+        .map(|(data, lamports)| {
+            data.as_mut()
+                .map(|data| AccountInfo::new(&key, false, false, lamports, data, &key, false, 0))
+        })
+        .collect();
+    f(open_orders_infos.iter().map(|info| info.as_ref()).collect())
+}
+
+/// Account data read over RPC isn't necessarily aligned for the Mango structs, this copies it into a buffer that is
+pub struct AlignedData {
+    buffer: Vec<u128>,
+    len: usize,
+}
+
+impl AlignedData {
+    pub fn new(data: &[u8]) -> Self {
+        let mut buffer = vec![0u128; (data.len() + 15) / 16];
+        bytemuck::cast_slice_mut::<u128, u8>(&mut buffer)[..data.len()].copy_from_slice(data);
+        AlignedData {
+            buffer,
+            len: data.len(),
+        }
+    }
+
+    pub fn load<T: Pod>(&self) -> Result<&T, ProgramError> {
+        let data = &bytemuck::cast_slice::<u128, u8>(&self.buffer)[..self.len];
+        bytemuck::try_from_bytes(data).map_err(|_| ProgramError::InvalidAccountData)
+    }
+}
@@ -0,0 +1,200 @@
+use anchor_lang::prelude::*;
+use fixed::types::I80F48;
+use mango::error::MangoResult;
+use mango::state::{
+    load_open_orders, MangoAccount, MangoCache, MangoGroup, RootBankCache, UserActiveAssets,
+    MAX_PAIRS, ONE_I80F48, QUOTE_INDEX, ZERO_I80F48,
+};
+use mango::utils::split_open_orders;
+
+/// Calculates the total value of the pooled MangoAccount in QUOTE (includes open orders)
+/// With `mngo_index` set, MNGO rewards accrued on perp markets but not yet redeemed are valued at the MNGO oracle price
+#[inline(never)]
+pub fn calculate_pool_value(
+    mango_account: &MangoAccount,
+    mango_cache: &MangoCache,
+    mango_group: &MangoGroup,
+    open_orders_ais: Vec<Option<&AccountInfo>>,
+    active_assets: &UserActiveAssets,
+    mngo_index: Option<usize>,
+) -> I80F48 {
+    let mut pool_value_quote = ZERO_I80F48;
+
+    for i in 0..MAX_PAIRS {
+        //spot
+        if active_assets.spot[i] {
+            let base_net =
+                get_mango_account_base_net(mango_account, &mango_cache.root_bank_cache[i], i);
+            let price = mango_cache.get_price(i);
+            let market_value_quote = get_spot_val_in_quote(
+                base_net,
+                price,
+                open_orders_ais[i],
+                mango_account.in_margin_basket[i],
+            )
+            .unwrap();
+            pool_value_quote += market_value_quote;
+        }
+        //perp
+        if active_assets.perps[i] {
+            let (perp_base, perp_quote) = mango_account.perp_accounts[i]
+                .get_val(
+                    &mango_group.perp_markets[i],
+                    &mango_cache.perp_market_cache[i],
+                    mango_cache.price_cache[i].price,
+                )
+                .unwrap();
+            pool_value_quote += perp_base + perp_quote;
+        }
+    }
+
+    //quote
+    let quote_value = get_mango_account_base_net(
+        mango_account,
+        &mango_cache.root_bank_cache[QUOTE_INDEX],
+        QUOTE_INDEX,
+    );
+    pool_value_quote += quote_value;
+
+    //accrued mngo
+    if let Some(mngo_index) = mngo_index {
+        let mngo_accrued: u64 = mango_account
+            .perp_accounts
+            .iter()
+            .map(|perp_account| perp_account.mngo_accrued)
+            .sum();
+        pool_value_quote += I80F48::from_num(mngo_accrued) * mango_cache.get_price(mngo_index);
+    }
+    pool_value_quote
+}
+
+/// Copypasta of private fn get_net in mango-v3
+pub fn get_mango_account_base_net(
+    mango_account: &MangoAccount,
+    bank_cache: &RootBankCache,
+    token_index: usize,
+) -> I80F48 {
+    if mango_account.deposits[token_index].is_positive() {
+        mango_account.deposits[token_index]
+            .checked_mul(bank_cache.deposit_index)
+            .unwrap()
+    } else if mango_account.borrows[token_index].is_positive() {
+        -mango_account.borrows[token_index]
+            .checked_mul(bank_cache.borrow_index)
+            .unwrap()
+    } else {
+        ZERO_I80F48
+    }
+}
+
+/// Copypasta of private fn get_spot_val in mango-v3
+/// Return the value (in quote tokens) for this market taking into account open orders
+/// but not doing asset weighting
+pub fn get_spot_val_in_quote(
+    base_net: I80F48,
+    price: I80F48,
+    open_orders_ai: Option<&AccountInfo>,
+    in_margin_basket: bool,
+) -> MangoResult<I80F48> {
+    if !in_margin_basket || open_orders_ai.is_none() {
+        Ok(base_net * price)
+    } else {
+        let open_orders = load_open_orders(open_orders_ai.unwrap())?;
+        let (quote_free, quote_locked, base_free, base_locked) = split_open_orders(&open_orders);
+
+        // Two "worst-case" scenarios are considered:
+        // 1. All bids are executed at current price, producing a base amount of bids_base_net
+        //    when all quote_locked are converted to base.
+        // 2. All asks are executed at current price, producing a base amount of asks_base_net
+        //    because base_locked would be converted to quote.
+        let bids_base_net: I80F48 = base_net + base_free + base_locked + quote_locked / price;
+        let asks_base_net = base_net + base_free;
+
+        // Report the scenario that would have a worse outcome on health.
+        //
+        // Explanation: This function returns (base, quote) and the values later get used in
+        //     health += (if base > 0 { asset_weight } else { liab_weight }) * base + quote
+        // and here we return the scenario that will increase health the least.
+        //
+        // Correctness proof:
+        // - always bids_base_net >= asks_base_net
+        // - note that scenario 1 returns (a + b, c)
+        //         and scenario 2 returns (a,     c + b), and b >= 0, c >= 0
+        // - if a >= 0: scenario 1 will lead to less health as asset_weight <= 1.
+        // - if a < 0 and b <= -a: scenario 2 will lead to less health as liab_weight >= 1.
+        // - if a < 0 and b > -a:
+        //   The health contributions of both scenarios are identical if
+        //       asset_weight * (a + b) + c = liab_weight * a + c + b
+        //   <=> b = (asset_weight - liab_weight) / (1 - asset_weight) * a
+        //   <=> b = -2 a  since asset_weight + liab_weight = 2 by weight construction
+        //   So the worse scenario switches when a + b = -a.
+        // That means scenario 1 leads to less health whenever |a + b| > |a|.
+
+        if bids_base_net.abs() > asks_base_net.abs() {
+            Ok((bids_base_net * price) + quote_free)
+        } else {
+            Ok((asks_base_net * price) + (base_locked * price + quote_free + quote_locked))
+        }
+    }
+}
+
+/// Calculate how many iou tokens should be issued for a deposit
+/// We want to ensure that a depositor always purchases a proportion of the pool that is determined by the pool value at time of deposit
+/// e.g. If the pool is worth $90 and I deposit $10, I should own 10% of all minted iou tokens
+///
+/// To achieve this: (deposit value / old pool + deposit value) = (new iou tokens / old + new iou tokens)
+///
+/// Implying: new iou tokens = (deposit *  old iou tokens) / (old pool value)
+pub fn calculate_iou_mint_amount(
+    deposit_value_quote: I80F48,
+    pool_value_quote: I80F48,
+    outstanding_iou_tokens: I80F48,
+) -> u64 {
+    if outstanding_iou_tokens == 0 {
+        let mint_amount: u64 = deposit_value_quote
+            .checked_floor()
+            .unwrap()
+            .checked_to_num()
+            .unwrap();
+        mint_amount
+    } else {
+        let mint_amount: u64 = ((deposit_value_quote * outstanding_iou_tokens)
+            / (pool_value_quote))
+            .checked_floor()
+            .unwrap()
+            .checked_to_num()
+            .unwrap();
+        mint_amount
+    }
+}
+
+/// Calculate how many iou tokens should be burned for a withdrawak
+/// We want to ensure that a withdrawer can only withdraw what they are entitled to and that they burn the correct amount of iou tokens
+/// e.g. If the pool is worth $100 and I own 10% of all minted iou tokens, I should be entitled to withdraw $10 worth of quote (aka 10% of the pool)
+///
+/// To achieve this: (withdraw value / starting pool value) = (my burnable iou tokens / outstanding iou tokens)
+pub fn calculate_iou_burn_amount(
+    withdraw_value_quote: I80F48,
+    pool_value_quote: I80F48,
+    outstanding_iou_tokens: I80F48,
+) -> u64 {
+    // Rounds up against the exact share, truncation in the division alone can let a withdrawal burn one IOU too few
+    let burn_amount = match withdraw_value_quote.checked_mul(outstanding_iou_tokens) {
+        Some(burn_value) => {
+            let mut burn_amount = (burn_value / pool_value_quote).checked_floor().unwrap();
+            while burn_amount * pool_value_quote < burn_value {
+                burn_amount += ONE_I80F48;
+            }
+            burn_amount
+        }
+        // too large to multiply first: divide first, then add the most that division can have truncated
+        // (one unit in the last place per outstanding IOU)
+        None => {
+            let truncation = I80F48::from_bits(outstanding_iou_tokens.to_bits() >> 48);
+            ((withdraw_value_quote / pool_value_quote) * outstanding_iou_tokens + truncation)
+                .checked_ceil()
+                .unwrap()
+        }
+    };
+    burn_amount.checked_to_num().unwrap()
+}
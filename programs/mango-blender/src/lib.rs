@@ -1,8 +1,9 @@
 use anchor_lang::prelude::*;
 use blender::instructions::*;
+use blender::state::RiskLimits;
 
-mod blender;
-mod helpers;
+pub mod blender;
+pub mod helpers;
 
 declare_id!("HzJMW7y12YSPDZMWNeqKDR51QnHwhF3TB96CZsPhpNoB");
 
@@ -24,6 +25,13 @@ pub mod mango_blender {
         )
     }
 
+    pub fn create_depositor_position(
+        ctx: Context<CreateDepositorPosition>,
+        bump: u8,
+    ) -> ProgramResult {
+        blender::instructions::create_depositor_position::handler(ctx, bump)
+    }
+
     pub fn buy_into_pool(ctx: Context<BuyIntoPool>, quantity: u64) -> ProgramResult {
         blender::instructions::buy_into_pool::handler(ctx, quantity)
     }
@@ -34,4 +42,331 @@ pub mod mango_blender {
     ) -> ProgramResult {
         blender::instructions::withdraw_from_pool::handler(ctx, quantity)
     }
+
+    pub fn process_queued_withdrawal<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, WithdrawFromPool<'info>>,
+    ) -> ProgramResult {
+        blender::instructions::withdraw_from_pool::process_queued_withdrawal(ctx)
+    }
+
+    pub fn withdraw_with_unwind<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, WithdrawWithUnwind<'info>>,
+        quantity: u64,
+        max_slippage_bps: u16,
+    ) -> ProgramResult {
+        blender::instructions::withdraw_with_unwind::handler(ctx, quantity, max_slippage_bps)
+    }
+
+    pub fn set_deposit_lock_slots(
+        ctx: Context<UpdatePool>,
+        deposit_lock_slots: u64,
+    ) -> ProgramResult {
+        blender::instructions::update_pool::set_deposit_lock_slots(ctx, deposit_lock_slots)
+    }
+
+    pub fn set_withdrawal_limit(
+        ctx: Context<UpdatePool>,
+        withdrawal_limit_bps: u16,
+    ) -> ProgramResult {
+        blender::instructions::update_pool::set_withdrawal_limit(ctx, withdrawal_limit_bps)
+    }
+
+    pub fn set_queue_unhealthy_withdrawals(
+        ctx: Context<UpdatePool>,
+        queue_unhealthy_withdrawals: bool,
+    ) -> ProgramResult {
+        blender::instructions::update_pool::set_queue_unhealthy_withdrawals(
+            ctx,
+            queue_unhealthy_withdrawals,
+        )
+    }
+
+    pub fn view_withdrawal_capacity(ctx: Context<ViewPool>) -> ProgramResult {
+        blender::instructions::view_pool::view_withdrawal_capacity(ctx)
+    }
+
+    pub fn set_withdraw_health_floor(
+        ctx: Context<UpdatePool>,
+        withdraw_health_floor_bps: u16,
+    ) -> ProgramResult {
+        blender::instructions::update_pool::set_withdraw_health_floor(
+            ctx,
+            withdraw_health_floor_bps,
+        )
+    }
+
+    pub fn set_max_withdraw_leverage(
+        ctx: Context<UpdatePool>,
+        max_withdraw_leverage_bps: u32,
+    ) -> ProgramResult {
+        blender::instructions::update_pool::set_max_withdraw_leverage(
+            ctx,
+            max_withdraw_leverage_bps,
+        )
+    }
+
+    pub fn set_risk_limits(ctx: Context<UpdatePool>, risk_limits: RiskLimits) -> ProgramResult {
+        blender::instructions::update_pool::set_risk_limits(ctx, risk_limits)
+    }
+
+    pub fn set_delegate(ctx: Context<SetDelegate>) -> ProgramResult {
+        blender::instructions::set_delegate::set_delegate(ctx)
+    }
+
+    pub fn enable_proxy_only(ctx: Context<SetDelegate>) -> ProgramResult {
+        blender::instructions::set_delegate::enable_proxy_only(ctx)
+    }
+
+    pub fn proxy_place_perp_order<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, ProxyPlacePerpOrder<'info>>,
+        side: OrderSide,
+        price: i64,
+        quantity: i64,
+        client_order_id: u64,
+        order_type: ProxyOrderType,
+        reduce_only: bool,
+    ) -> ProgramResult {
+        blender::instructions::proxy_trade::place_perp_order(
+            ctx,
+            side,
+            price,
+            quantity,
+            client_order_id,
+            order_type,
+            reduce_only,
+        )
+    }
+
+    pub fn proxy_cancel_perp_order(
+        ctx: Context<ProxyCancelPerpOrder>,
+        order_id: i128,
+        invalid_id_ok: bool,
+    ) -> ProgramResult {
+        blender::instructions::proxy_trade::cancel_perp_order(ctx, order_id, invalid_id_ok)
+    }
+
+    pub fn proxy_place_spot_order<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, ProxyPlaceSpotOrder<'info>>,
+        side: OrderSide,
+        limit_price: u64,
+        max_coin_qty: u64,
+        max_native_pc_qty_including_fees: u64,
+        client_order_id: u64,
+        order_type: ProxyOrderType,
+    ) -> ProgramResult {
+        blender::instructions::proxy_trade::place_spot_order(
+            ctx,
+            side,
+            limit_price,
+            max_coin_qty,
+            max_native_pc_qty_including_fees,
+            client_order_id,
+            order_type,
+        )
+    }
+
+    pub fn proxy_cancel_spot_order(
+        ctx: Context<ProxyCancelSpotOrder>,
+        side: OrderSide,
+        order_id: u128,
+    ) -> ProgramResult {
+        blender::instructions::proxy_trade::cancel_spot_order(ctx, side, order_id)
+    }
+
+    pub fn set_market_allowlist(
+        ctx: Context<UpdatePool>,
+        spot_markets: u16,
+        perp_markets: u16,
+    ) -> ProgramResult {
+        blender::instructions::update_pool::set_market_allowlist(ctx, spot_markets, perp_markets)
+    }
+
+    pub fn set_allowlist_timelock(
+        ctx: Context<UpdatePool>,
+        timelock_seconds: i64,
+    ) -> ProgramResult {
+        blender::instructions::update_pool::set_allowlist_timelock(ctx, timelock_seconds)
+    }
+
+    pub fn apply_market_allowlist(ctx: Context<ApplyMarketAllowlist>) -> ProgramResult {
+        blender::instructions::update_pool::apply_market_allowlist(ctx)
+    }
+
+    pub fn apply_risk_limits(ctx: Context<ApplyRiskLimits>) -> ProgramResult {
+        blender::instructions::update_pool::apply_risk_limits(ctx)
+    }
+
+    pub fn view_market_allowlist(ctx: Context<ViewPool>) -> ProgramResult {
+        blender::instructions::view_pool::view_market_allowlist(ctx)
+    }
+
+    pub fn set_max_drawdown(ctx: Context<UpdatePool>, max_drawdown_bps: u16) -> ProgramResult {
+        blender::instructions::update_pool::set_max_drawdown(ctx, max_drawdown_bps)
+    }
+
+    pub fn apply_max_drawdown(ctx: Context<ApplyMaxDrawdown>) -> ProgramResult {
+        blender::instructions::update_pool::apply_max_drawdown(ctx)
+    }
+
+    pub fn trip_circuit_breaker<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, TripCircuitBreaker<'info>>,
+    ) -> ProgramResult {
+        blender::instructions::circuit_breaker::handler(ctx)
+    }
+
+    pub fn start_wind_down(
+        ctx: Context<StartWindDown>,
+        wind_down_slippage_bps: u16,
+    ) -> ProgramResult {
+        blender::instructions::wind_down::start_wind_down(ctx, wind_down_slippage_bps)
+    }
+
+    pub fn unwind_perp<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, UnwindPerp<'info>>,
+        limit: u8,
+    ) -> ProgramResult {
+        blender::instructions::wind_down::unwind_perp(ctx, limit)
+    }
+
+    pub fn cancel_all_orders(ctx: Context<CancelAllOrders>, limit: u8) -> ProgramResult {
+        blender::instructions::wind_down::cancel_all_orders(ctx, limit)
+    }
+
+    pub fn unwind_spot<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, UnwindSpot<'info>>,
+    ) -> ProgramResult {
+        blender::instructions::wind_down::unwind_spot(ctx)
+    }
+
+    pub fn redeem<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, WithdrawFromPool<'info>>,
+        iou_amount: u64,
+    ) -> ProgramResult {
+        blender::instructions::wind_down::redeem(ctx, iou_amount)
+    }
+
+    pub fn settle_pnl(ctx: Context<SettlePnl>, market_index: u8) -> ProgramResult {
+        blender::instructions::crank::settle_pnl(ctx, market_index)
+    }
+
+    pub fn settle_funds(ctx: Context<SettleFunds>) -> ProgramResult {
+        blender::instructions::crank::settle_funds(ctx)
+    }
+
+    pub fn settle_fees(ctx: Context<SettleFees>) -> ProgramResult {
+        blender::instructions::crank::settle_fees(ctx)
+    }
+
+    pub fn redeem_mngo<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, RedeemMngo<'info>>,
+    ) -> ProgramResult {
+        blender::instructions::crank::redeem_mngo(ctx)
+    }
+
+    pub fn set_accrued_mngo_valuation(
+        ctx: Context<UpdatePool>,
+        value_accrued_mngo: bool,
+        mngo_token_index: u8,
+    ) -> ProgramResult {
+        blender::instructions::update_pool::set_accrued_mngo_valuation(
+            ctx,
+            value_accrued_mngo,
+            mngo_token_index,
+        )
+    }
+
+    pub fn create_nav_snapshot(ctx: Context<CreateNavSnapshot>, bump: u8) -> ProgramResult {
+        blender::instructions::refresh_nav::create_nav_snapshot(ctx, bump)
+    }
+
+    pub fn refresh_nav<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, RefreshNav<'info>>,
+    ) -> ProgramResult {
+        blender::instructions::refresh_nav::refresh_nav(ctx)
+    }
+
+    pub fn set_nav_max_age_slots(
+        ctx: Context<UpdatePool>,
+        nav_max_age_slots: u64,
+    ) -> ProgramResult {
+        blender::instructions::update_pool::set_nav_max_age_slots(ctx, nav_max_age_slots)
+    }
+
+    pub fn create_nav_history(ctx: Context<CreateNavHistory>) -> ProgramResult {
+        blender::instructions::refresh_nav::create_nav_history(ctx)
+    }
+
+    pub fn set_nav_history_interval(
+        ctx: Context<UpdatePool>,
+        nav_history_interval_seconds: i64,
+    ) -> ProgramResult {
+        blender::instructions::update_pool::set_nav_history_interval(
+            ctx,
+            nav_history_interval_seconds,
+        )
+    }
+
+    pub fn set_price_confidence(
+        ctx: Context<UpdatePool>,
+        price_confidence_bps: u16,
+    ) -> ProgramResult {
+        blender::instructions::update_pool::set_price_confidence(ctx, price_confidence_bps)
+    }
+
+    pub fn create_price_feed(ctx: Context<CreatePriceFeed>, bump: u8) -> ProgramResult {
+        blender::instructions::price_feed::create_price_feed(ctx, bump)
+    }
+
+    pub fn create_senior_tranche(
+        ctx: Context<CreateSeniorTranche>,
+        senior_iou_mint_bump: u8,
+        senior_rate_bps: u16,
+    ) -> ProgramResult {
+        blender::instructions::tranche::create_senior_tranche(
+            ctx,
+            senior_iou_mint_bump,
+            senior_rate_bps,
+        )
+    }
+
+    pub fn set_senior_rate(ctx: Context<UpdatePool>, senior_rate_bps: u16) -> ProgramResult {
+        blender::instructions::update_pool::set_senior_rate(ctx, senior_rate_bps)
+    }
+
+    pub fn set_distribution_mode(
+        ctx: Context<UpdatePool>,
+        distribution_mode: bool,
+        distribution_interval_seconds: i64,
+    ) -> ProgramResult {
+        blender::instructions::update_pool::set_distribution_mode(
+            ctx,
+            distribution_mode,
+            distribution_interval_seconds,
+        )
+    }
+
+    pub fn create_distribution_vault(
+        ctx: Context<CreateDistributionVault>,
+        bump: u8,
+    ) -> ProgramResult {
+        blender::instructions::distribution::create_distribution_vault(ctx, bump)
+    }
+
+    pub fn distribute<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, Distribute<'info>>,
+    ) -> ProgramResult {
+        blender::instructions::distribution::distribute(ctx)
+    }
+
+    pub fn claim_distribution(ctx: Context<ClaimDistribution>) -> ProgramResult {
+        blender::instructions::distribution::claim_distribution(ctx)
+    }
+
+    pub fn thaw_ious(ctx: Context<ThawIous>) -> ProgramResult {
+        blender::instructions::thaw_ious::handler(ctx)
+    }
+
+    pub fn transfer_ious(ctx: Context<TransferIous>, amount: u64) -> ProgramResult {
+        blender::instructions::transfer_ious::handler(ctx, amount)
+    }
 }
@@ -0,0 +1,676 @@
+// Compute budgets of the instructions that value or trade the whole pooled MangoAccount, in their worst case:
+// a balance in every token, a perp order on every market, a full margin basket of spot orders and
+// every optional check turned on. Run with `cargo test-bpf`, natively only Mango's share is metered,
+// and `-- --nocapture` to see the compute units each instruction consumed
+
+mod program_test;
+
+use mango::matching::Side as PerpSide;
+use mango::state::{MangoAccount, MAX_NUM_IN_MARGIN_BASKET, MAX_PAIRS, QUOTE_INDEX};
+use mango_blender::blender::instructions::OrderSide;
+use mango_blender::blender::state::{RiskLimits, WITHDRAWAL_WINDOW_SECONDS};
+use mango_blender::instruction;
+use program_test::*;
+use serum_dex::matching::Side as SpotSide;
+use solana_program_test::tokio;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+
+const DECIMALS: u8 = 6;
+const POOL_NAME: &str = "budgetpool";
+const SPOT_BASE_LOT: u64 = 1_000_000;
+const SPOT_QUOTE_LOT: u64 = 100;
+const PERP_BASE_LOT: i64 = 1_000_000;
+const PERP_QUOTE_LOT: i64 = 100_000;
+const ALL_MARKETS: u16 = (1 << MAX_PAIRS) - 1;
+// the pool's MNGO is token 0, listed like any other token
+const MNGO_INDEX: usize = 0;
+const WIND_DOWN_SLIPPAGE_BPS: u16 = 100;
+
+// Budgets in compute units, per transaction. 200k is what a transaction gets without requesting more,
+// an instruction that needs more can't be sent by clients that don't add a compute budget request
+const BUY_INTO_POOL_BUDGET: u32 = 200_000;
+const WITHDRAW_FROM_POOL_BUDGET: u32 = 200_000;
+const REFRESH_NAV_BUDGET: u32 = 200_000;
+const TRIP_CIRCUIT_BREAKER_BUDGET: u32 = 200_000;
+const PROCESS_QUEUED_WITHDRAWAL_BUDGET: u32 = 200_000;
+const WITHDRAW_WITH_UNWIND_BUDGET: u32 = 200_000;
+const PROXY_PLACE_PERP_ORDER_BUDGET: u32 = 200_000;
+const PROXY_CANCEL_PERP_ORDER_BUDGET: u32 = 200_000;
+const PROXY_PLACE_SPOT_ORDER_BUDGET: u32 = 200_000;
+const PROXY_CANCEL_SPOT_ORDER_BUDGET: u32 = 200_000;
+const UNWIND_PERP_BUDGET: u32 = 200_000;
+const UNWIND_SPOT_BUDGET: u32 = 200_000;
+const DISTRIBUTE_BUDGET: u32 = 200_000;
+const REDEEM_MNGO_BUDGET: u32 = 200_000;
+
+/// A pool using every market of a full Mango group, the admin (test payer) is also its only depositor
+struct WorstCase {
+    test: BlenderTest,
+    mango: MangoTestGroup,
+    spot_markets: Vec<SpotMarket>,
+    pool: BlenderPool,
+    depositor: Keypair,
+    quote_mint: Pubkey,
+    quote: Pubkey,
+    iou: Pubkey,
+}
+
+impl WorstCase {
+    async fn new() -> Self {
+        let mut test = BlenderTest::start().await;
+        let quote_mint = test.create_mint(DECIMALS).await;
+        let mut mango = MangoTestGroup::create(&mut test, &quote_mint, DECIMALS).await;
+
+        let depositor = test.payer_keypair();
+        let owner = depositor.pubkey();
+        let quote = test
+            .create_associated_token_account(&owner, &quote_mint)
+            .await;
+        test.mint_to(&quote_mint, &quote, 100_000_000).await;
+
+        let mut mints = vec![];
+        for _ in 0..MAX_PAIRS {
+            mints.push(test.create_mint(DECIMALS).await);
+        }
+        let mut spot_markets = vec![];
+        let mut token_accounts = vec![];
+        for mint in mints.iter() {
+            let oracle = mango.add_oracle(&mut test, 1.0).await;
+            let market =
+                SpotMarket::list(&mut test, mint, &quote_mint, SPOT_BASE_LOT, SPOT_QUOTE_LOT).await;
+            mango
+                .add_spot_market(&mut test, oracle, &market, DECIMALS)
+                .await;
+            mango
+                .add_perp_market(
+                    &mut test,
+                    oracle,
+                    &mints[MNGO_INDEX],
+                    PERP_BASE_LOT,
+                    PERP_QUOTE_LOT,
+                )
+                .await;
+            let token_account = test.create_associated_token_account(&owner, mint).await;
+            test.mint_to(mint, &token_account, 1_000_000).await;
+            spot_markets.push(market);
+            token_accounts.push(token_account);
+        }
+
+        let pool = BlenderPool::new(POOL_NAME, &owner, &mango);
+        pool.create(&mut test, &mango).await;
+        pool.create_nav_snapshot(&mut test).await;
+        pool.create_nav_history(&mut test).await;
+        pool.create_price_feed(&mut test).await;
+        pool.create_depositor_position(&mut test, &depositor).await;
+        pool.create_distribution_vault(&mut test, &mango).await;
+        pool.update_pool(
+            &mut test,
+            instruction::SetDistributionMode {
+                distribution_mode: true,
+                distribution_interval_seconds: 0,
+            },
+        )
+        .await
+        .unwrap();
+        let iou = test
+            .create_associated_token_account(&owner, &pool.iou_mint)
+            .await;
+
+        pool.update_pool(
+            &mut test,
+            instruction::SetMarketAllowlist {
+                spot_markets: ALL_MARKETS,
+                perp_markets: ALL_MARKETS,
+            },
+        )
+        .await
+        .unwrap();
+        pool.update_pool(
+            &mut test,
+            instruction::SetRiskLimits {
+                risk_limits: RiskLimits {
+                    max_position_notional: [1_000_000_000; MAX_PAIRS],
+                    max_leverage_bps: 50_000,
+                    max_order_notional: 1_000_000_000,
+                },
+            },
+        )
+        .await
+        .unwrap();
+        pool.update_pool(
+            &mut test,
+            instruction::SetAccruedMngoValuation {
+                value_accrued_mngo: true,
+                mngo_token_index: MNGO_INDEX as u8,
+            },
+        )
+        .await
+        .unwrap();
+        pool.update_pool(
+            &mut test,
+            instruction::SetWithdrawalLimit {
+                withdrawal_limit_bps: 10_000,
+            },
+        )
+        .await
+        .unwrap();
+        pool.update_pool(
+            &mut test,
+            instruction::SetWithdrawHealthFloor {
+                withdraw_health_floor_bps: 1_000,
+            },
+        )
+        .await
+        .unwrap();
+        pool.update_pool(
+            &mut test,
+            instruction::SetMaxWithdrawLeverage {
+                max_withdraw_leverage_bps: 10_000,
+            },
+        )
+        .await
+        .unwrap();
+        pool.update_pool(
+            &mut test,
+            instruction::SetMaxDrawdown {
+                max_drawdown_bps: 100,
+            },
+        )
+        .await
+        .unwrap();
+
+        // 100 QUOTE and 1 of every token
+        pool.buy_into_pool(
+            &mut test,
+            &mango,
+            &depositor,
+            &quote,
+            &iou,
+            &mango.quote,
+            100_000_000,
+        )
+        .await
+        .unwrap();
+        for (i, token_account) in token_accounts.iter().enumerate() {
+            let token = *mango.token(i);
+            pool.buy_into_pool(
+                &mut test,
+                &mango,
+                &depositor,
+                token_account,
+                &iou,
+                &token,
+                1_000_000,
+            )
+            .await
+            .unwrap();
+        }
+
+        // resting bids at half the oracle price: spot on as many markets as the margin basket takes, perp on all of them
+        mango.keeper_refresh(&mut test).await;
+        for (i, market) in spot_markets
+            .iter()
+            .take(MAX_NUM_IN_MARGIN_BASKET as usize)
+            .enumerate()
+        {
+            mango
+                .init_spot_open_orders(&mut test, &pool.mango_account, &depositor, market)
+                .await;
+            mango
+                .place_spot_order(
+                    &mut test,
+                    &pool.mango_account,
+                    &depositor,
+                    market,
+                    SpotSide::Bid,
+                    market.price_lots(0.5, DECIMALS, DECIMALS),
+                    1,
+                    i as u64,
+                )
+                .await;
+        }
+        let perp_price = (0.5 * PERP_BASE_LOT as f64 / PERP_QUOTE_LOT as f64) as i64;
+        for (i, perp_market) in mango.perp_markets.iter().enumerate() {
+            mango
+                .place_perp_order(
+                    &mut test,
+                    &pool.mango_account,
+                    &depositor,
+                    perp_market,
+                    PerpSide::Bid,
+                    perp_price,
+                    1,
+                    i as u64,
+                )
+                .await
+                .unwrap();
+        }
+
+        WorstCase {
+            test,
+            mango,
+            spot_markets,
+            pool,
+            depositor,
+            quote_mint,
+            quote,
+            iou,
+        }
+    }
+
+    /// Sends `instruction` (signed by the test payer) capped at `budget` compute units, logging what it consumed
+    async fn assert_within_budget(&mut self, name: &str, instruction: Instruction, budget: u32) {
+        let units = self
+            .test
+            .process_measured(&[instruction], &[], budget)
+            .await
+            .unwrap_or_else(|e| panic!("{} failed within {} compute units: {:?}", name, budget, e));
+        println!("{}: {} of {} compute units", name, units, budget);
+    }
+
+    /// A trader with 100 QUOTE in their wallet
+    async fn trader(&mut self) -> (Keypair, Pubkey) {
+        let trader = self.test.create_user(1_000_000_000).await;
+        let quote = self
+            .test
+            .create_associated_token_account(&trader.pubkey(), &self.quote_mint)
+            .await;
+        self.test
+            .mint_to(&self.quote_mint, &quote, 100_000_000)
+            .await;
+        (trader, quote)
+    }
+
+    /// A resting bid at the oracle price for one lot of market 0, straight on Serum
+    async fn spot_bid_from_trader(&mut self) {
+        let (trader, quote) = self.trader().await;
+        let market = &self.spot_markets[0];
+        let open_orders = market.create_open_orders(&mut self.test).await;
+        let price = market.price_lots(1.0, DECIMALS, DECIMALS);
+        market
+            .place_order(
+                &mut self.test,
+                &trader,
+                &open_orders,
+                &quote,
+                SpotSide::Bid,
+                price,
+                1,
+            )
+            .await;
+    }
+
+    /// A long lot on perp market 0, bought from a counterparty MangoAccount that then bids
+    /// at the oracle price again, for the position to be sold back into
+    async fn perp_position_with_counterparty(&mut self) {
+        let (trader, quote) = self.trader().await;
+        let counterparty = self
+            .mango
+            .create_mango_account(&mut self.test, &trader)
+            .await;
+        self.mango.keeper_refresh(&mut self.test).await;
+        let quote_token = self.mango.quote;
+        self.mango
+            .deposit(
+                &mut self.test,
+                &counterparty,
+                &trader,
+                &quote,
+                &quote_token,
+                100_000_000,
+            )
+            .await;
+
+        let perp_market = self.mango.perp_markets[0];
+        let oracle_price = PERP_BASE_LOT / PERP_QUOTE_LOT;
+        for (owner, mango_account, side) in [
+            (&trader, counterparty, PerpSide::Ask),
+            (&self.depositor, self.pool.mango_account, PerpSide::Bid),
+            (&trader, counterparty, PerpSide::Bid),
+        ] {
+            self.mango
+                .place_perp_order(
+                    &mut self.test,
+                    &mango_account,
+                    owner,
+                    &perp_market,
+                    side,
+                    oracle_price,
+                    1,
+                    100,
+                )
+                .await
+                .unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn buy_into_pool_stays_within_budget() {
+    let mut w = WorstCase::new().await;
+    w.mango.keeper_refresh(&mut w.test).await;
+    let buy = w
+        .pool
+        .buy_into_pool_instruction(
+            &mut w.test,
+            &w.mango,
+            &w.depositor.pubkey(),
+            &w.quote,
+            &w.iou,
+            &w.mango.quote,
+            1_000_000,
+        )
+        .await;
+    w.assert_within_budget("buy_into_pool", buy, BUY_INTO_POOL_BUDGET)
+        .await;
+}
+
+#[tokio::test]
+async fn withdraw_from_pool_stays_within_budget() {
+    let mut w = WorstCase::new().await;
+    w.mango.keeper_refresh(&mut w.test).await;
+    let withdraw = w
+        .pool
+        .withdraw_from_pool_instruction(
+            &mut w.test,
+            &w.mango,
+            &w.depositor.pubkey(),
+            &w.quote,
+            &w.iou,
+            &w.mango.quote,
+            1_000_000,
+        )
+        .await;
+    w.assert_within_budget("withdraw_from_pool", withdraw, WITHDRAW_FROM_POOL_BUDGET)
+        .await;
+}
+
+#[tokio::test]
+async fn refresh_nav_stays_within_budget() {
+    let mut w = WorstCase::new().await;
+    w.mango.keeper_refresh(&mut w.test).await;
+    let refresh = w.pool.refresh_nav_instruction(&mut w.test, &w.mango).await;
+    w.assert_within_budget("refresh_nav", refresh, REFRESH_NAV_BUDGET)
+        .await;
+}
+
+#[tokio::test]
+async fn trip_circuit_breaker_stays_within_budget() {
+    let mut w = WorstCase::new().await;
+    // every token halves, the breaker trips and revokes the delegate
+    for i in 0..MAX_PAIRS {
+        w.mango.set_oracle_price(&mut w.test, i, 0.5).await;
+    }
+    w.mango.keeper_refresh(&mut w.test).await;
+    let trip = w
+        .pool
+        .trip_circuit_breaker_instruction(&mut w.test, &w.mango)
+        .await;
+    w.assert_within_budget("trip_circuit_breaker", trip, TRIP_CIRCUIT_BREAKER_BUDGET)
+        .await;
+}
+
+#[tokio::test]
+async fn process_queued_withdrawal_stays_within_budget() {
+    let mut w = WorstCase::new().await;
+    w.pool
+        .update_pool(
+            &mut w.test,
+            instruction::SetWithdrawalLimit {
+                withdrawal_limit_bps: 1_000,
+            },
+        )
+        .await
+        .unwrap();
+    // 10% of the pool fits in this window, the rest of 12 QUOTE is queued for the next
+    w.pool
+        .withdraw_from_pool(
+            &mut w.test,
+            &w.mango,
+            &w.depositor,
+            &w.quote,
+            &w.iou,
+            &w.mango.quote,
+            12_000_000,
+        )
+        .await
+        .unwrap();
+    w.test.advance_clock(WITHDRAWAL_WINDOW_SECONDS).await;
+    w.mango.keeper_refresh(&mut w.test).await;
+    let process = w
+        .pool
+        .process_queued_withdrawal_instruction(
+            &mut w.test,
+            &w.mango,
+            &w.depositor.pubkey(),
+            &w.quote,
+            &w.iou,
+            &w.mango.quote,
+        )
+        .await;
+    w.assert_within_budget(
+        "process_queued_withdrawal",
+        process,
+        PROCESS_QUEUED_WITHDRAWAL_BUDGET,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn withdraw_with_unwind_stays_within_budget() {
+    let mut w = WorstCase::new().await;
+    w.spot_bid_from_trader().await;
+    w.mango.keeper_refresh(&mut w.test).await;
+    // half a QUOTE more than the pool's QUOTE deposit, covered by selling its lot of token 0
+    let quote_deposit = w
+        .mango
+        .deposit_ui(&mut w.test, &w.pool.mango_account, QUOTE_INDEX)
+        .await;
+    let quantity = (quote_deposit * 1_000_000.0) as u64 + 500_000;
+    let withdraw = w
+        .pool
+        .withdraw_with_unwind_instruction(
+            &mut w.test,
+            &w.mango,
+            &w.depositor.pubkey(),
+            &w.quote,
+            &w.iou,
+            &w.spot_markets[0],
+            quantity,
+            WIND_DOWN_SLIPPAGE_BPS,
+        )
+        .await;
+    w.assert_within_budget(
+        "withdraw_with_unwind",
+        withdraw,
+        WITHDRAW_WITH_UNWIND_BUDGET,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn proxy_place_perp_order_stays_within_budget() {
+    let mut w = WorstCase::new().await;
+    w.pool
+        .enable_proxy_only(&mut w.test, &w.mango)
+        .await
+        .unwrap();
+    w.mango.keeper_refresh(&mut w.test).await;
+    let perp_market = w.mango.perp_markets[0];
+    let perp_price = (0.5 * PERP_BASE_LOT as f64 / PERP_QUOTE_LOT as f64) as i64;
+    let place = w
+        .pool
+        .proxy_place_perp_order_instruction(
+            &mut w.test,
+            &w.mango,
+            &perp_market,
+            OrderSide::Bid,
+            perp_price,
+            1,
+            100,
+        )
+        .await;
+    w.assert_within_budget(
+        "proxy_place_perp_order",
+        place,
+        PROXY_PLACE_PERP_ORDER_BUDGET,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn proxy_cancel_perp_order_stays_within_budget() {
+    let mut w = WorstCase::new().await;
+    w.pool
+        .enable_proxy_only(&mut w.test, &w.mango)
+        .await
+        .unwrap();
+    let perp_market = w.mango.perp_markets[0];
+    let mango_account: MangoAccount = w.test.load(&w.pool.mango_account).await;
+    let slot = mango_account
+        .order_market
+        .iter()
+        .position(|&market_index| market_index as usize == perp_market.index)
+        .unwrap();
+    let cancel = w.pool.proxy_cancel_perp_order_instruction(
+        &w.mango,
+        &perp_market,
+        mango_account.orders[slot],
+    );
+    w.assert_within_budget(
+        "proxy_cancel_perp_order",
+        cancel,
+        PROXY_CANCEL_PERP_ORDER_BUDGET,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn proxy_place_spot_order_stays_within_budget() {
+    let mut w = WorstCase::new().await;
+    w.pool
+        .enable_proxy_only(&mut w.test, &w.mango)
+        .await
+        .unwrap();
+    w.mango.keeper_refresh(&mut w.test).await;
+    let market = &w.spot_markets[0];
+    let price = market.price_lots(0.5, DECIMALS, DECIMALS);
+    let place = w
+        .pool
+        .proxy_place_spot_order_instruction(
+            &mut w.test,
+            &w.mango,
+            market,
+            OrderSide::Bid,
+            price,
+            1,
+            100,
+        )
+        .await;
+    w.assert_within_budget(
+        "proxy_place_spot_order",
+        place,
+        PROXY_PLACE_SPOT_ORDER_BUDGET,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn proxy_cancel_spot_order_stays_within_budget() {
+    let mut w = WorstCase::new().await;
+    w.pool
+        .enable_proxy_only(&mut w.test, &w.mango)
+        .await
+        .unwrap();
+    let market = &w.spot_markets[0];
+    let mango_account: MangoAccount = w.test.load(&w.pool.mango_account).await;
+    let (side, order_id) = market
+        .resting_orders(&mut w.test, &mango_account.spot_open_orders[0])
+        .await[0];
+    let side = match side {
+        SpotSide::Bid => OrderSide::Bid,
+        SpotSide::Ask => OrderSide::Ask,
+    };
+    let cancel = w
+        .pool
+        .proxy_cancel_spot_order_instruction(&mut w.test, &w.mango, market, side, order_id)
+        .await;
+    w.assert_within_budget(
+        "proxy_cancel_spot_order",
+        cancel,
+        PROXY_CANCEL_SPOT_ORDER_BUDGET,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn unwind_perp_stays_within_budget() {
+    let mut w = WorstCase::new().await;
+    w.perp_position_with_counterparty().await;
+    w.pool
+        .start_wind_down(&mut w.test, &w.mango, WIND_DOWN_SLIPPAGE_BPS)
+        .await
+        .unwrap();
+    w.mango.keeper_refresh(&mut w.test).await;
+    // cancels the pool's resting bid, then sells its lot into the counterparty's bid
+    let perp_market = w.mango.perp_markets[0];
+    let unwind = w
+        .pool
+        .unwind_perp_instruction(&mut w.test, &w.mango, &perp_market, u8::MAX)
+        .await;
+    w.assert_within_budget("unwind_perp", unwind, UNWIND_PERP_BUDGET)
+        .await;
+}
+
+#[tokio::test]
+async fn unwind_spot_stays_within_budget() {
+    let mut w = WorstCase::new().await;
+    w.spot_bid_from_trader().await;
+    w.pool
+        .start_wind_down(&mut w.test, &w.mango, WIND_DOWN_SLIPPAGE_BPS)
+        .await
+        .unwrap();
+    w.mango.keeper_refresh(&mut w.test).await;
+    // sells the pool's lot of token 0 into the trader's bid and settles it
+    let unwind = w
+        .pool
+        .unwind_spot_instruction(&mut w.test, &w.mango, &w.spot_markets[0])
+        .await;
+    w.assert_within_budget("unwind_spot", unwind, UNWIND_SPOT_BUDGET)
+        .await;
+}
+
+#[tokio::test]
+async fn distribute_stays_within_budget() {
+    let mut w = WorstCase::new().await;
+    // the first crank only records NAV per IOU, then every token gains 10%
+    w.pool.distribute(&mut w.test, &w.mango).await.unwrap();
+    for i in 0..MAX_PAIRS {
+        w.mango.set_oracle_price(&mut w.test, i, 1.1).await;
+    }
+    w.mango.keeper_refresh(&mut w.test).await;
+    let distribute = w.pool.distribute_instruction(&mut w.test, &w.mango).await;
+    w.assert_within_budget("distribute", distribute, DISTRIBUTE_BUDGET)
+        .await;
+}
+
+#[tokio::test]
+async fn redeem_mngo_stays_within_budget() {
+    let mut w = WorstCase::new().await;
+    // 1 MNGO accrued on every perp market, funded in each market's MNGO vault
+    let mngo = *w.mango.token(MNGO_INDEX);
+    let mut mango_account: MangoAccount = w.test.load(&w.pool.mango_account).await;
+    for perp_market in w.mango.perp_markets.clone() {
+        w.test
+            .mint_to(&mngo.mint, &perp_market.mngo_vault, 1_000_000)
+            .await;
+        mango_account.perp_accounts[perp_market.index].mngo_accrued = 1_000_000;
+    }
+    w.test.store(&w.pool.mango_account, &mango_account).await;
+    w.mango.keeper_refresh(&mut w.test).await;
+    let redeem = w.pool.redeem_mngo_instruction(&w.mango, &mngo);
+    w.assert_within_budget("redeem_mngo", redeem, REDEEM_MNGO_BUDGET)
+        .await;
+}
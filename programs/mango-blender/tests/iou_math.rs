@@ -0,0 +1,158 @@
+// Property tests for the IOU mint / burn math, over random pool value, IOU supply and amounts
+
+use fixed::types::I80F48;
+use mango_blender::helpers::{calculate_iou_burn_amount, calculate_iou_mint_amount};
+use proptest::prelude::*;
+
+/// Pool values are generated with 16 fractional bits, so exact checks fit in u128
+const FRAC_BITS: u32 = 16;
+
+fn to_fixed(scaled: u128) -> I80F48 {
+    I80F48::from_bits((scaled as i128) << (48 - FRAC_BITS))
+}
+
+fn scaled(native: u64) -> u128 {
+    (native as u128) << FRAC_BITS
+}
+
+fn mint(deposit: u64, pool_value: u128, supply: u64) -> u64 {
+    calculate_iou_mint_amount(
+        I80F48::from_num(deposit),
+        to_fixed(pool_value),
+        I80F48::from_num(supply),
+    )
+}
+
+fn burn(withdrawal: u64, pool_value: u128, supply: u64) -> u64 {
+    calculate_iou_burn_amount(
+        I80F48::from_num(withdrawal),
+        to_fixed(pool_value),
+        I80F48::from_num(supply),
+    )
+}
+
+/// The largest QUOTE withdrawal whose burn fits in `ious`
+fn max_withdrawal(pool_value: u128, supply: u64, ious: u64) -> u64 {
+    let fair = (ious as u128 * pool_value / scaled(supply)) as u64;
+    (fair.saturating_sub(2)..=fair + 2)
+        .rev()
+        .find(|&withdrawal| burn(withdrawal, pool_value, supply) <= ious)
+        .unwrap_or(0)
+}
+
+prop_compose! {
+    /// A pool with `supply` IOUs outstanding at a NAV per IOU between 2^-10 and 2^10 (plus some dust),
+    /// returned as (pool value with FRAC_BITS fractional bits, supply)
+    fn pool()(
+        supply in 1u64..=1 << 40,
+        nav_per_iou in 1u128 << 6..=1 << 26,
+        dust in 0u128..1 << FRAC_BITS,
+    ) -> (u128, u64) {
+        (supply as u128 * nav_per_iou + dust, supply)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Op {
+    Deposit(u64),
+    Withdraw(u64),
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        (1u64..=1 << 30).prop_map(Op::Deposit),
+        (1u64..=1 << 30).prop_map(Op::Withdraw),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn deposit_then_full_redemption_returns_at_most_the_deposit(
+        (pool_value, supply) in pool(),
+        deposit in 1u64..=1 << 36,
+    ) {
+        let minted = mint(deposit, pool_value, supply);
+        let redeemed = max_withdrawal(pool_value + scaled(deposit), supply + minted, minted);
+        prop_assert!(redeemed <= deposit, "deposited {}, redeemed {}", deposit, redeemed);
+    }
+
+    #[test]
+    fn claims_never_exceed_pool_value(
+        (pool_value, supply) in pool(),
+        weights in prop::collection::vec(1u64..=1000, 1..8),
+    ) {
+        // split the supply between holders, the last one takes the remainder
+        let total_weight: u64 = weights.iter().sum();
+        let mut holdings: Vec<u64> = weights
+            .iter()
+            .map(|weight| (supply as u128 * *weight as u128 / total_weight as u128) as u64)
+            .collect();
+        let assigned: u64 = holdings.iter().sum();
+        *holdings.last_mut().unwrap() += supply - assigned;
+
+        let (mut value, mut outstanding) = (pool_value, supply);
+        let mut claimed = 0u128;
+        for ious in holdings {
+            if ious == 0 {
+                continue;
+            }
+            let withdrawal = max_withdrawal(value, outstanding, ious);
+            let burned = burn(withdrawal, value, outstanding);
+            prop_assert!(burned <= ious);
+            prop_assert!(scaled(withdrawal) <= value, "withdrew {} from {}", withdrawal, value);
+            value -= scaled(withdrawal);
+            outstanding -= burned;
+            claimed += scaled(withdrawal);
+        }
+        prop_assert!(claimed <= pool_value);
+    }
+
+    #[test]
+    fn rounding_favours_the_pool(
+        (pool_value, supply) in pool(),
+        amount in 1u64..=1 << 36,
+    ) {
+        // exact share of the supply `amount` is worth: amount * supply / pool value
+        let exact = scaled(amount) * supply as u128;
+
+        let minted = mint(amount, pool_value, supply);
+        prop_assert!(minted as u128 * pool_value <= exact, "minted {} for {}", minted, amount);
+
+        let burned = burn(amount, pool_value, supply);
+        prop_assert!(burned as u128 * pool_value >= exact, "burned {} for {}", burned, amount);
+    }
+
+    #[test]
+    fn nav_per_iou_is_monotone_without_pnl(
+        first_deposit in 1u64..=1 << 30,
+        ops in prop::collection::vec(op(), 1..32),
+    ) {
+        let mut value = scaled(first_deposit);
+        let mut supply = mint(first_deposit, 0, 0);
+        prop_assert_eq!(supply, first_deposit);
+
+        for op in ops {
+            let (next_value, next_supply) = match op {
+                Op::Deposit(deposit) => {
+                    (value + scaled(deposit), supply + mint(deposit, value, supply))
+                }
+                Op::Withdraw(withdrawal) => {
+                    let withdrawal = withdrawal.min((value >> FRAC_BITS) as u64);
+                    let burned = burn(withdrawal, value, supply);
+                    if burned >= supply {
+                        // would need every outstanding IOU
+                        continue;
+                    }
+                    (value - scaled(withdrawal), supply - burned)
+                }
+            };
+            // next_value / next_supply >= value / supply
+            prop_assert!(
+                next_value * supply as u128 >= value * next_supply as u128,
+                "{:?}: {}/{} -> {}/{}", op, value, supply, next_value, next_supply
+            );
+            value = next_value;
+            supply = next_supply;
+        }
+    }
+}
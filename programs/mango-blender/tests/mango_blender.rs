@@ -0,0 +1,1020 @@
+// Rust port of tests/mango-blender.ts, run with `cargo test-bpf` (or natively with `cargo test`)
+
+mod program_test;
+
+use std::convert::TryInto;
+
+use fixed::types::I80F48;
+use mango::matching::Side as PerpSide;
+use mango::state::{MangoAccount, QUOTE_INDEX};
+use mango_blender::blender::errors::BlenderError;
+use mango_blender::blender::state::{
+    DepositorPosition, NavSnapshot, Pool, PoolStatus, RiskLimits, MAX_DEPOSIT_LOCK_SLOTS,
+    MAX_NAV_AGE_SLOTS, PRICE_FEED_EXPO, PRICE_FEED_SIZE, WITHDRAWAL_WINDOW_SECONDS,
+};
+use mango_blender::instruction;
+use program_test::*;
+use serum_dex::matching::Side as SpotSide;
+use solana_program_test::tokio;
+use solana_sdk::program_option::COption;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transport::TransportError;
+
+const DECIMALS: u8 = 6;
+const POOL_NAME: &str = "testpool";
+// 1 base token per lot, 0.0001 QUOTE ticks
+const SPOT_BASE_LOT: u64 = 1_000_000;
+const SPOT_QUOTE_LOT: u64 = 100;
+const PERP_BASE_LOT: i64 = 1_000_000;
+const PERP_QUOTE_LOT: i64 = 100_000;
+// Mango error codes
+const MANGO_INSUFFICIENT_FUNDS: u32 = 7;
+const MANGO_INVALID_TOKEN: u32 = 8;
+// SPL token error codes
+const TOKEN_ACCOUNT_FROZEN: u32 = 17;
+
+struct Depositor {
+    keypair: Keypair,
+    quote: Pubkey,
+    token_a: Pubkey,
+    iou: Pubkey,
+}
+
+/// The TS suite's `before`: a group with AAAA and BBBB spot markets, an AAAA perp market, a pool
+/// (with its nav snapshot and price feed) and two depositors holding 5 QUOTE and 5 AAAA each
+struct Fixture {
+    test: BlenderTest,
+    mango: MangoTestGroup,
+    market_a: SpotMarket,
+    token_a: MangoToken,
+    perp_a: MangoPerpMarket,
+    pool: BlenderPool,
+    provider: Depositor,
+    other: Depositor,
+}
+
+impl Fixture {
+    async fn new() -> Self {
+        let mut test = BlenderTest::start().await;
+        let quote_mint = test.create_mint(DECIMALS).await;
+        let mint_a = test.create_mint(DECIMALS).await;
+        let mint_b = test.create_mint(DECIMALS).await;
+        let dummy_mngo_mint = test.create_mint(DECIMALS).await;
+
+        let mut mango = MangoTestGroup::create(&mut test, &quote_mint, DECIMALS).await;
+        let oracle_a = mango.add_oracle(&mut test, 1.0).await;
+        let oracle_b = mango.add_oracle(&mut test, 1.0).await;
+
+        let market_a = SpotMarket::list(
+            &mut test,
+            &mint_a,
+            &quote_mint,
+            SPOT_BASE_LOT,
+            SPOT_QUOTE_LOT,
+        )
+        .await;
+        let market_b = SpotMarket::list(
+            &mut test,
+            &mint_b,
+            &quote_mint,
+            SPOT_BASE_LOT,
+            SPOT_QUOTE_LOT,
+        )
+        .await;
+        let token_a = mango
+            .add_spot_market(&mut test, oracle_a, &market_a, DECIMALS)
+            .await;
+        mango
+            .add_spot_market(&mut test, oracle_b, &market_b, DECIMALS)
+            .await;
+        let perp_a = mango
+            .add_perp_market(
+                &mut test,
+                oracle_a,
+                &dummy_mngo_mint,
+                PERP_BASE_LOT,
+                PERP_QUOTE_LOT,
+            )
+            .await;
+
+        let pool = BlenderPool::new(POOL_NAME, &test.payer(), &mango);
+        pool.create(&mut test, &mango).await;
+        pool.create_nav_snapshot(&mut test).await;
+        pool.create_price_feed(&mut test).await;
+
+        let provider_keypair = test.payer_keypair();
+        let provider =
+            Self::depositor(&mut test, &pool, provider_keypair, &quote_mint, &mint_a).await;
+        let other_keypair = test.create_user(1_000_000_000).await;
+        let other = Self::depositor(&mut test, &pool, other_keypair, &quote_mint, &mint_a).await;
+
+        Fixture {
+            test,
+            mango,
+            market_a,
+            token_a,
+            perp_a,
+            pool,
+            provider,
+            other,
+        }
+    }
+
+    async fn depositor(
+        test: &mut BlenderTest,
+        pool: &BlenderPool,
+        keypair: Keypair,
+        quote_mint: &Pubkey,
+        mint_a: &Pubkey,
+    ) -> Depositor {
+        let owner = keypair.pubkey();
+        let quote = test
+            .create_associated_token_account(&owner, quote_mint)
+            .await;
+        test.mint_to(quote_mint, &quote, 5_000_000).await;
+        let token_a = test.create_associated_token_account(&owner, mint_a).await;
+        test.mint_to(mint_a, &token_a, 5_000_000).await;
+        let iou = test
+            .create_associated_token_account(&owner, &pool.iou_mint)
+            .await;
+        pool.create_depositor_position(test, &keypair).await;
+        Depositor {
+            keypair,
+            quote,
+            token_a,
+            iou,
+        }
+    }
+
+    async fn deposit_quote(&mut self, other: bool, quantity: u64) {
+        let depositor = if other { &self.other } else { &self.provider };
+        self.pool
+            .buy_into_pool(
+                &mut self.test,
+                &self.mango,
+                &depositor.keypair,
+                &depositor.quote,
+                &depositor.iou,
+                &self.mango.quote,
+                quantity,
+            )
+            .await
+            .unwrap();
+    }
+
+    async fn withdraw_quote(&mut self, other: bool, quantity: u64) -> Result<(), TransportError> {
+        let withdrawer = if other { &self.other } else { &self.provider };
+        self.pool
+            .withdraw_from_pool(
+                &mut self.test,
+                &self.mango,
+                &withdrawer.keypair,
+                &withdrawer.quote,
+                &withdrawer.iou,
+                &self.mango.quote,
+                quantity,
+            )
+            .await
+    }
+
+    async fn process_queued_withdrawal(&mut self) -> Result<(), TransportError> {
+        self.pool
+            .process_queued_withdrawal(
+                &mut self.test,
+                &self.mango,
+                &self.provider.keypair.pubkey(),
+                &self.provider.quote,
+                &self.provider.iou,
+                &self.mango.quote,
+            )
+            .await
+    }
+
+    async fn mango_deposit(&mut self, index: usize) -> f64 {
+        self.mango
+            .deposit_ui(&mut self.test, &self.pool.mango_account, index)
+            .await
+    }
+}
+
+fn assert_ui_eq(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-6,
+        "expected {}, got {}",
+        expected,
+        actual
+    );
+}
+
+#[tokio::test]
+async fn creates_pool_with_delegated_mango_account() {
+    let mut f = Fixture::new().await;
+
+    let pool: Pool = f.test.load_anchor(&f.pool.pool).await;
+    assert_eq!(pool.admin, f.test.payer());
+    assert_eq!(pool.iou_mint, f.pool.iou_mint);
+    assert_eq!(pool.pool_name, POOL_NAME);
+    assert_eq!(pool.mango_account, f.pool.mango_account);
+
+    let iou_mint_account = f.test.get_account(&f.pool.iou_mint).await;
+    assert_eq!(iou_mint_account.owner, spl_token::id());
+    let iou_mint = spl_token::state::Mint::unpack(&iou_mint_account.data).unwrap();
+    assert_eq!(iou_mint.mint_authority, COption::Some(f.pool.pool));
+    assert_eq!(iou_mint.freeze_authority, COption::Some(f.pool.pool));
+
+    let mango_account: MangoAccount = f.test.load(&f.pool.mango_account).await;
+    assert!(mango_account.meta_data.is_initialized);
+    assert_eq!(mango_account.delegate, f.test.payer());
+}
+
+#[tokio::test]
+async fn creates_positions_nav_snapshot_and_price_feed() {
+    let mut f = Fixture::new().await;
+
+    let position: DepositorPosition = f
+        .test
+        .load_anchor(&f.pool.position(&f.provider.keypair.pubkey()).0)
+        .await;
+    assert_eq!(position.pool, f.pool.pool);
+    assert_eq!(position.owner, f.provider.keypair.pubkey());
+    assert_eq!(position.last_deposit_slot, 0);
+
+    let snapshot: NavSnapshot = f.test.load_anchor(&f.pool.nav_snapshot).await;
+    assert_eq!(snapshot.pool, f.pool.pool);
+    assert_eq!(snapshot.iou_supply, 0);
+
+    let price_feed = f.test.get_account(&f.pool.price_feed).await;
+    let data = &price_feed.data;
+    assert_eq!(price_feed.owner, mango_blender::ID);
+    assert_eq!(data.len(), PRICE_FEED_SIZE);
+    // magic, then a price account
+    assert_eq!(
+        u32::from_le_bytes(data[0..4].try_into().unwrap()),
+        0xa1b2c3d4
+    );
+    assert_eq!(u32::from_le_bytes(data[8..12].try_into().unwrap()), 3);
+    assert_eq!(
+        i32::from_le_bytes(data[20..24].try_into().unwrap()),
+        PRICE_FEED_EXPO
+    );
+}
+
+#[tokio::test]
+async fn publishes_nav_price_without_averages() {
+    let mut f = Fixture::new().await;
+    f.deposit_quote(false, 2_000_000).await;
+    f.withdraw_quote(false, 500_000).await.unwrap();
+
+    // 1 QUOTE per IOU (up to Mango's index rounding), the twap and twac are never filled in
+    let data = f.test.get_account(&f.pool.price_feed).await.data;
+    let price = i64::from_le_bytes(data[208..216].try_into().unwrap());
+    assert!((price - 1_000_000_000).abs() <= 1);
+    assert!(data[48..96].iter().all(|byte| *byte == 0));
+}
+
+#[tokio::test]
+async fn deposits_and_withdraws_quote() {
+    let mut f = Fixture::new().await;
+
+    assert_eq!(f.test.mint_supply(&f.pool.iou_mint).await, 0);
+    f.deposit_quote(false, 2_000_000).await;
+
+    assert_eq!(f.test.token_balance(&f.provider.quote).await, 3_000_000);
+    assert_ui_eq(f.mango_deposit(QUOTE_INDEX).await, 2.0);
+    assert_eq!(f.test.mint_supply(&f.pool.iou_mint).await, 2_000_000);
+    assert_eq!(f.test.token_balance(&f.provider.iou).await, 2_000_000);
+
+    f.withdraw_quote(false, 500_000).await.unwrap();
+
+    assert_eq!(f.test.token_balance(&f.provider.quote).await, 3_500_000);
+    assert_ui_eq(f.mango_deposit(QUOTE_INDEX).await, 1.5);
+    assert_eq!(f.test.mint_supply(&f.pool.iou_mint).await, 1_500_000);
+    assert_eq!(f.test.token_balance(&f.provider.iou).await, 1_500_000);
+}
+
+#[tokio::test]
+async fn rejects_non_quote_deposit() {
+    let mut f = Fixture::new().await;
+
+    let result = f
+        .pool
+        .buy_into_pool(
+            &mut f.test,
+            &f.mango,
+            &f.provider.keypair,
+            &f.provider.token_a,
+            &f.provider.iou,
+            &f.token_a,
+            1_000_000,
+        )
+        .await;
+    assert_custom_error(result, MANGO_INVALID_TOKEN);
+}
+
+#[tokio::test]
+async fn rejects_non_quote_withdrawal() {
+    let mut f = Fixture::new().await;
+    f.deposit_quote(false, 2_000_000).await;
+
+    let result = f
+        .pool
+        .withdraw_from_pool(
+            &mut f.test,
+            &f.mango,
+            &f.provider.keypair,
+            &f.provider.token_a,
+            &f.provider.iou,
+            &f.token_a,
+            500_000,
+        )
+        .await;
+    assert_custom_error(result, MANGO_INVALID_TOKEN);
+}
+
+#[tokio::test]
+async fn rejects_withdrawal_above_holdings() {
+    let mut f = Fixture::new().await;
+    f.deposit_quote(false, 1_500_000).await;
+
+    let result = f.withdraw_quote(false, 1_500_001).await;
+    assert_custom_error(result, MANGO_INSUFFICIENT_FUNDS);
+}
+
+#[tokio::test]
+async fn blocks_withdrawals_inside_deposit_lock() {
+    let mut f = Fixture::new().await;
+    let result = f
+        .pool
+        .update_pool(
+            &mut f.test,
+            instruction::SetDepositLockSlots {
+                deposit_lock_slots: MAX_DEPOSIT_LOCK_SLOTS + 1,
+            },
+        )
+        .await;
+    assert_custom_error(result, blender_error(BlenderError::InvalidPoolSetting));
+    f.pool
+        .update_pool(
+            &mut f.test,
+            instruction::SetDepositLockSlots {
+                deposit_lock_slots: 100,
+            },
+        )
+        .await
+        .unwrap();
+    f.deposit_quote(false, 2_000_000).await;
+
+    let result = f.withdraw_quote(false, 100_000).await;
+    assert_custom_error(result, blender_error(BlenderError::WithdrawLocked));
+
+    // the lock follows the IOUs, moving them to another wallet doesn't get around it
+    let (provider_iou, other_iou) = (f.provider.iou, f.other.iou);
+    let result = f
+        .test
+        .transfer_tokens(&f.provider.keypair, &provider_iou, &other_iou, 100_000)
+        .await;
+    assert_custom_error(result, TOKEN_ACCOUNT_FROZEN);
+    let provider = f.provider.keypair.pubkey();
+    let result = f
+        .pool
+        .thaw_ious(&mut f.test, &provider, &f.pool.iou_mint, &provider_iou)
+        .await;
+    assert_custom_error(result, blender_error(BlenderError::WithdrawLocked));
+
+    // the lock was fixed at deposit time, dropping the pool's lock doesn't shorten it
+    f.pool
+        .update_pool(
+            &mut f.test,
+            instruction::SetDepositLockSlots {
+                deposit_lock_slots: 0,
+            },
+        )
+        .await
+        .unwrap();
+    let result = f.withdraw_quote(false, 100_000).await;
+    assert_custom_error(result, blender_error(BlenderError::WithdrawLocked));
+
+    f.test.advance_slots(101).await;
+    f.pool
+        .thaw_ious(&mut f.test, &provider, &f.pool.iou_mint, &provider_iou)
+        .await
+        .unwrap();
+    f.test
+        .transfer_tokens(&f.provider.keypair, &provider_iou, &other_iou, 100_000)
+        .await
+        .unwrap();
+    f.withdraw_quote(false, 100_000).await.unwrap();
+}
+
+#[tokio::test]
+async fn blocks_withdrawals_while_winding_down() {
+    let mut f = Fixture::new().await;
+    f.deposit_quote(false, 2_000_000).await;
+    f.pool
+        .start_wind_down(&mut f.test, &f.mango, 100)
+        .await
+        .unwrap();
+
+    let result = f.withdraw_quote(false, 100_000).await;
+    assert_custom_error(result, blender_error(BlenderError::WindingDown));
+}
+
+#[tokio::test]
+async fn queues_withdrawals_over_the_window_for_a_keeper() {
+    let mut f = Fixture::new().await;
+    f.deposit_quote(false, 2_000_000).await;
+    f.pool
+        .update_pool(
+            &mut f.test,
+            instruction::SetWithdrawalLimit {
+                withdrawal_limit_bps: 2_000,
+            },
+        )
+        .await
+        .unwrap();
+
+    // 20% of 2 QUOTE fits in this window, the rest is queued
+    f.withdraw_quote(false, 500_000).await.unwrap();
+    assert_eq!(f.test.token_balance(&f.provider.quote).await, 3_400_000);
+    let provider = f.provider.keypair.pubkey();
+    let position: DepositorPosition = f.test.load_anchor(&f.pool.position(&provider).0).await;
+    assert_eq!(position.queued_withdrawal, 100_000);
+    // the pool can burn just what the queued request needs
+    assert_eq!(f.test.delegated_amount(&f.provider.iou).await, 100_000);
+
+    let result = f.process_queued_withdrawal().await;
+    assert_custom_error(result, blender_error(BlenderError::WithdrawalLimitExceeded));
+
+    // a keeper pays it out of the next window, without the withdrawer
+    f.test.advance_clock(WITHDRAWAL_WINDOW_SECONDS).await;
+    f.process_queued_withdrawal().await.unwrap();
+    assert_eq!(f.test.token_balance(&f.provider.quote).await, 3_500_000);
+    assert_eq!(f.test.token_balance(&f.provider.iou).await, 1_500_000);
+    let position: DepositorPosition = f.test.load_anchor(&f.pool.position(&provider).0).await;
+    assert_eq!(position.queued_withdrawal, 0);
+    assert_eq!(f.test.delegated_amount(&f.provider.iou).await, 0);
+
+    let result = f.process_queued_withdrawal().await;
+    assert_custom_error(result, blender_error(BlenderError::NothingQueued));
+}
+
+#[tokio::test]
+async fn drops_the_queue_when_the_limit_is_turned_off() {
+    let mut f = Fixture::new().await;
+    f.deposit_quote(false, 2_000_000).await;
+    let set_limit = |withdrawal_limit_bps| instruction::SetWithdrawalLimit {
+        withdrawal_limit_bps,
+    };
+    f.pool
+        .update_pool(&mut f.test, set_limit(2_000))
+        .await
+        .unwrap();
+    f.withdraw_quote(false, 500_000).await.unwrap();
+    f.pool.update_pool(&mut f.test, set_limit(0)).await.unwrap();
+    let pool: Pool = f.test.load_anchor(&f.pool.pool).await;
+    assert_eq!(pool.withdrawal_queued, 0);
+
+    // the keeper drops the request instead of paying it out again
+    f.test.advance_clock(WITHDRAWAL_WINDOW_SECONDS).await;
+    f.process_queued_withdrawal().await.unwrap();
+    assert_eq!(f.test.token_balance(&f.provider.quote).await, 3_400_000);
+    assert_eq!(f.test.token_balance(&f.provider.iou).await, 1_600_000);
+    let provider = f.provider.keypair.pubkey();
+    let position: DepositorPosition = f.test.load_anchor(&f.pool.position(&provider).0).await;
+    assert_eq!(position.queued_withdrawal, 0);
+    let result = f.process_queued_withdrawal().await;
+    assert_custom_error(result, blender_error(BlenderError::NothingQueued));
+
+    // the next withdrawal takes back the allowance left for the keeper
+    f.withdraw_quote(false, 100_000).await.unwrap();
+    assert_eq!(f.test.delegated_amount(&f.provider.iou).await, 0);
+}
+
+#[tokio::test]
+async fn validates_admin_settings() {
+    let mut f = Fixture::new().await;
+    let invalid_setting = blender_error(BlenderError::InvalidPoolSetting);
+
+    let result = f
+        .pool
+        .update_pool(
+            &mut f.test,
+            instruction::SetWithdrawalLimit {
+                withdrawal_limit_bps: 10001,
+            },
+        )
+        .await;
+    assert_custom_error(result, invalid_setting);
+
+    f.pool
+        .update_pool(
+            &mut f.test,
+            instruction::SetAllowlistTimelock {
+                timelock_seconds: 3600,
+            },
+        )
+        .await
+        .unwrap();
+    let result = f
+        .pool
+        .update_pool(
+            &mut f.test,
+            instruction::SetAllowlistTimelock {
+                timelock_seconds: 0,
+            },
+        )
+        .await;
+    assert_custom_error(result, invalid_setting);
+
+    // unhealthy withdrawals are queued through the withdrawal window
+    let queue_unhealthy = instruction::SetQueueUnhealthyWithdrawals {
+        queue_unhealthy_withdrawals: true,
+    };
+    let result = f.pool.update_pool(&mut f.test, queue_unhealthy).await;
+    assert_custom_error(result, invalid_setting);
+
+    let result = f
+        .pool
+        .update_pool(
+            &mut f.test,
+            instruction::SetNavMaxAgeSlots {
+                nav_max_age_slots: MAX_NAV_AGE_SLOTS + 1,
+            },
+        )
+        .await;
+    assert_custom_error(result, invalid_setting);
+
+    // no drawdown limit set
+    f.mango.keeper_refresh(&mut f.test).await;
+    let result = f.pool.trip_circuit_breaker(&mut f.test, &f.mango).await;
+    assert_custom_error(result, invalid_setting);
+
+    let result = f.pool.start_wind_down(&mut f.test, &f.mango, 10000).await;
+    assert_custom_error(result, blender_error(BlenderError::InvalidSlippage));
+
+    let pool: Pool = f.test.load_anchor(&f.pool.pool).await;
+    assert_eq!(pool.withdrawal_limit_bps, 0);
+    assert_eq!(pool.market_allowlist.timelock_seconds, 3600);
+    assert_eq!(pool.status, PoolStatus::Active);
+}
+
+#[tokio::test]
+async fn timelocks_looser_risk_limits() {
+    let mut f = Fixture::new().await;
+    let set_limits = |max_order_notional| instruction::SetRiskLimits {
+        risk_limits: RiskLimits {
+            max_order_notional,
+            ..RiskLimits::default()
+        },
+    };
+    f.pool
+        .update_pool(
+            &mut f.test,
+            instruction::SetAllowlistTimelock {
+                timelock_seconds: 3600,
+            },
+        )
+        .await
+        .unwrap();
+
+    // from unlimited to a limit only tightens, it applies right away
+    f.pool
+        .update_pool(&mut f.test, set_limits(1_000_000))
+        .await
+        .unwrap();
+    let pool: Pool = f.test.load_anchor(&f.pool.pool).await;
+    assert_eq!(pool.risk_limits.max_order_notional, 1_000_000);
+
+    // raising it waits out the timelock
+    f.pool
+        .update_pool(&mut f.test, set_limits(2_000_000))
+        .await
+        .unwrap();
+    let pool: Pool = f.test.load_anchor(&f.pool.pool).await;
+    assert_eq!(pool.risk_limits.max_order_notional, 1_000_000);
+    let result = f.pool.apply_risk_limits(&mut f.test).await;
+    assert_custom_error(result, blender_error(BlenderError::RiskLimitsTimelocked));
+
+    f.test.advance_clock(3600).await;
+    f.pool.apply_risk_limits(&mut f.test).await.unwrap();
+    let pool: Pool = f.test.load_anchor(&f.pool.pool).await;
+    assert_eq!(pool.risk_limits.max_order_notional, 2_000_000);
+    assert_eq!(pool.pending_risk_limits_ts, 0);
+}
+
+#[tokio::test]
+async fn timelocks_a_looser_drawdown_limit() {
+    let mut f = Fixture::new().await;
+    let set_drawdown = |max_drawdown_bps| instruction::SetMaxDrawdown { max_drawdown_bps };
+    f.pool
+        .update_pool(
+            &mut f.test,
+            instruction::SetAllowlistTimelock {
+                timelock_seconds: 3600,
+            },
+        )
+        .await
+        .unwrap();
+    f.pool
+        .update_pool(&mut f.test, set_drawdown(1_000))
+        .await
+        .unwrap();
+    f.pool
+        .update_pool(&mut f.test, set_drawdown(500))
+        .await
+        .unwrap();
+    let pool: Pool = f.test.load_anchor(&f.pool.pool).await;
+    assert_eq!(pool.max_drawdown_bps, 500);
+
+    // turning it off can't happen right before a drawdown
+    f.pool
+        .update_pool(&mut f.test, set_drawdown(0))
+        .await
+        .unwrap();
+    let pool: Pool = f.test.load_anchor(&f.pool.pool).await;
+    assert_eq!(pool.max_drawdown_bps, 500);
+    let result = f.pool.apply_max_drawdown(&mut f.test).await;
+    assert_custom_error(result, blender_error(BlenderError::MaxDrawdownTimelocked));
+
+    f.test.advance_clock(3600).await;
+    f.pool.apply_max_drawdown(&mut f.test).await.unwrap();
+    let pool: Pool = f.test.load_anchor(&f.pool.pool).await;
+    assert_eq!(pool.max_drawdown_bps, 0);
+}
+
+/// The trading half of the TS suite, each step builds on the previous one
+#[tokio::test]
+async fn values_open_orders_and_blocks_leveraged_withdrawals() {
+    let mut f = Fixture::new().await;
+    let admin = f.test.payer_keypair();
+    f.deposit_quote(false, 2_000_000).await;
+    f.withdraw_quote(false, 500_000).await.unwrap();
+
+    // the delegate buys 1 AAAA at 1 through Mango, an outside trader sells into it on Serum
+    let open_orders = f
+        .mango
+        .init_spot_open_orders(&mut f.test, &f.pool.mango_account, &admin, &f.market_a)
+        .await;
+    f.mango.keeper_refresh(&mut f.test).await;
+    let price = f.market_a.price_lots(1.0, DECIMALS, DECIMALS);
+    f.mango
+        .place_spot_order(
+            &mut f.test,
+            &f.pool.mango_account,
+            &admin,
+            &f.market_a,
+            SpotSide::Bid,
+            price,
+            1,
+            1234,
+        )
+        .await;
+    let other_open_orders = f.market_a.create_open_orders(&mut f.test).await;
+    f.market_a
+        .place_order(
+            &mut f.test,
+            &f.other.keypair,
+            &other_open_orders,
+            &f.other.token_a,
+            SpotSide::Ask,
+            price,
+            1,
+        )
+        .await;
+    f.market_a
+        .consume_events(&mut f.test, &[open_orders, other_open_orders])
+        .await;
+    f.mango
+        .settle_funds(&mut f.test, &f.pool.mango_account, &admin, &f.market_a)
+        .await;
+    f.mango.keeper_refresh(&mut f.test).await;
+    assert_ui_eq(f.mango_deposit(QUOTE_INDEX).await, 0.5);
+    assert_ui_eq(f.mango_deposit(f.token_a.index).await, 1.0);
+
+    // a second depositor gets IOUs at the current NAV per IOU (1)
+    f.deposit_quote(true, 1_500_000).await;
+    assert_eq!(f.test.token_balance(&f.other.quote).await, 3_500_000);
+    assert_ui_eq(f.mango_deposit(QUOTE_INDEX).await, 2.0);
+    assert_eq!(f.test.mint_supply(&f.pool.iou_mint).await, 3_000_000);
+    assert_eq!(f.test.token_balance(&f.other.iou).await, 1_500_000);
+
+    // AAAA halves and the delegate offers it back, the pool is worth 2.5 QUOTE with the AAAA sitting in open orders
+    f.mango
+        .set_oracle_price(&mut f.test, f.token_a.index, 0.5)
+        .await;
+    f.mango.keeper_refresh(&mut f.test).await;
+    f.mango
+        .place_spot_order(
+            &mut f.test,
+            &f.pool.mango_account,
+            &admin,
+            &f.market_a,
+            SpotSide::Ask,
+            f.market_a.price_lots(0.5, DECIMALS, DECIMALS),
+            1,
+            4321,
+        )
+        .await;
+    f.withdraw_quote(false, 1_250_000).await.unwrap();
+    assert_eq!(f.test.token_balance(&f.provider.quote).await, 4_750_000);
+    assert_ui_eq(f.mango_deposit(QUOTE_INDEX).await, 0.75);
+    assert_eq!(f.test.mint_supply(&f.pool.iou_mint).await, 1_500_000);
+    assert_eq!(f.test.token_balance(&f.provider.iou).await, 0);
+
+    // a large perp bid leaves no room for the second depositor to take QUOTE out
+    f.mango.keeper_refresh(&mut f.test).await;
+    let perp_price = (23.5 * PERP_BASE_LOT as f64 / PERP_QUOTE_LOT as f64) as i64;
+    f.mango
+        .place_perp_order(
+            &mut f.test,
+            &f.pool.mango_account,
+            &admin,
+            &f.perp_a,
+            PerpSide::Bid,
+            perp_price,
+            1,
+            71,
+        )
+        .await
+        .unwrap();
+    let result = f.withdraw_quote(true, 500_000).await;
+    assert_custom_error(result, MANGO_INSUFFICIENT_FUNDS);
+}
+
+#[tokio::test]
+async fn senior_tranche_is_paid_before_junior() {
+    let mut f = Fixture::new().await;
+    f.pool.create_senior_tranche(&mut f.test, 0).await;
+    f.pool
+        .update_pool(
+            &mut f.test,
+            instruction::SetMarketAllowlist {
+                spot_markets: 1 << f.token_a.index,
+                perp_markets: 0,
+            },
+        )
+        .await
+        .unwrap();
+    let senior_iou = f
+        .test
+        .create_associated_token_account(&f.provider.keypair.pubkey(), &f.pool.senior_iou_mint)
+        .await;
+
+    // the tranche is picked by the IOU mint, anything else is rejected
+    let result = f
+        .pool
+        .buy_into_pool(
+            &mut f.test,
+            &f.mango,
+            &f.provider.keypair,
+            &f.provider.quote,
+            &f.provider.token_a,
+            &f.mango.quote,
+            1_000_000,
+        )
+        .await;
+    assert_custom_error(result, blender_error(BlenderError::InvalidTrancheMint));
+
+    // the provider buys 2 QUOTE of senior IOUs, the second depositor 2 AAAA (at 1) of junior IOUs
+    f.pool
+        .buy_into_pool(
+            &mut f.test,
+            &f.mango,
+            &f.provider.keypair,
+            &f.provider.quote,
+            &senior_iou,
+            &f.mango.quote,
+            2_000_000,
+        )
+        .await
+        .unwrap();
+    f.pool
+        .buy_into_pool(
+            &mut f.test,
+            &f.mango,
+            &f.other.keypair,
+            &f.other.token_a,
+            &f.other.iou,
+            &f.token_a,
+            2_000_000,
+        )
+        .await
+        .unwrap();
+    assert_eq!(f.test.mint_supply(&f.pool.senior_iou_mint).await, 2_000_000);
+    assert_eq!(f.test.mint_supply(&f.pool.iou_mint).await, 2_000_000);
+
+    // a fresh snapshot only holds the junior supply, the tranches are still priced live
+    f.pool
+        .update_pool(
+            &mut f.test,
+            instruction::SetNavMaxAgeSlots {
+                nav_max_age_slots: MAX_NAV_AGE_SLOTS,
+            },
+        )
+        .await
+        .unwrap();
+    f.mango.keeper_refresh(&mut f.test).await;
+    let refresh = f.pool.refresh_nav_instruction(&mut f.test, &f.mango).await;
+    f.test.process(&[refresh], &[]).await.unwrap();
+
+    // AAAA halves, of the 3 QUOTE the pool is worth the senior tranche is still owed its 2
+    f.mango
+        .set_oracle_price(&mut f.test, f.token_a.index, 0.5)
+        .await;
+    f.pool
+        .withdraw_from_pool(
+            &mut f.test,
+            &f.mango,
+            &f.provider.keypair,
+            &f.provider.quote,
+            &senior_iou,
+            &f.mango.quote,
+            1_000_000,
+        )
+        .await
+        .unwrap();
+    assert_eq!(f.test.token_balance(&f.provider.quote).await, 4_000_000);
+    assert_eq!(f.test.token_balance(&senior_iou).await, 1_000_000);
+    let pool: Pool = f.test.load_anchor(&f.pool.pool).await;
+    assert_eq!(
+        I80F48::from_bits(pool.senior_claim),
+        I80F48::from_num(1_000_000)
+    );
+
+    // the junior tranche took the whole loss, 1 QUOTE for 2 IOUs
+    f.withdraw_quote(true, 500_000).await.unwrap();
+    assert_eq!(f.test.token_balance(&f.other.quote).await, 5_500_000);
+    assert_eq!(f.test.token_balance(&f.other.iou).await, 1_000_000);
+
+    // AAAA drops to 0.1, the pool no longer covers the senior claim and senior deposits stop
+    f.mango
+        .set_oracle_price(&mut f.test, f.token_a.index, 0.1)
+        .await;
+    let result = f
+        .pool
+        .buy_into_pool(
+            &mut f.test,
+            &f.mango,
+            &f.provider.keypair,
+            &f.provider.quote,
+            &senior_iou,
+            &f.mango.quote,
+            1_000_000,
+        )
+        .await;
+    assert_custom_error(result, blender_error(BlenderError::SeniorTrancheImpaired));
+}
+
+#[tokio::test]
+async fn distributes_nav_growth_to_iou_holders() {
+    let mut f = Fixture::new().await;
+    f.pool
+        .create_distribution_vault(&mut f.test, &f.mango)
+        .await;
+    f.pool
+        .update_pool(
+            &mut f.test,
+            instruction::SetDistributionMode {
+                distribution_mode: true,
+                distribution_interval_seconds: 0,
+            },
+        )
+        .await
+        .unwrap();
+    f.pool
+        .update_pool(
+            &mut f.test,
+            instruction::SetMarketAllowlist {
+                spot_markets: 1 << f.token_a.index,
+                perp_markets: 0,
+            },
+        )
+        .await
+        .unwrap();
+
+    // 2 QUOTE and 2 AAAA (at 1) in, the first crank only records NAV per IOU
+    f.deposit_quote(false, 2_000_000).await;
+    f.pool
+        .buy_into_pool(
+            &mut f.test,
+            &f.mango,
+            &f.other.keypair,
+            &f.other.token_a,
+            &f.other.iou,
+            &f.token_a,
+            2_000_000,
+        )
+        .await
+        .unwrap();
+    f.pool.distribute(&mut f.test, &f.mango).await.unwrap();
+    assert_eq!(f.test.token_balance(&f.pool.distribution_vault).await, 0);
+    let result = f
+        .pool
+        .claim_distribution(
+            &mut f.test,
+            &f.provider.keypair,
+            &f.provider.iou,
+            &f.provider.quote,
+        )
+        .await;
+    assert_custom_error(result, blender_error(BlenderError::NothingToClaim));
+
+    // AAAA goes to 1.5, the 1 QUOTE of growth moves from the MangoAccount to the vault
+    f.mango
+        .set_oracle_price(&mut f.test, f.token_a.index, 1.5)
+        .await;
+    f.pool.distribute(&mut f.test, &f.mango).await.unwrap();
+    assert_eq!(
+        f.test.token_balance(&f.pool.distribution_vault).await,
+        1_000_000
+    );
+    assert_ui_eq(f.mango_deposit(QUOTE_INDEX).await, 1.0);
+    assert_eq!(f.test.mint_supply(&f.pool.iou_mint).await, 4_000_000);
+    let pool: Pool = f.test.load_anchor(&f.pool.pool).await;
+    assert_eq!(pool.distribution_index(), I80F48::from_num(0.25));
+    assert_eq!(pool.distribution_nav_per_iou(), I80F48::from_num(1));
+
+    // nothing new to pay out until NAV per IOU grows again
+    f.pool.distribute(&mut f.test, &f.mango).await.unwrap();
+    assert_eq!(
+        f.test.token_balance(&f.pool.distribution_vault).await,
+        1_000_000
+    );
+
+    // half each, a withdrawal settles what the burned IOUs were owed first
+    f.withdraw_quote(true, 500_000).await.unwrap();
+    assert_eq!(f.test.token_balance(&f.other.iou).await, 1_500_000);
+    f.pool
+        .claim_distribution(
+            &mut f.test,
+            &f.provider.keypair,
+            &f.provider.iou,
+            &f.provider.quote,
+        )
+        .await
+        .unwrap();
+    f.pool
+        .claim_distribution(&mut f.test, &f.other.keypair, &f.other.iou, &f.other.quote)
+        .await
+        .unwrap();
+    assert_eq!(f.test.token_balance(&f.provider.quote).await, 3_500_000);
+    assert_eq!(f.test.token_balance(&f.other.quote).await, 6_000_000);
+    assert_eq!(f.test.token_balance(&f.pool.distribution_vault).await, 0);
+
+    // transfer_ious settles both sides and moves the IOUs' share of later distributions with them
+    let (provider_iou, other_iou) = (f.provider.iou, f.other.iou);
+    let other = f.other.keypair.pubkey();
+    f.pool
+        .transfer_ious(
+            &mut f.test,
+            &f.provider.keypair,
+            &other,
+            &f.pool.iou_mint,
+            &provider_iou,
+            &other_iou,
+            500_000,
+        )
+        .await
+        .unwrap();
+    assert_eq!(f.test.token_balance(&provider_iou).await, 1_500_000);
+    assert_eq!(f.test.token_balance(&other_iou).await, 2_000_000);
+
+    // IOUs moved any other way stop earning, the recipient only earns on their recorded balance
+    f.test
+        .transfer_tokens(&f.provider.keypair, &provider_iou, &other_iou, 500_000)
+        .await
+        .unwrap();
+    f.deposit_quote(true, 500_000).await;
+    let pool: Pool = f.test.load_anchor(&f.pool.pool).await;
+    assert_eq!(pool.distribution_weight, 4_000_000);
+    assert_eq!(f.test.mint_supply(&f.pool.iou_mint).await, 4_000_000);
+
+    // AAAA goes to 1.75, 0.125 QUOTE per recorded IOU
+    f.mango
+        .set_oracle_price(&mut f.test, f.token_a.index, 1.75)
+        .await;
+    f.pool.distribute(&mut f.test, &f.mango).await.unwrap();
+    assert_eq!(
+        f.test.token_balance(&f.pool.distribution_vault).await,
+        500_000
+    );
+    f.pool
+        .claim_distribution(
+            &mut f.test,
+            &f.provider.keypair,
+            &f.provider.iou,
+            &f.provider.quote,
+        )
+        .await
+        .unwrap();
+    f.pool
+        .claim_distribution(&mut f.test, &f.other.keypair, &f.other.iou, &f.other.quote)
+        .await
+        .unwrap();
+    assert_eq!(f.test.token_balance(&f.provider.quote).await, 3_625_000);
+    assert_eq!(f.test.token_balance(&f.other.quote).await, 5_812_500);
+    let pool: Pool = f.test.load_anchor(&f.pool.pool).await;
+    assert_eq!(pool.distribution_weight, 3_500_000);
+}
@@ -0,0 +1,551 @@
+use std::mem::size_of;
+use std::num::NonZeroU64;
+
+use fixed::types::I80F48;
+use mango::instruction as MangoInstructions;
+use mango::matching::{BookSide, OrderType as PerpOrderType, Side as PerpSide};
+use mango::oracle::StubOracle;
+use mango::queue::{AnyEvent, EventQueueHeader};
+use mango::state::{
+    MangoAccount, MangoCache, MangoGroup, NodeBank, PerpMarket, RootBank, MAX_PAIRS, QUOTE_INDEX,
+};
+use serum_dex::instruction::{NewOrderInstructionV3, SelfTradeBehavior};
+use serum_dex::matching::{OrderType as SpotOrderType, Side as SpotSide};
+use solana_sdk::instruction::AccountMeta;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transport::TransportError;
+
+use super::{mango_program_id, serum_program_id, BlenderTest, SpotMarket, OPEN_ORDERS_SIZE};
+
+// Risk and interest params from tests/setupMango.ts, reused for every market
+const VALID_INTERVAL: u64 = 10000;
+const OPTIMAL_UTIL: f64 = 0.7;
+const OPTIMAL_RATE: f64 = 0.06;
+const MAX_RATE: f64 = 1.5;
+const MAINT_LEVERAGE: f64 = 20.0;
+const INIT_LEVERAGE: f64 = 10.0;
+const LIQUIDATION_FEE: f64 = 0.05;
+const PERP_EVENT_QUEUE_LEN: usize = 256;
+
+/// A token listed on the group, with its (single) node bank
+#[derive(Clone, Copy, Debug)]
+pub struct MangoToken {
+    pub index: usize,
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub root_bank: Pubkey,
+    pub node_bank: Pubkey,
+    pub vault: Pubkey,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MangoPerpMarket {
+    pub index: usize,
+    pub perp_market: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub event_queue: Pubkey,
+    pub mngo_vault: Pubkey,
+}
+
+/// A Mango group administered by the test payer, with stub oracles
+pub struct MangoTestGroup {
+    pub group: Pubkey,
+    pub signer: Pubkey,
+    pub cache: Pubkey,
+    pub quote: MangoToken,
+    pub tokens: Vec<MangoToken>,
+    pub oracles: Vec<Pubkey>,
+    pub perp_markets: Vec<MangoPerpMarket>,
+}
+
+fn signer_key_and_nonce(group: &Pubkey, program_id: &Pubkey) -> (Pubkey, u64) {
+    (0u64..)
+        .find_map(|nonce| {
+            Pubkey::create_program_address(&[group.as_ref(), &nonce.to_le_bytes()], program_id)
+                .ok()
+                .map(|key| (key, nonce))
+        })
+        .unwrap()
+}
+
+impl MangoTestGroup {
+    pub async fn create(test: &mut BlenderTest, quote_mint: &Pubkey, quote_decimals: u8) -> Self {
+        let mango = mango_program_id();
+        let admin = test.payer();
+        let group = test.create_account(size_of::<MangoGroup>(), &mango).await;
+        let (signer, signer_nonce) = signer_key_and_nonce(&group, &mango);
+        let cache = test.create_account(size_of::<MangoCache>(), &mango).await;
+        let root_bank = test.create_account(size_of::<RootBank>(), &mango).await;
+        let node_bank = test.create_account(size_of::<NodeBank>(), &mango).await;
+        let vault = test.create_token_account(quote_mint, &signer).await;
+        let insurance_vault = test.create_token_account(quote_mint, &signer).await;
+        // same as the TS setup, the fees vault is owned by the token program
+        let fees_vault = test
+            .create_token_account(quote_mint, &spl_token::id())
+            .await;
+
+        let init_group = MangoInstructions::init_mango_group(
+            &mango,
+            &group,
+            &signer,
+            &admin,
+            quote_mint,
+            &vault,
+            &node_bank,
+            &root_bank,
+            &insurance_vault,
+            &Pubkey::default(),
+            &fees_vault,
+            &cache,
+            &serum_program_id(),
+            signer_nonce,
+            VALID_INTERVAL,
+            I80F48::from_num(OPTIMAL_UTIL),
+            I80F48::from_num(OPTIMAL_RATE),
+            I80F48::from_num(MAX_RATE),
+        )
+        .unwrap();
+        test.process(&[init_group], &[]).await.unwrap();
+
+        MangoTestGroup {
+            group,
+            signer,
+            cache,
+            quote: MangoToken {
+                index: QUOTE_INDEX,
+                mint: *quote_mint,
+                decimals: quote_decimals,
+                root_bank,
+                node_bank,
+                vault,
+            },
+            tokens: vec![],
+            oracles: vec![],
+            perp_markets: vec![],
+        }
+    }
+
+    /// Adds a stub oracle, its index is the market index of the spot and perp markets using it
+    pub async fn add_oracle(&mut self, test: &mut BlenderTest, price: f64) -> usize {
+        let mango = mango_program_id();
+        let oracle = test.create_account(size_of::<StubOracle>(), &mango).await;
+        let add_oracle =
+            MangoInstructions::add_oracle(&mango, &self.group, &oracle, &test.payer()).unwrap();
+        test.process(&[add_oracle], &[]).await.unwrap();
+        self.oracles.push(oracle);
+        self.set_oracle_price(test, self.oracles.len() - 1, price)
+            .await;
+        self.oracles.len() - 1
+    }
+
+    /// For a stub oracle the price is QUOTE native per base native
+    pub async fn set_oracle_price(&self, test: &mut BlenderTest, index: usize, price: f64) {
+        let set_oracle = MangoInstructions::set_oracle(
+            &mango_program_id(),
+            &self.group,
+            &self.oracles[index],
+            &test.payer(),
+            I80F48::from_num(price),
+        )
+        .unwrap();
+        test.process(&[set_oracle], &[]).await.unwrap();
+    }
+
+    /// Lists a Serum market as the spot market of the oracle at `oracle_index`
+    pub async fn add_spot_market(
+        &mut self,
+        test: &mut BlenderTest,
+        oracle_index: usize,
+        market: &SpotMarket,
+        decimals: u8,
+    ) -> MangoToken {
+        let mango = mango_program_id();
+        let root_bank = test.create_account(size_of::<RootBank>(), &mango).await;
+        let node_bank = test.create_account(size_of::<NodeBank>(), &mango).await;
+        let vault = test
+            .create_token_account(&market.base_mint, &self.signer)
+            .await;
+
+        let add_spot_market = MangoInstructions::add_spot_market(
+            &mango,
+            &self.group,
+            &self.oracles[oracle_index],
+            &market.market,
+            &serum_program_id(),
+            &market.base_mint,
+            &node_bank,
+            &vault,
+            &root_bank,
+            &test.payer(),
+            I80F48::from_num(MAINT_LEVERAGE),
+            I80F48::from_num(INIT_LEVERAGE),
+            I80F48::from_num(LIQUIDATION_FEE),
+            I80F48::from_num(OPTIMAL_UTIL),
+            I80F48::from_num(OPTIMAL_RATE),
+            I80F48::from_num(MAX_RATE),
+        )
+        .unwrap();
+        test.process(&[add_spot_market], &[]).await.unwrap();
+
+        let token = MangoToken {
+            index: oracle_index,
+            mint: market.base_mint,
+            decimals,
+            root_bank,
+            node_bank,
+            vault,
+        };
+        self.tokens.push(token);
+        token
+    }
+
+    /// Lists a perp market on the oracle at `oracle_index`, the fixture has the MNGO mint check disabled
+    pub async fn add_perp_market(
+        &mut self,
+        test: &mut BlenderTest,
+        oracle_index: usize,
+        mngo_mint: &Pubkey,
+        base_lot_size: i64,
+        quote_lot_size: i64,
+    ) -> MangoPerpMarket {
+        let mango = mango_program_id();
+        let perp_market = test.create_account(size_of::<PerpMarket>(), &mango).await;
+        let event_queue = test
+            .create_account(
+                size_of::<EventQueueHeader>() + PERP_EVENT_QUEUE_LEN * size_of::<AnyEvent>(),
+                &mango,
+            )
+            .await;
+        let bids = test.create_account(size_of::<BookSide>(), &mango).await;
+        let asks = test.create_account(size_of::<BookSide>(), &mango).await;
+        let mngo_vault = test.create_token_account(mngo_mint, &self.signer).await;
+
+        let add_perp_market = MangoInstructions::add_perp_market(
+            &mango,
+            &self.group,
+            &self.oracles[oracle_index],
+            &perp_market,
+            &event_queue,
+            &bids,
+            &asks,
+            &mngo_vault,
+            &test.payer(),
+            I80F48::from_num(MAINT_LEVERAGE),
+            I80F48::from_num(INIT_LEVERAGE),
+            I80F48::from_num(LIQUIDATION_FEE),
+            I80F48::from_num(0.0),
+            I80F48::from_num(0.0005),
+            base_lot_size,
+            quote_lot_size,
+            I80F48::from_num(0),
+            I80F48::from_num(200),
+            3600,
+            0,
+            1,
+        )
+        .unwrap();
+        test.process(&[add_perp_market], &[]).await.unwrap();
+
+        let market = MangoPerpMarket {
+            index: oracle_index,
+            perp_market,
+            bids,
+            asks,
+            event_queue,
+            mngo_vault,
+        };
+        self.perp_markets.push(market);
+        market
+    }
+
+    pub fn token(&self, index: usize) -> &MangoToken {
+        if index == QUOTE_INDEX {
+            &self.quote
+        } else {
+            self.tokens.iter().find(|t| t.index == index).unwrap()
+        }
+    }
+
+    /// What the TS suite's keeperRefresh does: cache root banks, oracle prices and perp markets
+    pub async fn keeper_refresh(&self, test: &mut BlenderTest) {
+        let mango = mango_program_id();
+        let mut root_banks: Vec<Pubkey> = self.tokens.iter().map(|t| t.root_bank).collect();
+        root_banks.push(self.quote.root_bank);
+        let perp_markets: Vec<Pubkey> = self.perp_markets.iter().map(|p| p.perp_market).collect();
+        let mut instructions = vec![
+            MangoInstructions::cache_root_banks(&mango, &self.group, &self.cache, &root_banks)
+                .unwrap(),
+            MangoInstructions::cache_prices(&mango, &self.group, &self.cache, &self.oracles)
+                .unwrap(),
+        ];
+        if !perp_markets.is_empty() {
+            instructions.push(
+                MangoInstructions::cache_perp_markets(
+                    &mango,
+                    &self.group,
+                    &self.cache,
+                    &perp_markets,
+                )
+                .unwrap(),
+            );
+        }
+        // one transaction each, a full group's accounts don't fit in one
+        for instruction in instructions {
+            test.process(&[instruction], &[]).await.unwrap();
+        }
+    }
+
+    /// A MangoAccount for `owner` outside the pool, e.g. a counterparty to the pool's orders
+    pub async fn create_mango_account(&self, test: &mut BlenderTest, owner: &Keypair) -> Pubkey {
+        let mango_account = test
+            .create_account(size_of::<MangoAccount>(), &mango_program_id())
+            .await;
+        let init = MangoInstructions::init_mango_account(
+            &mango_program_id(),
+            &self.group,
+            &mango_account,
+            &owner.pubkey(),
+        )
+        .unwrap();
+        test.process(&[init], &[owner]).await.unwrap();
+        mango_account
+    }
+
+    /// Deposits `quantity` native units of `token` into a MangoAccount owned by `owner`
+    pub async fn deposit(
+        &self,
+        test: &mut BlenderTest,
+        mango_account: &Pubkey,
+        owner: &Keypair,
+        token_account: &Pubkey,
+        token: &MangoToken,
+        quantity: u64,
+    ) {
+        let deposit = MangoInstructions::deposit(
+            &mango_program_id(),
+            &self.group,
+            mango_account,
+            &owner.pubkey(),
+            &self.cache,
+            &token.root_bank,
+            &token.node_bank,
+            &token.vault,
+            token_account,
+            quantity,
+        )
+        .unwrap();
+        test.process(&[deposit], &[owner]).await.unwrap();
+    }
+
+    pub async fn mango_account(&self, test: &mut BlenderTest, key: &Pubkey) -> MangoAccount {
+        test.load::<MangoAccount>(key).await
+    }
+
+    /// The account's deposit of a token, in UI units
+    pub async fn deposit_ui(&self, test: &mut BlenderTest, key: &Pubkey, index: usize) -> f64 {
+        let mango_account = self.mango_account(test, key).await;
+        let cache = test.load::<MangoCache>(&self.cache).await;
+        let native = mango_account
+            .get_native_deposit(&cache.root_bank_cache[index], index)
+            .unwrap();
+        native.to_num::<f64>() / 10f64.powi(self.token(index).decimals as i32)
+    }
+
+    /// The open orders accounts a pool instruction takes as remaining accounts, one per market
+    /// (the default pubkey outside the margin basket, like the TS client's getOpenOrdersKeysInBasket)
+    pub async fn open_orders_metas(
+        &self,
+        test: &mut BlenderTest,
+        key: &Pubkey,
+    ) -> Vec<AccountMeta> {
+        let mango_account = self.mango_account(test, key).await;
+        (0..MAX_PAIRS)
+            .map(|i| {
+                let open_orders = if mango_account.in_margin_basket[i] {
+                    mango_account.spot_open_orders[i]
+                } else {
+                    Pubkey::default()
+                };
+                AccountMeta::new_readonly(open_orders, false)
+            })
+            .collect()
+    }
+
+    /// Creates the MangoAccount's open orders on a spot market, `owner` is its owner or delegate
+    pub async fn init_spot_open_orders(
+        &self,
+        test: &mut BlenderTest,
+        mango_account: &Pubkey,
+        owner: &Keypair,
+        market: &SpotMarket,
+    ) -> Pubkey {
+        let open_orders = test
+            .create_account(OPEN_ORDERS_SIZE, &serum_program_id())
+            .await;
+        let init = MangoInstructions::init_spot_open_orders(
+            &mango_program_id(),
+            &self.group,
+            mango_account,
+            &owner.pubkey(),
+            &serum_program_id(),
+            &open_orders,
+            &market.market,
+            &self.signer,
+        )
+        .unwrap();
+        test.process(&[init], &[owner]).await.unwrap();
+        open_orders
+    }
+
+    /// Places a limit order for the MangoAccount, `price` in quote lots per base lot and `quantity` in base lots
+    #[allow(clippy::too_many_arguments)]
+    pub async fn place_spot_order(
+        &self,
+        test: &mut BlenderTest,
+        mango_account: &Pubkey,
+        owner: &Keypair,
+        market: &SpotMarket,
+        side: SpotSide,
+        price: u64,
+        quantity: u64,
+        client_order_id: u64,
+    ) {
+        let mango = mango_program_id();
+        let account = self.mango_account(test, mango_account).await;
+        let base = *self
+            .tokens
+            .iter()
+            .find(|t| t.mint == market.base_mint)
+            .unwrap();
+        let dex_signer = market.vault_signer;
+        let order = NewOrderInstructionV3 {
+            side,
+            limit_price: NonZeroU64::new(price).unwrap(),
+            max_coin_qty: NonZeroU64::new(quantity).unwrap(),
+            // leaves room for taker fees
+            max_native_pc_qty_including_fees: NonZeroU64::new(
+                price * quantity * market.quote_lot_size * 101 / 100,
+            )
+            .unwrap(),
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            order_type: SpotOrderType::Limit,
+            client_order_id,
+            limit: 32,
+        };
+        let place = MangoInstructions::place_spot_order2(
+            &mango,
+            &self.group,
+            mango_account,
+            &owner.pubkey(),
+            &self.cache,
+            &serum_program_id(),
+            &market.market,
+            &market.bids,
+            &market.asks,
+            &market.request_queue,
+            &market.event_queue,
+            &market.base_vault,
+            &market.quote_vault,
+            &base.root_bank,
+            &base.node_bank,
+            &base.vault,
+            &self.quote.root_bank,
+            &self.quote.node_bank,
+            &self.quote.vault,
+            &self.signer,
+            &dex_signer,
+            &Pubkey::default(),
+            &account.spot_open_orders,
+            base.index,
+            order,
+        )
+        .unwrap();
+        test.process(&[place], &[owner]).await.unwrap();
+    }
+
+    /// Moves the MangoAccount's free balances on a spot market back into Mango
+    pub async fn settle_funds(
+        &self,
+        test: &mut BlenderTest,
+        mango_account: &Pubkey,
+        owner: &Keypair,
+        market: &SpotMarket,
+    ) {
+        let account = self.mango_account(test, mango_account).await;
+        let base = *self
+            .tokens
+            .iter()
+            .find(|t| t.mint == market.base_mint)
+            .unwrap();
+        let settle = MangoInstructions::settle_funds(
+            &mango_program_id(),
+            &self.group,
+            &self.cache,
+            &owner.pubkey(),
+            mango_account,
+            &serum_program_id(),
+            &market.market,
+            &account.spot_open_orders[base.index],
+            &self.signer,
+            &market.base_vault,
+            &market.quote_vault,
+            &base.root_bank,
+            &base.node_bank,
+            &self.quote.root_bank,
+            &self.quote.node_bank,
+            &base.vault,
+            &self.quote.vault,
+            &market.vault_signer,
+        )
+        .unwrap();
+        test.process(&[settle], &[owner]).await.unwrap();
+    }
+
+    /// Places a perp limit order for the MangoAccount, `price` in quote lots per base lot and `quantity` in base lots
+    #[allow(clippy::too_many_arguments)]
+    pub async fn place_perp_order(
+        &self,
+        test: &mut BlenderTest,
+        mango_account: &Pubkey,
+        owner: &Keypair,
+        market: &MangoPerpMarket,
+        side: PerpSide,
+        price: i64,
+        quantity: i64,
+        client_order_id: u64,
+    ) -> Result<(), TransportError> {
+        let account = self.mango_account(test, mango_account).await;
+        let open_orders: Vec<Pubkey> = (0..MAX_PAIRS)
+            .map(|i| {
+                if account.in_margin_basket[i] {
+                    account.spot_open_orders[i]
+                } else {
+                    Pubkey::default()
+                }
+            })
+            .collect();
+        let place = MangoInstructions::place_perp_order(
+            &mango_program_id(),
+            &self.group,
+            mango_account,
+            &owner.pubkey(),
+            &self.cache,
+            &market.perp_market,
+            &market.bids,
+            &market.asks,
+            &market.event_queue,
+            None,
+            &open_orders,
+            side,
+            price,
+            quantity,
+            client_order_id,
+            PerpOrderType::Limit,
+            false,
+        )
+        .unwrap();
+        test.process(&[place], &[owner]).await
+    }
+}
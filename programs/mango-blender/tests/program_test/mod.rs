@@ -0,0 +1,389 @@
+#![allow(dead_code)]
+
+use std::mem::size_of;
+use std::str::FromStr;
+
+use anchor_lang::AccountDeserialize;
+use bytemuck::{bytes_of, bytes_of_mut, Pod, Zeroable};
+use mango_blender::blender::errors::BlenderError;
+use solana_program_test::*;
+use solana_sdk::account::Account;
+use solana_sdk::bpf_loader;
+use solana_sdk::clock::Clock;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::instruction::{Instruction, InstructionError};
+use solana_sdk::program_error::ProgramError;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::rent::Rent;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use solana_sdk::transport::TransportError;
+
+pub mod mango;
+pub mod pool;
+pub mod serum;
+
+pub use mango::*;
+pub use pool::*;
+pub use serum::*;
+
+pub fn mango_program_id() -> Pubkey {
+    Pubkey::from_str("mv3ekLzLbnVPNxjSKvqBpU3ZeZXPQdEC3bp5MDEBG68").unwrap()
+}
+
+pub fn serum_program_id() -> Pubkey {
+    Pubkey::from_str("9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin").unwrap()
+}
+
+/// Loads a deployed program from tests/fixtures (the same builds the TS suite runs against)
+fn program_fixture(name: &str) -> Account {
+    let path = format!(
+        "{}/../../tests/fixtures/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    );
+    let data = std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: bpf_loader::id(),
+        executable: true,
+        rent_epoch: 0,
+    }
+}
+
+pub struct BlenderTest {
+    pub context: ProgramTestContext,
+    pub rent: Rent,
+}
+
+impl BlenderTest {
+    /// mango-blender (native, or the BPF build under `cargo test-bpf`) next to the Mango and Serum fixtures
+    pub fn program_test() -> ProgramTest {
+        let mut test = ProgramTest::new(
+            "mango_blender",
+            mango_blender::ID,
+            processor!(mango_blender::entry),
+        );
+        test.add_account(mango_program_id(), program_fixture("mango.so"));
+        test.add_account(serum_program_id(), program_fixture("serum_dex.so"));
+        test
+    }
+
+    pub async fn start() -> Self {
+        Self::start_with(Self::program_test()).await
+    }
+
+    pub async fn start_with(test: ProgramTest) -> Self {
+        let mut context = test.start_with_context().await;
+        let rent = context.banks_client.get_rent().await.unwrap();
+        BlenderTest { context, rent }
+    }
+
+    pub fn payer(&self) -> Pubkey {
+        self.context.payer.pubkey()
+    }
+
+    /// A copy of the payer (the pool and group admin) to sign alongside `test` borrows
+    pub fn payer_keypair(&self) -> Keypair {
+        Keypair::from_bytes(&self.context.payer.to_bytes()).unwrap()
+    }
+
+    /// Sends the instructions paid and signed by the test payer (plus `signers`)
+    /// Every transaction gets a new blockhash, so repeating an instruction (e.g. a keeper refresh) isn't deduplicated
+    pub async fn process(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<(), TransportError> {
+        self.context.last_blockhash = self
+            .context
+            .banks_client
+            .get_new_latest_blockhash(&self.context.last_blockhash)
+            .await?;
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            self.context.last_blockhash,
+        );
+        self.context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+    }
+
+    /// Like `process`, with the transaction capped at `max_units` compute units
+    /// Only programs running as BPF are metered, so the cap covers mango-blender under `cargo test-bpf` only
+    pub async fn process_with_compute_budget(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+        max_units: u32,
+    ) -> Result<(), TransportError> {
+        let mut capped = vec![ComputeBudgetInstruction::request_units(max_units, 0)];
+        capped.extend_from_slice(instructions);
+        self.process(&capped, signers).await
+    }
+
+    /// Like `process_with_compute_budget`, returning the fewest compute units (up to `max_units`)
+    /// the instructions run in, found by binary search over dry runs
+    pub async fn process_measured(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+        max_units: u32,
+    ) -> Result<u32, TransportError> {
+        let mut enough = max_units;
+        if self
+            .fits_compute_budget(instructions, signers, max_units)
+            .await?
+        {
+            let mut too_few = 0;
+            while enough - too_few > 1 {
+                let units = too_few + (enough - too_few) / 2;
+                if self
+                    .fits_compute_budget(instructions, signers, units)
+                    .await?
+                {
+                    enough = units;
+                } else {
+                    too_few = units;
+                }
+            }
+        }
+        self.process_with_compute_budget(instructions, signers, max_units)
+            .await?;
+        Ok(enough)
+    }
+
+    /// Dry runs the instructions capped at `units`, followed by a transfer the payer can't afford so
+    /// nothing commits: failing on the transfer means they fit, running out of compute means they didn't
+    async fn fits_compute_budget(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+        units: u32,
+    ) -> Result<bool, TransportError> {
+        let payer = self.payer();
+        let mut dry_run = instructions.to_vec();
+        dry_run.push(system_instruction::transfer(&payer, &payer, u64::MAX));
+        // after the compute budget request
+        let transfer_index = dry_run.len() as u8;
+        match self
+            .process_with_compute_budget(&dry_run, signers, units)
+            .await
+        {
+            Err(TransportError::TransactionError(TransactionError::InstructionError(index, _)))
+                if index == transfer_index =>
+            {
+                Ok(true)
+            }
+            Err(TransportError::TransactionError(TransactionError::InstructionError(
+                _,
+                InstructionError::ComputationalBudgetExceeded,
+            ))) => Ok(false),
+            Err(e) => Err(e),
+            Ok(()) => unreachable!("the payer can't afford u64::MAX lamports"),
+        }
+    }
+
+    pub async fn slot(&mut self) -> u64 {
+        self.context.banks_client.get_root_slot().await.unwrap()
+    }
+
+    /// Warps `slots` slots ahead
+    pub async fn advance_slots(&mut self, slots: u64) {
+        let slot = self.slot().await;
+        self.context.warp_to_slot(slot + slots).unwrap();
+    }
+
+    /// Moves the clock's unix timestamp forward by `seconds`
+    pub async fn advance_clock(&mut self, seconds: i64) {
+        let mut clock: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp += seconds;
+        self.context.set_sysvar(&clock);
+    }
+
+    /// A funded keypair that can pay for its own accounts
+    pub async fn create_user(&mut self, lamports: u64) -> Keypair {
+        let user = Keypair::new();
+        let transfer = system_instruction::transfer(&self.payer(), &user.pubkey(), lamports);
+        self.process(&[transfer], &[]).await.unwrap();
+        user
+    }
+
+    /// A new rent exempt account of `size` bytes owned by `owner`
+    pub async fn create_account(&mut self, size: usize, owner: &Pubkey) -> Pubkey {
+        let keypair = Keypair::new();
+        let create = system_instruction::create_account(
+            &self.payer(),
+            &keypair.pubkey(),
+            self.rent.minimum_balance(size),
+            size as u64,
+            owner,
+        );
+        self.process(&[create], &[&keypair]).await.unwrap();
+        keypair.pubkey()
+    }
+
+    /// A new mint, the test payer is its mint authority
+    pub async fn create_mint(&mut self, decimals: u8) -> Pubkey {
+        let mint = self
+            .create_account(spl_token::state::Mint::LEN, &spl_token::id())
+            .await;
+        let init = spl_token::instruction::initialize_mint(
+            &spl_token::id(),
+            &mint,
+            &self.payer(),
+            None,
+            decimals,
+        )
+        .unwrap();
+        self.process(&[init], &[]).await.unwrap();
+        mint
+    }
+
+    /// A new (non associated) token account
+    pub async fn create_token_account(&mut self, mint: &Pubkey, owner: &Pubkey) -> Pubkey {
+        let account = self
+            .create_account(spl_token::state::Account::LEN, &spl_token::id())
+            .await;
+        let init =
+            spl_token::instruction::initialize_account(&spl_token::id(), &account, mint, owner)
+                .unwrap();
+        self.process(&[init], &[]).await.unwrap();
+        account
+    }
+
+    pub async fn create_associated_token_account(
+        &mut self,
+        wallet: &Pubkey,
+        mint: &Pubkey,
+    ) -> Pubkey {
+        let create = spl_associated_token_account::create_associated_token_account(
+            &self.payer(),
+            wallet,
+            mint,
+        );
+        self.process(&[create], &[]).await.unwrap();
+        spl_associated_token_account::get_associated_token_address(wallet, mint)
+    }
+
+    pub async fn mint_to(&mut self, mint: &Pubkey, account: &Pubkey, amount: u64) {
+        let mint_to = spl_token::instruction::mint_to(
+            &spl_token::id(),
+            mint,
+            account,
+            &self.payer(),
+            &[],
+            amount,
+        )
+        .unwrap();
+        self.process(&[mint_to], &[]).await.unwrap();
+    }
+
+    pub async fn transfer_tokens(
+        &mut self,
+        owner: &Keypair,
+        from: &Pubkey,
+        to: &Pubkey,
+        amount: u64,
+    ) -> Result<(), TransportError> {
+        let transfer = spl_token::instruction::transfer(
+            &spl_token::id(),
+            from,
+            to,
+            &owner.pubkey(),
+            &[],
+            amount,
+        )
+        .unwrap();
+        self.process(&[transfer], &[owner]).await
+    }
+
+    pub async fn get_account(&mut self, key: &Pubkey) -> Account {
+        self.context
+            .banks_client
+            .get_account(*key)
+            .await
+            .unwrap()
+            .unwrap_or_else(|| panic!("account {} not found", key))
+    }
+
+    pub async fn token_balance(&mut self, key: &Pubkey) -> u64 {
+        let account = self.get_account(key).await;
+        spl_token::state::Account::unpack(&account.data)
+            .unwrap()
+            .amount
+    }
+
+    pub async fn token_mint(&mut self, key: &Pubkey) -> Pubkey {
+        let account = self.get_account(key).await;
+        spl_token::state::Account::unpack(&account.data)
+            .unwrap()
+            .mint
+    }
+
+    pub async fn delegated_amount(&mut self, key: &Pubkey) -> u64 {
+        let account = self.get_account(key).await;
+        spl_token::state::Account::unpack(&account.data)
+            .unwrap()
+            .delegated_amount
+    }
+
+    pub async fn mint_supply(&mut self, key: &Pubkey) -> u64 {
+        let account = self.get_account(key).await;
+        spl_token::state::Mint::unpack(&account.data)
+            .unwrap()
+            .supply
+    }
+
+    /// Copies a zero copy (Mango / Serum) account out of its data, which carries no alignment guarantee
+    pub async fn load<T: Pod>(&mut self, key: &Pubkey) -> T {
+        let account = self.get_account(key).await;
+        let mut value = T::zeroed();
+        bytes_of_mut(&mut value).copy_from_slice(&account.data[..size_of::<T>()]);
+        value
+    }
+
+    /// Overwrites the start of a zero copy account's data with `value`, for state the test can't reach
+    /// through instructions (e.g. MNGO rewards accrued over months)
+    pub async fn store<T: Pod>(&mut self, key: &Pubkey, value: &T) {
+        let mut account = self.get_account(key).await;
+        account.data[..size_of::<T>()].copy_from_slice(bytes_of(value));
+        self.context.set_account(key, &account.into());
+    }
+
+    pub async fn load_anchor<T: AccountDeserialize>(&mut self, key: &Pubkey) -> T {
+        let account = self.get_account(key).await;
+        T::try_deserialize(&mut account.data.as_ref()).unwrap()
+    }
+}
+
+/// The custom error code a BlenderError surfaces as
+pub fn blender_error(error: BlenderError) -> u32 {
+    match ProgramError::from(error) {
+        ProgramError::Custom(code) => code,
+        _ => unreachable!(),
+    }
+}
+
+/// Asserts the transaction failed with `code`, raised by mango-blender or by a program it invoked
+pub fn assert_custom_error(result: Result<(), TransportError>, code: u32) {
+    match result {
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            _,
+            InstructionError::Custom(error_code),
+        ))) => assert_eq!(
+            error_code, code,
+            "expected custom error {:#x}, got {:#x}",
+            code, error_code
+        ),
+        other => panic!("expected custom error {:#x}, got {:?}", code, other),
+    }
+}
@@ -0,0 +1,993 @@
+use anchor_lang::{InstructionData, ToAccountMetas};
+use mango_blender::blender::instructions::{OrderSide, ProxyOrderType};
+use mango_blender::{accounts, instruction};
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transport::TransportError;
+use solana_sdk::{system_program, sysvar};
+
+use super::{
+    mango_program_id, serum_program_id, BlenderTest, MangoPerpMarket, MangoTestGroup, MangoToken,
+    SpotMarket,
+};
+
+pub fn blender_instruction(
+    accounts: impl ToAccountMetas,
+    data: impl InstructionData,
+    remaining_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    let mut account_metas = accounts.to_account_metas(None);
+    account_metas.extend(remaining_accounts);
+    Instruction {
+        program_id: mango_blender::ID,
+        accounts: account_metas,
+        data: data.data(),
+    }
+}
+
+/// Marks `signer` as signing `instruction`, for accounts the program checks itself rather than through Anchor
+pub fn signed_by(mut instruction: Instruction, signer: &Pubkey) -> Instruction {
+    for meta in instruction.accounts.iter_mut() {
+        if meta.pubkey == *signer {
+            meta.is_signer = true;
+        }
+    }
+    instruction
+}
+
+/// Addresses of a pool administered by the test payer
+pub struct BlenderPool {
+    pub pool_name: String,
+    pub admin: Pubkey,
+    pub pool: Pubkey,
+    pub pool_bump: u8,
+    pub iou_mint: Pubkey,
+    pub iou_mint_bump: u8,
+    pub senior_iou_mint: Pubkey,
+    pub senior_iou_mint_bump: u8,
+    pub mango_account: Pubkey,
+    pub nav_snapshot: Pubkey,
+    pub nav_snapshot_bump: u8,
+    pub nav_history: Pubkey,
+    pub price_feed: Pubkey,
+    pub price_feed_bump: u8,
+    pub distribution_vault: Pubkey,
+    pub distribution_vault_bump: u8,
+}
+
+impl BlenderPool {
+    pub fn new(pool_name: &str, admin: &Pubkey, mango: &MangoTestGroup) -> Self {
+        let (pool, pool_bump) =
+            Pubkey::find_program_address(&[pool_name.as_ref(), admin.as_ref()], &mango_blender::ID);
+        let (iou_mint, iou_mint_bump) = Pubkey::find_program_address(
+            &[pool_name.as_ref(), admin.as_ref(), b"iou"],
+            &mango_blender::ID,
+        );
+        let (senior_iou_mint, senior_iou_mint_bump) = Pubkey::find_program_address(
+            &[pool_name.as_ref(), admin.as_ref(), b"senior"],
+            &mango_blender::ID,
+        );
+        let (mango_account, _) = Pubkey::find_program_address(
+            &[mango.group.as_ref(), pool.as_ref(), &1u64.to_le_bytes()],
+            &mango_program_id(),
+        );
+        let (nav_snapshot, nav_snapshot_bump) =
+            Pubkey::find_program_address(&[pool.as_ref(), b"nav"], &mango_blender::ID);
+        let (nav_history, _) =
+            Pubkey::find_program_address(&[pool.as_ref(), b"nav_history"], &mango_blender::ID);
+        let (price_feed, price_feed_bump) =
+            Pubkey::find_program_address(&[pool.as_ref(), b"price"], &mango_blender::ID);
+        let (distribution_vault, distribution_vault_bump) =
+            Pubkey::find_program_address(&[pool.as_ref(), b"distribution"], &mango_blender::ID);
+        BlenderPool {
+            pool_name: pool_name.to_string(),
+            admin: *admin,
+            pool,
+            pool_bump,
+            iou_mint,
+            iou_mint_bump,
+            senior_iou_mint,
+            senior_iou_mint_bump,
+            mango_account,
+            nav_snapshot,
+            nav_snapshot_bump,
+            nav_history,
+            price_feed,
+            price_feed_bump,
+            distribution_vault,
+            distribution_vault_bump,
+        }
+    }
+
+    pub fn position(&self, owner: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[self.pool.as_ref(), owner.as_ref(), b"position"],
+            &mango_blender::ID,
+        )
+    }
+
+    /// Creates the pool, its MangoAccount is delegated to the admin
+    pub async fn create(&self, test: &mut BlenderTest, mango: &MangoTestGroup) {
+        let create_pool = blender_instruction(
+            accounts::CreatePool {
+                pool: self.pool,
+                admin: self.admin,
+                mango_program: mango_program_id(),
+                mango_group: mango.group,
+                mango_account: self.mango_account,
+                pool_iou_mint: self.iou_mint,
+                system_program: system_program::ID,
+                token_program: spl_token::ID,
+                rent: sysvar::rent::ID,
+            },
+            instruction::CreatePool {
+                pool_name: self.pool_name.clone(),
+                pool_bump: self.pool_bump,
+                iou_mint_bump: self.iou_mint_bump,
+            },
+            vec![],
+        );
+        test.process(&[create_pool], &[]).await.unwrap();
+    }
+
+    pub async fn create_nav_snapshot(&self, test: &mut BlenderTest) {
+        let create_nav_snapshot = blender_instruction(
+            accounts::CreateNavSnapshot {
+                pool: self.pool,
+                nav_snapshot: self.nav_snapshot,
+                payer: test.payer(),
+                system_program: system_program::ID,
+            },
+            instruction::CreateNavSnapshot {
+                bump: self.nav_snapshot_bump,
+            },
+            vec![],
+        );
+        test.process(&[create_nav_snapshot], &[]).await.unwrap();
+    }
+
+    pub async fn create_nav_history(&self, test: &mut BlenderTest) {
+        let create_nav_history = blender_instruction(
+            accounts::CreateNavHistory {
+                pool: self.pool,
+                nav_history: self.nav_history,
+                payer: test.payer(),
+                system_program: system_program::ID,
+            },
+            instruction::CreateNavHistory {},
+            vec![],
+        );
+        test.process(&[create_nav_history], &[]).await.unwrap();
+    }
+
+    pub async fn create_price_feed(&self, test: &mut BlenderTest) {
+        let create_price_feed = blender_instruction(
+            accounts::CreatePriceFeed {
+                pool: self.pool,
+                admin: self.admin,
+                price_feed: self.price_feed,
+                system_program: system_program::ID,
+            },
+            instruction::CreatePriceFeed {
+                bump: self.price_feed_bump,
+            },
+            vec![],
+        );
+        test.process(&[create_price_feed], &[]).await.unwrap();
+    }
+
+    pub async fn create_senior_tranche(&self, test: &mut BlenderTest, senior_rate_bps: u16) {
+        let create_senior_tranche = blender_instruction(
+            accounts::CreateSeniorTranche {
+                pool: self.pool,
+                admin: self.admin,
+                pool_iou_mint: self.iou_mint,
+                senior_iou_mint: self.senior_iou_mint,
+                system_program: system_program::ID,
+                token_program: spl_token::ID,
+                rent: sysvar::rent::ID,
+            },
+            instruction::CreateSeniorTranche {
+                senior_iou_mint_bump: self.senior_iou_mint_bump,
+                senior_rate_bps,
+            },
+            vec![],
+        );
+        test.process(&[create_senior_tranche], &[]).await.unwrap();
+    }
+
+    pub async fn create_depositor_position(&self, test: &mut BlenderTest, depositor: &Keypair) {
+        let (depositor_position, bump) = self.position(&depositor.pubkey());
+        let create_position = blender_instruction(
+            accounts::CreateDepositorPosition {
+                pool: self.pool,
+                depositor_position,
+                depositor: depositor.pubkey(),
+                system_program: system_program::ID,
+            },
+            instruction::CreateDepositorPosition { bump },
+            vec![],
+        );
+        test.process(&[create_position], &[depositor])
+            .await
+            .unwrap();
+    }
+
+    /// The tranche is picked by the mint of `depositor_iou_token_account`
+    #[allow(clippy::too_many_arguments)]
+    pub async fn buy_into_pool_instruction(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+        depositor: &Pubkey,
+        depositor_token_account: &Pubkey,
+        depositor_iou_token_account: &Pubkey,
+        token: &MangoToken,
+        quantity: u64,
+    ) -> Instruction {
+        let pool_iou_mint = test.token_mint(depositor_iou_token_account).await;
+        let remaining_accounts = mango.open_orders_metas(test, &self.mango_account).await;
+        blender_instruction(
+            accounts::BuyIntoPool {
+                mango_program: mango_program_id(),
+                pool: self.pool,
+                mango_group: mango.group,
+                mango_account: self.mango_account,
+                depositor: *depositor,
+                mango_cache: mango.cache,
+                root_bank: token.root_bank,
+                node_bank: token.node_bank,
+                vault: token.vault,
+                depositor_quote_token_account: *depositor_token_account,
+                pool_iou_mint,
+                depositor_iou_token_account: *depositor_iou_token_account,
+                depositor_position: self.position(depositor).0,
+                nav_snapshot: self.nav_snapshot,
+                price_feed: self.price_feed,
+                instructions: sysvar::instructions::ID,
+                token_program: spl_token::ID,
+            },
+            instruction::BuyIntoPool { quantity },
+            remaining_accounts,
+        )
+    }
+
+    /// Deposits `quantity` native units of `token`, after refreshing the Mango cache in a separate transaction
+    #[allow(clippy::too_many_arguments)]
+    pub async fn buy_into_pool(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+        depositor: &Keypair,
+        depositor_token_account: &Pubkey,
+        depositor_iou_token_account: &Pubkey,
+        token: &MangoToken,
+        quantity: u64,
+    ) -> Result<(), TransportError> {
+        mango.keeper_refresh(test).await;
+        let buy = self
+            .buy_into_pool_instruction(
+                test,
+                mango,
+                &depositor.pubkey(),
+                depositor_token_account,
+                depositor_iou_token_account,
+                token,
+                quantity,
+            )
+            .await;
+        test.process(&[buy], &[depositor]).await
+    }
+
+    /// The tranche is picked by the mint of `withdrawer_iou_token_account`
+    #[allow(clippy::too_many_arguments)]
+    pub async fn withdraw_from_pool_instruction(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+        withdrawer: &Pubkey,
+        withdrawer_token_account: &Pubkey,
+        withdrawer_iou_token_account: &Pubkey,
+        token: &MangoToken,
+        quantity: u64,
+    ) -> Instruction {
+        let withdraw = self
+            .withdraw_instruction(
+                test,
+                mango,
+                withdrawer,
+                withdrawer_token_account,
+                withdrawer_iou_token_account,
+                token,
+                instruction::WithdrawFromPool { quantity },
+            )
+            .await;
+        signed_by(withdraw, withdrawer)
+    }
+
+    /// A keeper paying out the withdrawer's queued request, the withdrawer doesn't sign
+    #[allow(clippy::too_many_arguments)]
+    pub async fn process_queued_withdrawal_instruction(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+        withdrawer: &Pubkey,
+        withdrawer_token_account: &Pubkey,
+        withdrawer_iou_token_account: &Pubkey,
+        token: &MangoToken,
+    ) -> Instruction {
+        self.withdraw_instruction(
+            test,
+            mango,
+            withdrawer,
+            withdrawer_token_account,
+            withdrawer_iou_token_account,
+            token,
+            instruction::ProcessQueuedWithdrawal {},
+        )
+        .await
+    }
+
+    /// Pays out the withdrawer's queued request as a keeper, without the withdrawer signing
+    #[allow(clippy::too_many_arguments)]
+    pub async fn process_queued_withdrawal(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+        withdrawer: &Pubkey,
+        withdrawer_token_account: &Pubkey,
+        withdrawer_iou_token_account: &Pubkey,
+        token: &MangoToken,
+    ) -> Result<(), TransportError> {
+        mango.keeper_refresh(test).await;
+        let process = self
+            .process_queued_withdrawal_instruction(
+                test,
+                mango,
+                withdrawer,
+                withdrawer_token_account,
+                withdrawer_iou_token_account,
+                token,
+            )
+            .await;
+        test.process(&[process], &[]).await
+    }
+
+    /// An instruction taking `WithdrawFromPool` accounts, the withdrawer isn't marked as a signer
+    #[allow(clippy::too_many_arguments)]
+    async fn withdraw_instruction(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+        withdrawer: &Pubkey,
+        withdrawer_token_account: &Pubkey,
+        withdrawer_iou_token_account: &Pubkey,
+        token: &MangoToken,
+        data: impl InstructionData,
+    ) -> Instruction {
+        let pool_iou_mint = test.token_mint(withdrawer_iou_token_account).await;
+        let remaining_accounts = mango.open_orders_metas(test, &self.mango_account).await;
+        blender_instruction(
+            accounts::WithdrawFromPool {
+                mango_program: mango_program_id(),
+                pool: self.pool,
+                mango_group: mango.group,
+                mango_group_signer: mango.signer,
+                mango_account: self.mango_account,
+                withdrawer: *withdrawer,
+                mango_cache: mango.cache,
+                root_bank: token.root_bank,
+                node_bank: token.node_bank,
+                vault: token.vault,
+                withdrawer_token_account: *withdrawer_token_account,
+                pool_iou_mint,
+                withdrawer_iou_token_account: *withdrawer_iou_token_account,
+                withdrawer_position: self.position(withdrawer).0,
+                nav_snapshot: self.nav_snapshot,
+                price_feed: self.price_feed,
+                token_program: spl_token::ID,
+            },
+            data,
+            remaining_accounts,
+        )
+    }
+
+    /// Withdraws `quantity` native units of `token`, after refreshing the Mango cache in a separate transaction
+    #[allow(clippy::too_many_arguments)]
+    pub async fn withdraw_from_pool(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+        withdrawer: &Keypair,
+        withdrawer_token_account: &Pubkey,
+        withdrawer_iou_token_account: &Pubkey,
+        token: &MangoToken,
+        quantity: u64,
+    ) -> Result<(), TransportError> {
+        mango.keeper_refresh(test).await;
+        let withdraw = self
+            .withdraw_from_pool_instruction(
+                test,
+                mango,
+                &withdrawer.pubkey(),
+                withdrawer_token_account,
+                withdrawer_iou_token_account,
+                token,
+                quantity,
+            )
+            .await;
+        test.process(&[withdraw], &[withdrawer]).await
+    }
+
+    /// Withdraws `quantity` QUOTE, selling the pool's `market` base token for whatever its QUOTE deposit can't cover
+    #[allow(clippy::too_many_arguments)]
+    pub async fn withdraw_with_unwind_instruction(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+        withdrawer: &Pubkey,
+        withdrawer_token_account: &Pubkey,
+        withdrawer_iou_token_account: &Pubkey,
+        market: &SpotMarket,
+        quantity: u64,
+        max_slippage_bps: u16,
+    ) -> Instruction {
+        let pool_iou_mint = test.token_mint(withdrawer_iou_token_account).await;
+        let remaining_accounts = mango.open_orders_metas(test, &self.mango_account).await;
+        let withdraw = blender_instruction(
+            accounts::WithdrawWithUnwind {
+                withdraw: accounts::WithdrawFromPool {
+                    mango_program: mango_program_id(),
+                    pool: self.pool,
+                    mango_group: mango.group,
+                    mango_group_signer: mango.signer,
+                    mango_account: self.mango_account,
+                    withdrawer: *withdrawer,
+                    mango_cache: mango.cache,
+                    root_bank: mango.quote.root_bank,
+                    node_bank: mango.quote.node_bank,
+                    vault: mango.quote.vault,
+                    withdrawer_token_account: *withdrawer_token_account,
+                    pool_iou_mint,
+                    withdrawer_iou_token_account: *withdrawer_iou_token_account,
+                    withdrawer_position: self.position(withdrawer).0,
+                    nav_snapshot: self.nav_snapshot,
+                    price_feed: self.price_feed,
+                    token_program: spl_token::ID,
+                },
+                unwind: self.spot_market_accounts(test, mango, market).await,
+            },
+            instruction::WithdrawWithUnwind {
+                quantity,
+                max_slippage_bps,
+            },
+            remaining_accounts,
+        );
+        signed_by(withdraw, withdrawer)
+    }
+
+    /// The pool's Serum accounts on `market`, its open orders there must exist
+    async fn spot_market_accounts(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+        market: &SpotMarket,
+    ) -> accounts::SpotMarketAccounts {
+        let base = *mango
+            .tokens
+            .iter()
+            .find(|t| t.mint == market.base_mint)
+            .unwrap();
+        let mango_account = mango.mango_account(test, &self.mango_account).await;
+        accounts::SpotMarketAccounts {
+            dex_program: serum_program_id(),
+            spot_market: market.market,
+            bids: market.bids,
+            asks: market.asks,
+            dex_request_queue: market.request_queue,
+            dex_event_queue: market.event_queue,
+            dex_base: market.base_vault,
+            dex_quote: market.quote_vault,
+            dex_signer: market.vault_signer,
+            base_root_bank: base.root_bank,
+            base_node_bank: base.node_bank,
+            base_vault: base.vault,
+            spot_open_orders: mango_account.spot_open_orders[base.index],
+            msrm_or_srm_vault: Pubkey::default(),
+        }
+    }
+
+    /// Sends an admin setting instruction that takes `UpdatePool` accounts
+    pub async fn update_pool(
+        &self,
+        test: &mut BlenderTest,
+        data: impl InstructionData,
+    ) -> Result<(), TransportError> {
+        let update = blender_instruction(
+            accounts::UpdatePool {
+                pool: self.pool,
+                admin: self.admin,
+            },
+            data,
+            vec![],
+        );
+        test.process(&[update], &[]).await
+    }
+
+    pub async fn apply_risk_limits(&self, test: &mut BlenderTest) -> Result<(), TransportError> {
+        let apply = blender_instruction(
+            accounts::ApplyRiskLimits { pool: self.pool },
+            instruction::ApplyRiskLimits,
+            vec![],
+        );
+        test.process(&[apply], &[]).await
+    }
+
+    pub async fn apply_max_drawdown(&self, test: &mut BlenderTest) -> Result<(), TransportError> {
+        let apply = blender_instruction(
+            accounts::ApplyMaxDrawdown { pool: self.pool },
+            instruction::ApplyMaxDrawdown,
+            vec![],
+        );
+        test.process(&[apply], &[]).await
+    }
+
+    /// Revokes the admin's delegation for good, trading goes through the proxy instructions from then on
+    pub async fn enable_proxy_only(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+    ) -> Result<(), TransportError> {
+        let enable_proxy_only = blender_instruction(
+            accounts::SetDelegate {
+                pool: self.pool,
+                admin: self.admin,
+                mango_program: mango_program_id(),
+                mango_group: mango.group,
+                mango_account: self.mango_account,
+                delegate: system_program::ID,
+            },
+            instruction::EnableProxyOnly {},
+            vec![],
+        );
+        test.process(&[enable_proxy_only], &[]).await
+    }
+
+    /// A limit order through the pool, `price` in quote lots per base lot and `quantity` in base lots
+    #[allow(clippy::too_many_arguments)]
+    pub async fn proxy_place_perp_order_instruction(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+        perp_market: &MangoPerpMarket,
+        side: OrderSide,
+        price: i64,
+        quantity: i64,
+        client_order_id: u64,
+    ) -> Instruction {
+        let remaining_accounts = mango.open_orders_metas(test, &self.mango_account).await;
+        blender_instruction(
+            accounts::ProxyPlacePerpOrder {
+                mango_program: mango_program_id(),
+                pool: self.pool,
+                admin: self.admin,
+                mango_group: mango.group,
+                mango_account: self.mango_account,
+                mango_cache: mango.cache,
+                perp_market: perp_market.perp_market,
+                bids: perp_market.bids,
+                asks: perp_market.asks,
+                event_queue: perp_market.event_queue,
+            },
+            instruction::ProxyPlacePerpOrder {
+                side,
+                price,
+                quantity,
+                client_order_id,
+                order_type: ProxyOrderType::Limit,
+                reduce_only: false,
+            },
+            remaining_accounts,
+        )
+    }
+
+    pub fn proxy_cancel_perp_order_instruction(
+        &self,
+        mango: &MangoTestGroup,
+        perp_market: &MangoPerpMarket,
+        order_id: i128,
+    ) -> Instruction {
+        blender_instruction(
+            accounts::ProxyCancelPerpOrder {
+                mango_program: mango_program_id(),
+                pool: self.pool,
+                admin: self.admin,
+                mango_group: mango.group,
+                mango_account: self.mango_account,
+                perp_market: perp_market.perp_market,
+                bids: perp_market.bids,
+                asks: perp_market.asks,
+            },
+            instruction::ProxyCancelPerpOrder {
+                order_id,
+                invalid_id_ok: false,
+            },
+            vec![],
+        )
+    }
+
+    /// A limit order through the pool, `limit_price` in quote lots per base lot and `max_coin_qty` in base lots
+    #[allow(clippy::too_many_arguments)]
+    pub async fn proxy_place_spot_order_instruction(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+        market: &SpotMarket,
+        side: OrderSide,
+        limit_price: u64,
+        max_coin_qty: u64,
+        client_order_id: u64,
+    ) -> Instruction {
+        let remaining_accounts = mango.open_orders_metas(test, &self.mango_account).await;
+        blender_instruction(
+            accounts::ProxyPlaceSpotOrder {
+                mango_program: mango_program_id(),
+                pool: self.pool,
+                admin: self.admin,
+                mango_group: mango.group,
+                mango_group_signer: mango.signer,
+                mango_account: self.mango_account,
+                mango_cache: mango.cache,
+                quote_root_bank: mango.quote.root_bank,
+                quote_node_bank: mango.quote.node_bank,
+                quote_vault: mango.quote.vault,
+                token_program: spl_token::ID,
+                spot: self.spot_market_accounts(test, mango, market).await,
+            },
+            instruction::ProxyPlaceSpotOrder {
+                side,
+                limit_price,
+                max_coin_qty,
+                // leaves room for taker fees
+                max_native_pc_qty_including_fees: limit_price
+                    * max_coin_qty
+                    * market.quote_lot_size
+                    * 101
+                    / 100,
+                client_order_id,
+                order_type: ProxyOrderType::Limit,
+            },
+            remaining_accounts,
+        )
+    }
+
+    pub async fn proxy_cancel_spot_order_instruction(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+        market: &SpotMarket,
+        side: OrderSide,
+        order_id: u128,
+    ) -> Instruction {
+        let spot = self.spot_market_accounts(test, mango, market).await;
+        blender_instruction(
+            accounts::ProxyCancelSpotOrder {
+                mango_program: mango_program_id(),
+                pool: self.pool,
+                admin: self.admin,
+                mango_group: mango.group,
+                mango_group_signer: mango.signer,
+                mango_account: self.mango_account,
+                dex_program: serum_program_id(),
+                spot_market: market.market,
+                bids: market.bids,
+                asks: market.asks,
+                spot_open_orders: spot.spot_open_orders,
+                dex_event_queue: market.event_queue,
+            },
+            instruction::ProxyCancelSpotOrder { side, order_id },
+            vec![],
+        )
+    }
+
+    pub async fn refresh_nav_instruction(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+    ) -> Instruction {
+        let remaining_accounts = mango.open_orders_metas(test, &self.mango_account).await;
+        blender_instruction(
+            accounts::RefreshNav {
+                mango_program: mango_program_id(),
+                pool: self.pool,
+                mango_group: mango.group,
+                mango_account: self.mango_account,
+                mango_cache: mango.cache,
+                pool_iou_mint: self.iou_mint,
+                nav_snapshot: self.nav_snapshot,
+                nav_history: self.nav_history,
+                price_feed: self.price_feed,
+            },
+            instruction::RefreshNav {},
+            remaining_accounts,
+        )
+    }
+
+    pub async fn trip_circuit_breaker_instruction(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+    ) -> Instruction {
+        let remaining_accounts = mango.open_orders_metas(test, &self.mango_account).await;
+        blender_instruction(
+            accounts::TripCircuitBreaker {
+                mango_program: mango_program_id(),
+                pool: self.pool,
+                mango_group: mango.group,
+                mango_account: self.mango_account,
+                mango_cache: mango.cache,
+                pool_iou_mint: self.iou_mint,
+                system_program: system_program::ID,
+            },
+            instruction::TripCircuitBreaker {},
+            remaining_accounts,
+        )
+    }
+
+    pub async fn trip_circuit_breaker(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+    ) -> Result<(), TransportError> {
+        let trip = self.trip_circuit_breaker_instruction(test, mango).await;
+        test.process(&[trip], &[]).await
+    }
+
+    pub async fn start_wind_down(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+        wind_down_slippage_bps: u16,
+    ) -> Result<(), TransportError> {
+        let start_wind_down = blender_instruction(
+            accounts::StartWindDown {
+                pool: self.pool,
+                admin: self.admin,
+                mango_program: mango_program_id(),
+                mango_group: mango.group,
+                mango_account: self.mango_account,
+                system_program: system_program::ID,
+            },
+            instruction::StartWindDown {
+                wind_down_slippage_bps,
+            },
+            vec![],
+        );
+        test.process(&[start_wind_down], &[]).await
+    }
+
+    /// Cancels up to `limit` of the pool's orders on `perp_market` and closes its position there
+    pub async fn unwind_perp_instruction(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+        perp_market: &MangoPerpMarket,
+        limit: u8,
+    ) -> Instruction {
+        let remaining_accounts = mango.open_orders_metas(test, &self.mango_account).await;
+        blender_instruction(
+            accounts::UnwindPerp {
+                mango_program: mango_program_id(),
+                pool: self.pool,
+                mango_group: mango.group,
+                mango_account: self.mango_account,
+                mango_cache: mango.cache,
+                perp_market: perp_market.perp_market,
+                bids: perp_market.bids,
+                asks: perp_market.asks,
+                event_queue: perp_market.event_queue,
+            },
+            instruction::UnwindPerp { limit },
+            remaining_accounts,
+        )
+    }
+
+    /// Sells the pool's deposit of `market`'s base token (or buys back its borrow)
+    pub async fn unwind_spot_instruction(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+        market: &SpotMarket,
+    ) -> Instruction {
+        let remaining_accounts = mango.open_orders_metas(test, &self.mango_account).await;
+        blender_instruction(
+            accounts::UnwindSpot {
+                mango_program: mango_program_id(),
+                pool: self.pool,
+                mango_group: mango.group,
+                mango_account: self.mango_account,
+                mango_cache: mango.cache,
+                mango_group_signer: mango.signer,
+                quote_root_bank: mango.quote.root_bank,
+                quote_node_bank: mango.quote.node_bank,
+                quote_vault: mango.quote.vault,
+                spot: self.spot_market_accounts(test, mango, market).await,
+                token_program: spl_token::ID,
+            },
+            instruction::UnwindSpot {},
+            remaining_accounts,
+        )
+    }
+
+    /// Redeems the MNGO accrued on every perp market of the group, `mngo` is the group's MNGO token
+    pub fn redeem_mngo_instruction(
+        &self,
+        mango: &MangoTestGroup,
+        mngo: &MangoToken,
+    ) -> Instruction {
+        let remaining_accounts = mango
+            .perp_markets
+            .iter()
+            .flat_map(|perp_market| {
+                vec![
+                    AccountMeta::new_readonly(perp_market.perp_market, false),
+                    AccountMeta::new(perp_market.mngo_vault, false),
+                ]
+            })
+            .collect();
+        blender_instruction(
+            accounts::RedeemMngo {
+                mango_program: mango_program_id(),
+                pool: self.pool,
+                mango_group: mango.group,
+                mango_cache: mango.cache,
+                mango_account: self.mango_account,
+                mngo_root_bank: mngo.root_bank,
+                mngo_node_bank: mngo.node_bank,
+                mngo_bank_vault: mngo.vault,
+                mango_group_signer: mango.signer,
+                token_program: spl_token::ID,
+            },
+            instruction::RedeemMngo {},
+            remaining_accounts,
+        )
+    }
+
+    pub async fn create_distribution_vault(&self, test: &mut BlenderTest, mango: &MangoTestGroup) {
+        let create_distribution_vault = blender_instruction(
+            accounts::CreateDistributionVault {
+                pool: self.pool,
+                admin: self.admin,
+                mango_program: mango_program_id(),
+                mango_group: mango.group,
+                mango_account: self.mango_account,
+                quote_mint: mango.quote.mint,
+                distribution_vault: self.distribution_vault,
+                system_program: system_program::ID,
+                token_program: spl_token::ID,
+                rent: sysvar::rent::ID,
+            },
+            instruction::CreateDistributionVault {
+                bump: self.distribution_vault_bump,
+            },
+            vec![],
+        );
+        test.process(&[create_distribution_vault], &[])
+            .await
+            .unwrap();
+    }
+
+    pub async fn distribute_instruction(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+    ) -> Instruction {
+        let remaining_accounts = mango.open_orders_metas(test, &self.mango_account).await;
+        blender_instruction(
+            accounts::Distribute {
+                mango_program: mango_program_id(),
+                pool: self.pool,
+                mango_group: mango.group,
+                mango_group_signer: mango.signer,
+                mango_account: self.mango_account,
+                mango_cache: mango.cache,
+                root_bank: mango.quote.root_bank,
+                node_bank: mango.quote.node_bank,
+                vault: mango.quote.vault,
+                distribution_vault: self.distribution_vault,
+                pool_iou_mint: self.iou_mint,
+                nav_snapshot: self.nav_snapshot,
+                token_program: spl_token::ID,
+            },
+            instruction::Distribute {},
+            remaining_accounts,
+        )
+    }
+
+    /// Cranks a distribution, after refreshing the Mango cache in a separate transaction
+    pub async fn distribute(
+        &self,
+        test: &mut BlenderTest,
+        mango: &MangoTestGroup,
+    ) -> Result<(), TransportError> {
+        mango.keeper_refresh(test).await;
+        let distribute = self.distribute_instruction(test, mango).await;
+        test.process(&[distribute], &[]).await
+    }
+
+    pub async fn claim_distribution(
+        &self,
+        test: &mut BlenderTest,
+        holder: &Keypair,
+        holder_iou_token_account: &Pubkey,
+        holder_token_account: &Pubkey,
+    ) -> Result<(), TransportError> {
+        let claim = blender_instruction(
+            accounts::ClaimDistribution {
+                pool: self.pool,
+                holder: holder.pubkey(),
+                holder_position: self.position(&holder.pubkey()).0,
+                pool_iou_mint: self.iou_mint,
+                holder_iou_token_account: *holder_iou_token_account,
+                distribution_vault: self.distribution_vault,
+                holder_token_account: *holder_token_account,
+                token_program: spl_token::ID,
+            },
+            instruction::ClaimDistribution {},
+            vec![],
+        );
+        test.process(&[claim], &[holder]).await
+    }
+
+    pub async fn thaw_ious(
+        &self,
+        test: &mut BlenderTest,
+        owner: &Pubkey,
+        iou_mint: &Pubkey,
+        iou_token_account: &Pubkey,
+    ) -> Result<(), TransportError> {
+        let thaw = blender_instruction(
+            accounts::ThawIous {
+                pool: self.pool,
+                owner: *owner,
+                position: self.position(owner).0,
+                pool_iou_mint: *iou_mint,
+                iou_token_account: *iou_token_account,
+                token_program: spl_token::ID,
+            },
+            instruction::ThawIous {},
+            vec![],
+        );
+        test.process(&[thaw], &[]).await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn transfer_ious(
+        &self,
+        test: &mut BlenderTest,
+        owner: &Keypair,
+        recipient: &Pubkey,
+        iou_mint: &Pubkey,
+        owner_iou_token_account: &Pubkey,
+        recipient_iou_token_account: &Pubkey,
+        amount: u64,
+    ) -> Result<(), TransportError> {
+        let transfer = blender_instruction(
+            accounts::TransferIous {
+                pool: self.pool,
+                owner: owner.pubkey(),
+                owner_position: self.position(&owner.pubkey()).0,
+                recipient: *recipient,
+                recipient_position: self.position(recipient).0,
+                pool_iou_mint: *iou_mint,
+                owner_iou_token_account: *owner_iou_token_account,
+                recipient_iou_token_account: *recipient_iou_token_account,
+                token_program: spl_token::ID,
+            },
+            instruction::TransferIous { amount },
+            vec![],
+        );
+        test.process(&[transfer], &[owner]).await
+    }
+}
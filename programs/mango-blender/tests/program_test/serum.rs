@@ -0,0 +1,193 @@
+use std::mem::size_of;
+use std::num::NonZeroU64;
+
+use bytemuck::{bytes_of_mut, Zeroable};
+use serum_dex::instruction::{self as serum_instruction, SelfTradeBehavior};
+use serum_dex::matching::{OrderType, Side};
+use serum_dex::state::{gen_vault_signer_key, OpenOrders};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::sysvar;
+
+use super::{serum_program_id, BlenderTest};
+
+// sizes from serum-dex's crank, https://github.com/project-serum/serum-dex/blob/master/dex/crank/src/lib.rs
+const MARKET_SIZE: usize = 376 + 12;
+const REQUEST_QUEUE_SIZE: usize = 640 + 12;
+const EVENT_QUEUE_SIZE: usize = 1048576 + 12;
+const ORDERBOOK_SIZE: usize = 65536 + 12;
+pub const OPEN_ORDERS_SIZE: usize = 3216 + 12;
+
+/// A Serum market between a base token and the group's QUOTE
+pub struct SpotMarket {
+    pub market: Pubkey,
+    pub request_queue: Pubkey,
+    pub event_queue: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub base_vault: Pubkey,
+    pub quote_vault: Pubkey,
+    pub vault_signer: Pubkey,
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    pub base_lot_size: u64,
+    pub quote_lot_size: u64,
+}
+
+impl SpotMarket {
+    pub async fn list(
+        test: &mut BlenderTest,
+        base_mint: &Pubkey,
+        quote_mint: &Pubkey,
+        base_lot_size: u64,
+        quote_lot_size: u64,
+    ) -> Self {
+        let serum = serum_program_id();
+        let market = test.create_account(MARKET_SIZE, &serum).await;
+        let request_queue = test.create_account(REQUEST_QUEUE_SIZE, &serum).await;
+        let event_queue = test.create_account(EVENT_QUEUE_SIZE, &serum).await;
+        let bids = test.create_account(ORDERBOOK_SIZE, &serum).await;
+        let asks = test.create_account(ORDERBOOK_SIZE, &serum).await;
+
+        let (vault_signer, vault_signer_nonce) = (0u64..)
+            .find_map(|nonce| {
+                gen_vault_signer_key(nonce, &market, &serum)
+                    .ok()
+                    .map(|key| (key, nonce))
+            })
+            .unwrap();
+        let base_vault = test.create_token_account(base_mint, &vault_signer).await;
+        let quote_vault = test.create_token_account(quote_mint, &vault_signer).await;
+
+        let init_market = serum_instruction::initialize_market(
+            &market,
+            &serum,
+            base_mint,
+            quote_mint,
+            &base_vault,
+            &quote_vault,
+            None,
+            None,
+            &bids,
+            &asks,
+            &request_queue,
+            &event_queue,
+            base_lot_size,
+            quote_lot_size,
+            vault_signer_nonce,
+            100,
+        )
+        .unwrap();
+        test.process(&[init_market], &[]).await.unwrap();
+
+        SpotMarket {
+            market,
+            request_queue,
+            event_queue,
+            bids,
+            asks,
+            base_vault,
+            quote_vault,
+            vault_signer,
+            base_mint: *base_mint,
+            quote_mint: *quote_mint,
+            base_lot_size,
+            quote_lot_size,
+        }
+    }
+
+    /// An open orders account for trading on the market directly (outside Mango)
+    pub async fn create_open_orders(&self, test: &mut BlenderTest) -> Pubkey {
+        test.create_account(OPEN_ORDERS_SIZE, &serum_program_id())
+            .await
+    }
+
+    /// Places a limit order straight on the Serum market, `price` in quote lots per base lot and `quantity` in base lots
+    #[allow(clippy::too_many_arguments)]
+    pub async fn place_order(
+        &self,
+        test: &mut BlenderTest,
+        owner: &Keypair,
+        open_orders: &Pubkey,
+        payer: &Pubkey,
+        side: Side,
+        price: u64,
+        quantity: u64,
+    ) {
+        let new_order = serum_instruction::new_order(
+            &self.market,
+            open_orders,
+            &self.request_queue,
+            &self.event_queue,
+            &self.bids,
+            &self.asks,
+            payer,
+            &owner.pubkey(),
+            &self.base_vault,
+            &self.quote_vault,
+            &spl_token::id(),
+            &sysvar::rent::id(),
+            None,
+            &serum_program_id(),
+            side,
+            NonZeroU64::new(price).unwrap(),
+            NonZeroU64::new(quantity).unwrap(),
+            OrderType::Limit,
+            0,
+            SelfTradeBehavior::DecrementTake,
+            u16::MAX,
+            NonZeroU64::new(price * quantity * self.quote_lot_size * 2).unwrap(),
+        )
+        .unwrap();
+        test.process(&[new_order], &[owner]).await.unwrap();
+    }
+
+    /// Cranks the event queue so fills land in the open orders accounts
+    pub async fn consume_events(&self, test: &mut BlenderTest, open_orders: &[Pubkey]) {
+        let mut open_orders = open_orders.to_vec();
+        open_orders.sort();
+        open_orders.dedup();
+        let consume = serum_instruction::consume_events(
+            &serum_program_id(),
+            open_orders.iter().collect(),
+            &self.market,
+            &self.event_queue,
+            &self.base_vault,
+            &self.quote_vault,
+            u16::MAX,
+        )
+        .unwrap();
+        test.process(&[consume], &[]).await.unwrap();
+    }
+
+    /// The orders an open orders account has resting on the book, with their side
+    pub async fn resting_orders(
+        &self,
+        test: &mut BlenderTest,
+        open_orders: &Pubkey,
+    ) -> Vec<(Side, u128)> {
+        let account = test.get_account(open_orders).await;
+        // Serum accounts start with 5 bytes of padding
+        let mut state = OpenOrders::zeroed();
+        bytes_of_mut(&mut state).copy_from_slice(&account.data[5..5 + size_of::<OpenOrders>()]);
+        let (free_slot_bits, is_bid_bits, orders) =
+            (state.free_slot_bits, state.is_bid_bits, state.orders);
+        (0..orders.len())
+            .filter(|&slot| free_slot_bits & (1 << slot) == 0)
+            .map(|slot| {
+                let side = if is_bid_bits & (1 << slot) != 0 {
+                    Side::Bid
+                } else {
+                    Side::Ask
+                };
+                (side, orders[slot])
+            })
+            .collect()
+    }
+
+    /// Converts a UI price (QUOTE per base token) into quote lots per base lot
+    pub fn price_lots(&self, price: f64, base_decimals: u8, quote_decimals: u8) -> u64 {
+        (price * 10f64.powi(quote_decimals as i32) * self.base_lot_size as f64
+            / (self.quote_lot_size as f64 * 10f64.powi(base_decimals as i32))) as u64
+    }
+}
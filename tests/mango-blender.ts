@@ -7,6 +7,7 @@ import {
   PublicKey,
   sendAndConfirmTransaction,
   SystemProgram,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
   SYSVAR_RENT_PUBKEY,
   Transaction,
 } from "@solana/web3.js";
@@ -37,6 +38,7 @@ import {
   getMarketByBaseSymbolAndKind,
   MangoClient,
   QUOTE_INDEX,
+  sleep,
   uiToNative,
   ZERO_BN,
 } from "@blockworks-foundation/mango-client";
@@ -91,6 +93,15 @@ describe("mango-blender", () => {
   let otherQuoteATA: PublicKey;
   let otherIouATA: PublicKey;
 
+  let providerPosition: PublicKey;
+  let providerPositionBump: number;
+  let otherPosition: PublicKey;
+  let otherPositionBump: number;
+  let navSnapshot: PublicKey;
+  let navSnapshotBump: number;
+  let priceFeed: PublicKey;
+  let priceFeedBump: number;
+
   // how many quote native tokens for 1 base native token
   let initialAPrice = 1;
   // let initialBPrice = 0.5;
@@ -131,6 +142,34 @@ describe("mango-blender", () => {
       program.programId
     );
 
+    [providerPosition, providerPositionBump] = await PublicKey.findProgramAddress(
+      [
+        poolAddress.toBytes(),
+        TEST_PROVIDER.wallet.publicKey.toBytes(),
+        utf8.encode("position"),
+      ],
+      program.programId
+    );
+
+    [otherPosition, otherPositionBump] = await PublicKey.findProgramAddress(
+      [
+        poolAddress.toBytes(),
+        OTHER_PROVIDER.wallet.publicKey.toBytes(),
+        utf8.encode("position"),
+      ],
+      program.programId
+    );
+
+    [navSnapshot, navSnapshotBump] = await PublicKey.findProgramAddress(
+      [poolAddress.toBytes(), utf8.encode("nav")],
+      program.programId
+    );
+
+    [priceFeed, priceFeedBump] = await PublicKey.findProgramAddress(
+      [poolAddress.toBytes(), utf8.encode("price")],
+      program.programId
+    );
+
     client = new MangoClient(TEST_PROVIDER.connection, MANGO_PROG_ID);
 
     // Create tokens (with same decimals for simplicity)
@@ -225,6 +264,7 @@ describe("mango-blender", () => {
     );
     assert.ok(initializedPool.iouMint.toBase58() === poolIouAddress.toBase58());
     assert.ok(initializedPool.poolName === poolName);
+    assert.ok(initializedPool.mangoAccount.equals(mangoAccountAddress));
 
     // check iou mint has been initialized correctly
     const poolIouMintAccountInfo =
@@ -243,6 +283,67 @@ describe("mango-blender", () => {
     assert.ok(mangoAccount.delegate.equals(TEST_PROVIDER.wallet.publicKey));
   });
 
+  it("allows users to create their depositor positions", async () => {
+    await program.rpc.createDepositorPosition(providerPositionBump, {
+      accounts: {
+        pool: poolAddress,
+        depositorPosition: providerPosition,
+        depositor: TEST_PROVIDER.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      },
+      signers: [TEST_PAYER],
+    });
+    await program.rpc.createDepositorPosition(otherPositionBump, {
+      accounts: {
+        pool: poolAddress,
+        depositorPosition: otherPosition,
+        depositor: OTHER_PROVIDER.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      },
+      signers: [OTHER_PAYER],
+    });
+
+    const position = await program.account.depositorPosition.fetch(providerPosition);
+    assert.ok(position.pool.equals(poolAddress));
+    assert.ok(position.owner.equals(TEST_PROVIDER.wallet.publicKey));
+    assert.ok(position.lastDepositSlot.eq(ZERO_BN));
+  });
+
+  it("allows anyone to create the pool's nav snapshot", async () => {
+    await program.rpc.createNavSnapshot(navSnapshotBump, {
+      accounts: {
+        pool: poolAddress,
+        navSnapshot,
+        payer: TEST_PROVIDER.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      },
+      signers: [TEST_PAYER],
+    });
+
+    const snapshot = await program.account.navSnapshot.fetch(navSnapshot);
+    assert.ok(snapshot.pool.equals(poolAddress));
+    assert.ok(snapshot.iouSupply.eq(ZERO_BN));
+  });
+
+  it("allows the admin to create the pool's Pyth-style price account", async () => {
+    await program.rpc.createPriceFeed(priceFeedBump, {
+      accounts: {
+        pool: poolAddress,
+        admin: TEST_PROVIDER.wallet.publicKey,
+        priceFeed,
+        systemProgram: SystemProgram.programId,
+      },
+      signers: [TEST_PAYER],
+    });
+
+    const info = await TEST_PROVIDER.connection.getAccountInfo(priceFeed);
+    assert.ok(info.owner.equals(program.programId));
+    assert.equal(info.data.length, 3312);
+    assert.equal(info.data.readUInt32LE(0), 0xa1b2c3d4); // magic
+    assert.equal(info.data.readUInt32LE(8), 3); // price account
+    assert.equal(info.data.readInt32LE(20), -9); // exponent
+  });
+
   it("allows a user to buy into the pool by depositing QUOTE into the delegated mangoAccount", async () => {
     const depositQuoteQuantity = new anchor.BN(2000000);
     providerIouATA = await initializeProviderATA(TEST_PROVIDER, poolIouAddress, 0, false);
@@ -273,6 +374,11 @@ describe("mango-blender", () => {
       return { pubkey: key, isWritable: false, isSigner: false };
     });
 
+    let depositEvent: any;
+    const listener = program.addEventListener("Deposit", (event: any) => {
+      depositEvent = event;
+    });
+
     const tx = await program.rpc.buyIntoPool(depositQuoteQuantity, {
       accounts: {
         mangoProgram: MANGO_PROG_ID,
@@ -287,6 +393,10 @@ describe("mango-blender", () => {
         vault: nodeBanks[0].vault,
         poolIouMint: poolIouAddress,
         depositorIouTokenAccount: providerIouATA,
+        depositorPosition: providerPosition,
+        navSnapshot,
+        priceFeed,
+        instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
         tokenProgram: TOKEN_PROGRAM_ID,
       },
       remainingAccounts,
@@ -300,6 +410,13 @@ describe("mango-blender", () => {
     await checkIouMintSupply(poolIouAddress, depositQuoteQuantity);
     //check provider IOU amount
     await checkProviderTokenAmount(providerIouATA, depositQuoteQuantity);
+
+    await sleep(1000);
+    await program.removeEventListener(listener);
+    assert.ok(depositEvent.pool.equals(poolAddress));
+    assert.ok(depositEvent.quantity.eq(depositQuoteQuantity));
+    assert.ok(depositEvent.iousMinted.eq(depositQuoteQuantity));
+    assert.ok(depositEvent.iouSupplyBefore.eq(ZERO_BN));
   });
 
   it("will allow a user to withdraw QUOTE", async () => {
@@ -347,6 +464,9 @@ describe("mango-blender", () => {
         vault: nodeBanks[0].vault,
         poolIouMint: poolIouAddress,
         withdrawerIouTokenAccount: providerIouATA,
+        withdrawerPosition: providerPosition,
+        navSnapshot,
+        priceFeed,
         tokenProgram: TOKEN_PROGRAM_ID,
       },
       remainingAccounts,
@@ -408,6 +528,10 @@ describe("mango-blender", () => {
               vault: nodeBanks[0].vault,
               poolIouMint: poolIouAddress,
               depositorIouTokenAccount: providerIouATA,
+              depositorPosition: providerPosition,
+              navSnapshot,
+              priceFeed,
+              instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
               tokenProgram: TOKEN_PROGRAM_ID,
             },
             remainingAccounts,
@@ -465,6 +589,9 @@ describe("mango-blender", () => {
             vault: nodeBanks[0].vault,
             poolIouMint: poolIouAddress,
             withdrawerIouTokenAccount: providerIouATA,
+            withdrawerPosition: providerPosition,
+            navSnapshot,
+            priceFeed,
             tokenProgram: TOKEN_PROGRAM_ID,
           },
           remainingAccounts,
@@ -521,6 +648,9 @@ describe("mango-blender", () => {
             vault: nodeBanks[0].vault,
             poolIouMint: poolIouAddress,
             withdrawerIouTokenAccount: providerIouATA,
+            withdrawerPosition: providerPosition,
+            navSnapshot,
+            priceFeed,
             tokenProgram: TOKEN_PROGRAM_ID,
           },
           remainingAccounts,
@@ -535,6 +665,186 @@ describe("mango-blender", () => {
     );
   });
 
+  it("will block withdrawals inside the deposit lock window", async () => {
+    await program.rpc.setDepositLockSlots(new anchor.BN(1000000), {
+      accounts: {
+        pool: poolAddress,
+        admin: TEST_PROVIDER.wallet.publicKey,
+      },
+      signers: [TEST_PAYER],
+    });
+
+    const group = await client.getMangoGroup(mangoGroupPubkey);
+    const rootBanks = await group.loadRootBanks(TEST_PROVIDER.connection);
+    const nodeBanks = await rootBanks[QUOTE_INDEX]?.loadNodeBanks(
+      TEST_PROVIDER.connection
+    );
+    const mangoCache = await group.loadCache(TEST_PROVIDER.connection);
+    if (!nodeBanks) {
+      throw Error;
+    }
+
+    await keeperRefresh(client, group, mangoCache, rootBanks);
+
+    const mangoAccount = await client.getMangoAccount(
+      mangoAccountAddress,
+      SERUM_PROG_ID
+    );
+    const openOrdersKeys = mangoAccount.getOpenOrdersKeysInBasket();
+    const remainingAccounts = openOrdersKeys.map((key) => {
+      return { pubkey: key, isWritable: false, isSigner: false };
+    });
+
+    await assert.rejects(
+      async () => {
+        const txn = await program.rpc.withdrawFromPool(new anchor.BN(100000), {
+          accounts: {
+            mangoProgram: MANGO_PROG_ID,
+            pool: poolAddress,
+            mangoGroup: mangoGroupPubkey,
+            mangoGroupSigner: group.signerKey,
+            mangoAccount: mangoAccountAddress,
+            withdrawer: TEST_PROVIDER.wallet.publicKey,
+            withdrawerTokenAccount: providerQuoteATA,
+            mangoCache: mangoCache.publicKey,
+            rootBank: rootBanks[QUOTE_INDEX]?.publicKey,
+            nodeBank: nodeBanks[0].publicKey,
+            vault: nodeBanks[0].vault,
+            poolIouMint: poolIouAddress,
+            withdrawerIouTokenAccount: providerIouATA,
+            withdrawerPosition: providerPosition,
+            navSnapshot,
+            priceFeed,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          },
+          remainingAccounts,
+          signers: [TEST_PAYER],
+        });
+      },
+      (err) => {
+        console.log(err.logs);
+        assert.ok(err.logs.includes("Program HzJMW7y12YSPDZMWNeqKDR51QnHwhF3TB96CZsPhpNoB failed: custom program error: 0x12c")); // WithdrawLocked
+        return true;
+      }
+    );
+
+    await program.rpc.setDepositLockSlots(ZERO_BN, {
+      accounts: {
+        pool: poolAddress,
+        admin: TEST_PROVIDER.wallet.publicKey,
+      },
+      signers: [TEST_PAYER],
+    });
+  });
+
+  it("will reject a withdrawal limit above 100%", async () => {
+    await assert.rejects(
+      async () => {
+        await program.rpc.setWithdrawalLimit(10001, {
+          accounts: {
+            pool: poolAddress,
+            admin: TEST_PROVIDER.wallet.publicKey,
+          },
+          signers: [TEST_PAYER],
+        });
+      },
+      (err) => {
+        console.log(err.logs);
+        assert.ok(err.logs.includes("Program HzJMW7y12YSPDZMWNeqKDR51QnHwhF3TB96CZsPhpNoB failed: custom program error: 0x12e")); // InvalidPoolSetting
+        return true;
+      }
+    );
+
+    const pool = await program.account.pool.fetch(poolAddress);
+    assert.ok(pool.withdrawalLimitBps === 0);
+  });
+
+  it("will not let the allowlist timelock be lowered", async () => {
+    await program.rpc.setAllowlistTimelock(new anchor.BN(3600), {
+      accounts: {
+        pool: poolAddress,
+        admin: TEST_PROVIDER.wallet.publicKey,
+      },
+      signers: [TEST_PAYER],
+    });
+
+    await assert.rejects(
+      async () => {
+        await program.rpc.setAllowlistTimelock(new anchor.BN(0), {
+          accounts: {
+            pool: poolAddress,
+            admin: TEST_PROVIDER.wallet.publicKey,
+          },
+          signers: [TEST_PAYER],
+        });
+      },
+      (err) => {
+        console.log(err.logs);
+        assert.ok(err.logs.includes("Program HzJMW7y12YSPDZMWNeqKDR51QnHwhF3TB96CZsPhpNoB failed: custom program error: 0x12e")); // InvalidPoolSetting
+        return true;
+      }
+    );
+
+    const pool = await program.account.pool.fetch(poolAddress);
+    assert.ok(pool.marketAllowlist.timelockSeconds.toNumber() === 3600);
+  });
+
+  it("will not trip the circuit breaker without a drawdown limit", async () => {
+    const group = await client.getMangoGroup(mangoGroupPubkey);
+    const mangoCache = await group.loadCache(TEST_PROVIDER.connection);
+
+    await assert.rejects(
+      async () => {
+        await program.rpc.tripCircuitBreaker({
+          accounts: {
+            mangoProgram: MANGO_PROG_ID,
+            pool: poolAddress,
+            mangoGroup: mangoGroupPubkey,
+            mangoAccount: mangoAccountAddress,
+            mangoCache: mangoCache.publicKey,
+            poolIouMint: poolIouAddress,
+            systemProgram: SystemProgram.programId,
+          },
+          signers: [TEST_PAYER],
+        });
+      },
+      (err) => {
+        console.log(err.logs);
+        assert.ok(err.logs.includes("Program HzJMW7y12YSPDZMWNeqKDR51QnHwhF3TB96CZsPhpNoB failed: custom program error: 0x12e")); // InvalidPoolSetting
+        return true;
+      }
+    );
+
+    const pool = await program.account.pool.fetch(poolAddress);
+    assert.ok(pool.status.active !== undefined);
+  });
+
+  it("will reject a wind down slippage of 100%", async () => {
+    await assert.rejects(
+      async () => {
+        await program.rpc.startWindDown(10000, {
+          accounts: {
+            pool: poolAddress,
+            admin: TEST_PROVIDER.wallet.publicKey,
+            mangoProgram: MANGO_PROG_ID,
+            mangoGroup: mangoGroupPubkey,
+            mangoAccount: mangoAccountAddress,
+            systemProgram: SystemProgram.programId,
+          },
+          signers: [TEST_PAYER],
+        });
+      },
+      (err) => {
+        console.log(err.logs);
+        assert.ok(err.logs.includes("Program HzJMW7y12YSPDZMWNeqKDR51QnHwhF3TB96CZsPhpNoB failed: custom program error: 0x132")); // InvalidSlippage
+        return true;
+      }
+    );
+
+    const pool = await program.account.pool.fetch(poolAddress);
+    assert.ok(pool.status.active !== undefined);
+  });
+
   it("allows delegate to trade on serum normally", async () => {
     const market = await Market.load(TEST_PROVIDER.connection, marketA.market, {}, SERUM_PROG_ID);
     const owner = new Account(TEST_PAYER.secretKey)
@@ -620,6 +930,10 @@ describe("mango-blender", () => {
         vault: nodeBanks[0].vault,
         poolIouMint: poolIouAddress,
         depositorIouTokenAccount: otherIouATA,
+        depositorPosition: otherPosition,
+        navSnapshot,
+        priceFeed,
+        instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
         tokenProgram: TOKEN_PROGRAM_ID,
       },
       remainingAccounts,
@@ -687,6 +1001,9 @@ describe("mango-blender", () => {
         vault: nodeBanks[0].vault,
         poolIouMint: poolIouAddress,
         withdrawerIouTokenAccount: providerIouATA,
+        withdrawerPosition: providerPosition,
+        navSnapshot,
+        priceFeed,
         tokenProgram: TOKEN_PROGRAM_ID,
       },
       remainingAccounts,
@@ -761,6 +1078,9 @@ describe("mango-blender", () => {
             vault: nodeBanks[0].vault,
             poolIouMint: poolIouAddress,
             withdrawerIouTokenAccount: otherIouATA,
+            withdrawerPosition: otherPosition,
+            navSnapshot,
+            priceFeed,
             tokenProgram: TOKEN_PROGRAM_ID,
           },
           remainingAccounts,